@@ -0,0 +1,80 @@
+use std::io;
+
+use anyhow::{anyhow, Result};
+use evdev::{EventSummary, InputEvent};
+use mio::{Registry, Token};
+
+use crate::{
+    capabilities::DeviceCapabilities,
+    input_device::{DeviceError, InputDevice},
+    output_device::OutputSpec,
+};
+
+/// Device input events are read from
+pub trait EventSource: Send {
+    /// Identifies the opened device, no two open sources share a path
+    fn path(&self) -> &str;
+
+    fn capabilities(&self) -> Result<DeviceCapabilities>;
+
+    /// Exclusive access, other readers of the device don't receive its events anymore
+    fn grab(&mut self) -> io::Result<()>;
+
+    fn ungrab(&mut self) -> io::Result<()>;
+
+    /// Registers for readiness events, reads don't block from then on
+    fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()>;
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()>;
+
+    /// Appends all pending events to `events`, returns once no more are available
+    fn read_events(&mut self, events: &mut Vec<EventSummary>) -> Result<(), DeviceError>;
+}
+
+/// Device mapped events are written to
+pub trait EventSink: Send {
+    /// Emits the events as a single report, terminated by `SYN_REPORT`
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()>;
+
+    /// Whether other programs can open the device yet
+    fn is_ready(&mut self) -> io::Result<bool>;
+}
+
+/// Opens input devices and creates output devices
+pub trait Backend: Send {
+    /// Opens the first device named `name` whose path isn't in `used_paths`
+    fn open_input(&self, name: &str, used_paths: &[&str]) -> Option<Box<dyn EventSource>>;
+
+    fn create_output(&self, spec: &OutputSpec) -> Result<Box<dyn EventSink>>;
+}
+
+/// Real devices, evdev for input and uinput for output
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EvdevBackend;
+
+impl Backend for EvdevBackend {
+    fn open_input(&self, name: &str, used_paths: &[&str]) -> Option<Box<dyn EventSource>> {
+        InputDevice::open(name, used_paths).map(|device| Box::new(device) as Box<dyn EventSource>)
+    }
+
+    fn create_output(&self, spec: &OutputSpec) -> Result<Box<dyn EventSink>> {
+        Ok(Box::new(spec.build_virtual_device()?))
+    }
+}
+
+/// Opens a device for every name, devices with the same name are assigned in enumeration order
+pub fn open_inputs(backend: &dyn Backend, names: &[String]) -> Result<Vec<Box<dyn EventSource>>> {
+    let mut sources: Vec<Box<dyn EventSource>> = Vec::new();
+
+    for name in names {
+        let used_paths: Vec<&str> = sources.iter().map(|source| source.path()).collect();
+
+        let source = backend
+            .open_input(name, &used_paths)
+            .ok_or(anyhow!("could not find input device ({name})"))?;
+
+        sources.push(source);
+    }
+
+    Ok(sources)
+}
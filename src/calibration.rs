@@ -0,0 +1,397 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read},
+    os::fd::{AsFd, AsRawFd},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+use evdev::EventSummary;
+use mio::{unix::SourceFd, Events, Interest, Poll, Token};
+use serde::{Deserialize, Serialize};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook_mio::v1_0::Signals;
+
+use crate::{
+    backend::EventSource,
+    capabilities::AxisRange,
+    descriptor::{device_refs, DeviceRef, VJoyDescriptor},
+    format::{self, Format},
+    mappings::Axis,
+};
+
+// input devices use their index as token
+const STDIN: Token = Token(usize::MAX - 1);
+const SIGNALS: Token = Token(usize::MAX);
+
+/// Range an axis actually reaches, e.g. of a worn stick that doesn't reach the limits it
+/// advertises or doesn't rest in the middle of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub min: i32,
+    pub center: i32,
+    pub max: i32,
+}
+
+/// Collects the reached range and the rest position of every axis while they are moved
+#[derive(Debug, Default)]
+pub struct Calibrator {
+    axes: HashMap<(usize, Axis), Observed>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Observed {
+    min: i32,
+    max: i32,
+    last: i32,
+    center: Option<i32>,
+}
+
+impl Calibration {
+    /// Stretches a raw value so that `min`, `center` and `max` become the minimum, the middle and
+    /// the maximum of the advertised `range`
+    pub fn apply(&self, value: i32, range: &AxisRange) -> i32 {
+        let middle = range.minimum + (range.maximum - range.minimum) / 2;
+
+        let scaled = if value < self.center {
+            rescale(value, (self.min, self.center), (range.minimum, middle))
+        } else {
+            rescale(value, (self.center, self.max), (middle, range.maximum))
+        };
+
+        scaled.clamp(range.minimum.into(), range.maximum.into()) as i32
+    }
+
+    /// Whether the center lies within the range
+    pub fn is_valid(&self) -> bool {
+        self.min < self.center && self.center < self.max
+    }
+}
+
+fn rescale(value: i32, from: (i32, i32), to: (i32, i32)) -> i64 {
+    let from = (i64::from(from.0), i64::from(from.1));
+    let to = (i64::from(to.0), i64::from(to.1));
+
+    if from.0 == from.1 {
+        return to.0;
+    }
+
+    to.0 + (i64::from(value) - from.0) * (to.1 - to.0) / (from.1 - from.0)
+}
+
+impl Calibrator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, device: usize, input: &EventSummary) {
+        let EventSummary::AbsoluteAxis(_, axis, value) = *input else {
+            return;
+        };
+
+        let observed = self.axes.entry((device, axis.into())).or_insert(Observed {
+            min: value,
+            max: value,
+            last: value,
+            center: None,
+        });
+
+        observed.min = observed.min.min(value);
+        observed.max = observed.max.max(value);
+        observed.last = value;
+    }
+
+    /// Takes the current position of every axis as its center
+    pub fn mark_centers(&mut self) {
+        for observed in self.axes.values_mut() {
+            observed.center = Some(observed.last);
+        }
+    }
+
+    /// Calibration of every axis that was moved to both sides of its center, the center is the
+    /// middle of the reached range if it wasn't marked
+    pub fn finish(self) -> HashMap<(usize, Axis), Calibration> {
+        self.axes
+            .into_iter()
+            .map(|(axis, observed)| {
+                let calibration = Calibration {
+                    min: observed.min,
+                    center: observed
+                        .center
+                        .unwrap_or(observed.min + (observed.max - observed.min) / 2),
+                    max: observed.max,
+                };
+
+                (axis, calibration)
+            })
+            .filter(|(_, calibration)| calibration.is_valid())
+            .collect()
+    }
+}
+
+/// Asks to move every axis of `sources` through its full range and then to let them rest at their
+/// center, both confirmed with Enter on stdin
+pub fn calibrate(
+    sources: &mut [Box<dyn EventSource>],
+) -> Result<HashMap<(usize, Axis), Calibration>> {
+    let mut calibrator = Calibrator::new();
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(16);
+    let mut inputs = Vec::new();
+
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    poll.registry()
+        .register(&mut signals, SIGNALS, Interest::READABLE)?;
+
+    // read without the buffer of io::Stdin, which could hold lines back from the poll
+    let mut stdin = File::from(io::stdin().as_fd().try_clone_to_owned()?);
+    poll.registry()
+        .register(&mut SourceFd(&stdin.as_raw_fd()), STDIN, Interest::READABLE)?;
+
+    for (index, source) in sources.iter_mut().enumerate() {
+        source.register(poll.registry(), Token(index))?;
+    }
+
+    println!("move every axis to both of its limits a few times, then press Enter");
+    let mut centering = false;
+
+    loop {
+        match poll.poll(&mut events, None) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            result => result?,
+        }
+
+        for event in events.iter() {
+            let index = match event.token() {
+                SIGNALS => bail!("calibration aborted"),
+                STDIN => {
+                    // Enter pressed twice in a row can arrive as a single event
+                    let Some(lines) = read_lines(&mut stdin)? else {
+                        bail!("calibration aborted, stdin was closed");
+                    };
+
+                    for _ in 0..lines {
+                        if centering {
+                            calibrator.mark_centers();
+                            return Ok(calibrator.finish());
+                        }
+
+                        println!("let every axis rest at its center, then press Enter");
+                        centering = true;
+                    }
+
+                    continue;
+                }
+                Token(index) => index,
+            };
+
+            let result = sources[index].read_events(&mut inputs);
+
+            for input in inputs.drain(..) {
+                calibrator.record(index, &input);
+            }
+
+            result.map_err(|err| anyhow!("input device {index} failed: {err}"))?;
+        }
+    }
+}
+
+/// Reads everything `input` has available without blocking, the number of complete lines in it or
+/// `None` once it was closed
+fn read_lines(input: &mut (impl Read + AsRawFd)) -> io::Result<Option<usize>> {
+    let mut available: libc::c_int = 0;
+
+    // SAFETY: FIONREAD only writes the number of readable bytes to `available`
+    if unsafe { libc::ioctl(input.as_raw_fd(), libc::FIONREAD, &mut available) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // readable without anything available means closed, reading a single byte returns at once
+    let mut buffer = vec![0; available.max(1) as usize];
+
+    match input.read(&mut buffer)? {
+        0 => Ok(None),
+        read => Ok(Some(buffer[..read].iter().filter(|&&b| b == b'\n').count())),
+    }
+}
+
+/// Source of the descriptor file at `path` with `calibration` added, earlier calibration of the
+/// same axes is replaced
+///
+/// `descriptor` is the loaded descriptor of the file, with its includes applied. Comments and
+/// formatting of the file are not kept.
+pub fn with_calibration(
+    path: &Path,
+    descriptor: &VJoyDescriptor,
+    calibration: &HashMap<(usize, Axis), Calibration>,
+) -> Result<String> {
+    let format = Format::from_path(path);
+    let source = fs::read_to_string(path)
+        .map_err(|err| anyhow!("failed to open descriptor file: {err:?}"))?;
+
+    let mut file = format::parse_descriptor(&source, format)?;
+
+    // the file might refer to a device by index and by alias
+    file.calibration.retain(|(device, axis), _| {
+        let index = match device {
+            DeviceRef::Index(index) => Some(*index),
+            DeviceRef::Alias(alias) => descriptor
+                .aliases
+                .iter()
+                .position(|a| a.as_deref() == Some(alias)),
+        };
+
+        index.is_none_or(|index| !calibration.contains_key(&(index, *axis)))
+    });
+
+    file.calibration
+        .extend(device_refs(&descriptor.aliases, calibration));
+
+    format::write_descriptor(&file, format)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        fs,
+        io::{self, Write},
+    };
+
+    use anyhow::Result;
+    use evdev::{AbsoluteAxisCode, EventType, InputEvent};
+
+    use crate::{capabilities::AxisRange, mappings::Axis, VJoyDescriptor};
+
+    use super::{read_lines, with_calibration, Calibration, Calibrator};
+
+    const RANGE: AxisRange = AxisRange {
+        minimum: 0,
+        maximum: 16383,
+        fuzz: 0,
+        flat: 0,
+        resolution: 0,
+    };
+
+    fn axis(code: AbsoluteAxisCode, value: i32) -> evdev::EventSummary {
+        InputEvent::new(EventType::ABSOLUTE.0, code.0, value).destructure()
+    }
+
+    #[test]
+    fn stretched() {
+        let calibration = Calibration {
+            min: 400,
+            center: 8500,
+            max: 16000,
+        };
+
+        assert_eq!(calibration.apply(400, &RANGE), 0);
+        assert_eq!(calibration.apply(8500, &RANGE), 8191);
+        assert_eq!(calibration.apply(16000, &RANGE), 16383);
+        assert_eq!(calibration.apply(4450, &RANGE), 4095);
+
+        // beyond the calibrated range
+        assert_eq!(calibration.apply(0, &RANGE), 0);
+        assert_eq!(calibration.apply(16383, &RANGE), 16383);
+
+        let centered = Calibration {
+            min: -100,
+            center: 0,
+            max: 100,
+        };
+        let range = AxisRange {
+            minimum: -511,
+            maximum: 511,
+            ..RANGE
+        };
+        assert_eq!(centered.apply(-100, &range), -511);
+        assert_eq!(centered.apply(0, &range), 0);
+        assert_eq!(centered.apply(50, &range), 255);
+        assert_eq!(centered.apply(100, &range), 511);
+    }
+
+    #[test]
+    fn observed() {
+        let mut calibrator = Calibrator::new();
+
+        for value in [8000, 300, 16100, 9000] {
+            calibrator.record(0, &axis(AbsoluteAxisCode::ABS_X, value));
+        }
+
+        // moved to one side only
+        for value in [100, 200] {
+            calibrator.record(1, &axis(AbsoluteAxisCode::ABS_Y, value));
+        }
+
+        calibrator.record(0, &axis(AbsoluteAxisCode::ABS_X, 8200));
+        calibrator.mark_centers();
+
+        let calibration = calibrator.finish();
+        assert_eq!(calibration.len(), 1);
+        assert_eq!(
+            calibration[&(0, Axis::ABS_X)],
+            Calibration {
+                min: 300,
+                center: 8200,
+                max: 16100,
+            }
+        );
+    }
+
+    #[test]
+    fn written() -> Result<()> {
+        let path = std::env::temp_dir().join("vjoy-linux-calibration.ron");
+        fs::write(
+            &path,
+            r#"(
+                version: 2,
+                input_devices: [(name: "Stick", alias: "stick")],
+                output_device: Combined("Joystick"),
+                axis_mappings: { ("stick", ABS_X): ABS_X },
+                calibration: {
+                    (0, ABS_X): (min: 1, center: 2, max: 3),
+                    (0, ABS_Y): (min: 4, center: 5, max: 6),
+                },
+            )"#,
+        )?;
+
+        let descriptor = VJoyDescriptor::load(&path)?;
+        let measured = Calibration {
+            min: 300,
+            center: 8200,
+            max: 16100,
+        };
+
+        let written = with_calibration(
+            &path,
+            &descriptor,
+            &HashMap::from([((0, Axis::ABS_X), measured)]),
+        )?;
+        assert!(written.contains(r#"("stick", ABS_X): ("#));
+
+        // replaces the entry by index, keeps the others
+        let calibrated = VJoyDescriptor::parse(&written)?;
+        assert_eq!(calibrated.calibration.len(), 2);
+        assert_eq!(calibrated.calibration[&(0, Axis::ABS_X)], measured);
+        assert_eq!(calibrated.calibration[&(0, Axis::ABS_Y)].center, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lines_at_once() -> Result<()> {
+        let (mut reader, mut writer) = io::pipe()?;
+
+        writer.write_all(b"\n\n")?;
+        assert_eq!(read_lines(&mut reader)?, Some(2));
+
+        writer.write_all(b"partial")?;
+        assert_eq!(read_lines(&mut reader)?, Some(0));
+
+        drop(writer);
+        assert_eq!(read_lines(&mut reader)?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use evdev::{AbsInfo, Device};
+use ron::{
+    from_str,
+    ser::{to_string_pretty, PrettyConfig},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::{open_inputs, EvdevBackend},
+    mappings::{Axis, Button},
+};
+
+/// Snapshot of what an input device supports, usable without the device being connected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceCapabilities {
+    pub name: String,
+    #[serde(default)]
+    pub vendor: u16,
+    #[serde(default)]
+    pub product: u16,
+    pub keys: Vec<Button>,
+    pub axes: Vec<(Axis, AxisRange)>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AxisRange {
+    pub minimum: i32,
+    pub maximum: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
+}
+
+impl DeviceCapabilities {
+    pub fn from_device(device: &Device) -> Result<Self> {
+        Ok(Self {
+            name: device.name().unwrap_or_default().to_string(),
+            vendor: device.input_id().vendor(),
+            product: device.input_id().product(),
+            keys: device
+                .supported_keys()
+                .map(|keys| keys.iter().map(|key| key.into()).collect())
+                .unwrap_or_default(),
+            axes: device
+                .get_absinfo()?
+                .map(|(axis, info)| (axis.into(), info.into()))
+                .collect(),
+        })
+    }
+
+    pub fn from_connected(input_device_names: &[String]) -> Result<Vec<Self>> {
+        open_inputs(&EvdevBackend, input_device_names)?
+            .iter()
+            .map(|source| source.capabilities())
+            .collect()
+    }
+
+    pub fn load(path: &Path) -> Result<Vec<Self>> {
+        from_str(
+            &fs::read_to_string(path)
+                .map_err(|err| anyhow!("failed to open capability snapshot: {err:?}"))?,
+        )
+        .map_err(|err| anyhow!("failed to parse capability snapshot: {err:?}"))
+    }
+
+    pub fn save(capabilities: &[Self], path: &Path) -> Result<()> {
+        fs::write(
+            path,
+            to_string_pretty(capabilities, PrettyConfig::default())?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn has_key(&self, key: Button) -> bool {
+        self.keys.contains(&key)
+    }
+
+    pub fn axis(&self, axis: Axis) -> Option<AxisRange> {
+        self.axes
+            .iter()
+            .find_map(|&(a, range)| (a == axis).then_some(range))
+    }
+}
+
+impl From<AbsInfo> for AxisRange {
+    fn from(info: AbsInfo) -> Self {
+        Self {
+            minimum: info.minimum(),
+            maximum: info.maximum(),
+            fuzz: info.fuzz(),
+            flat: info.flat(),
+            resolution: info.resolution(),
+        }
+    }
+}
+
+impl From<AxisRange> for AbsInfo {
+    fn from(range: AxisRange) -> Self {
+        AbsInfo::new(
+            0,
+            range.minimum,
+            range.maximum,
+            range.fuzz,
+            range.flat,
+            range.resolution,
+        )
+    }
+}
@@ -0,0 +1,522 @@
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use evdev::{AbsoluteAxisCode, EventType};
+
+use crate::{
+    capabilities::{AxisRange, DeviceCapabilities},
+    mappings::{Axis, Button},
+    recording::{RecordedEvent, Recording},
+};
+
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const LINKTYPE_USB_LINUX: u16 = 189;
+const LINKTYPE_USB_LINUX_MMAPPED: u16 = 220;
+
+const TRANSFER_INTERRUPT: u8 = 1;
+const TRANSFER_CONTROL: u8 = 2;
+
+const GET_DESCRIPTOR: u8 = 6;
+const DESCRIPTOR_DEVICE: u8 = 1;
+const DESCRIPTOR_STRING: u8 = 3;
+
+const T16000M_VENDOR: u16 = 0x044f;
+const T16000M_PRODUCT: u16 = 0xb10a;
+const T16000M_NAME: &str = "Thrustmaster T.16000M";
+const T16000M_REPORT_LEN: usize = 9;
+
+/// The kernel maps the 16 HID buttons of a joystick onto BTN_JOYSTICK and the following codes
+const T16000M_BUTTONS: [Button; 16] = [
+    Button::BTN_TRIGGER,
+    Button::BTN_THUMB,
+    Button::BTN_THUMB2,
+    Button::BTN_TOP,
+    Button::BTN_TOP2,
+    Button::BTN_PINKIE,
+    Button::BTN_BASE,
+    Button::BTN_BASE2,
+    Button::BTN_BASE3,
+    Button::BTN_BASE4,
+    Button::BTN_BASE5,
+    Button::BTN_BASE6,
+    Button::Unknown(0x12c),
+    Button::Unknown(0x12d),
+    Button::Unknown(0x12e),
+    Button::BTN_DEAD,
+];
+const BTN_JOYSTICK: u16 = 0x120;
+
+/// Directions of the hat switch values, 8 and above (the null state) center it
+const HAT_DIRECTIONS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// USB request block as captured by usbmon
+struct Urb<'a> {
+    id: u64,
+    /// `S`ubmission or `C`ompletion
+    kind: u8,
+    transfer: u8,
+    endpoint: u8,
+    /// Bus and device number
+    address: (u16, u8),
+    time: Duration,
+    setup: Option<[u8; 8]>,
+    data: &'a [u8],
+}
+
+/// State of a T.16000M as sent in its 9 byte interrupt report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Report {
+    buttons: u16,
+    hat: u8,
+    x: u16,
+    y: u16,
+    rz: u8,
+    throttle: u8,
+}
+
+/// Reads a usbmon capture of a Thrustmaster T.16000M, its interrupt reports become key and axis
+/// events
+pub fn load(path: &Path) -> Result<Recording> {
+    let bytes = fs::read(path).map_err(|err| anyhow!("failed to open capture: {err:?}"))?;
+
+    parse(&bytes).map_err(|err| anyhow!("failed to parse capture {}: {err}", path.display()))
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Recording> {
+    let urbs = urbs(bytes)?;
+
+    let mut device = None;
+    let mut strings = Vec::new();
+
+    // answers to the descriptor requests of the enumeration, if it was captured
+    for (submission, completion) in control_transfers(&urbs) {
+        let [0x80, GET_DESCRIPTOR, index, kind, ..] = submission else {
+            continue;
+        };
+
+        match kind {
+            DESCRIPTOR_DEVICE if completion.data.len() >= 16 => {
+                device = Some((completion.address, completion.data));
+            }
+            DESCRIPTOR_STRING if completion.data.len() >= 2 => {
+                let text: Vec<u16> = completion.data[2..]
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                    .collect();
+
+                strings.push((index, String::from_utf16_lossy(&text)));
+            }
+            _ => (),
+        }
+    }
+
+    let (address, name) = match device {
+        Some((address, descriptor)) => {
+            let vendor = u16::from_le_bytes([descriptor[8], descriptor[9]]);
+            let product = u16::from_le_bytes([descriptor[10], descriptor[11]]);
+
+            if (vendor, product) != (T16000M_VENDOR, T16000M_PRODUCT) {
+                bail!("not a T.16000M ({vendor:04x}:{product:04x})");
+            }
+
+            let string = |index: u8| {
+                strings
+                    .iter()
+                    .find(|(i, _)| *i == index)
+                    .map(|(_, text)| text.as_str())
+            };
+
+            // the kernel names the device after its manufacturer and product strings
+            let name = match (string(descriptor[14]), string(descriptor[15])) {
+                (Some(manufacturer), Some(product)) => format!("{manufacturer} {product}"),
+                _ => T16000M_NAME.to_string(),
+            };
+
+            (Some(address), name)
+        }
+        None => (None, T16000M_NAME.to_string()),
+    };
+
+    let reports: Vec<_> = urbs
+        .iter()
+        .filter(|urb| {
+            urb.kind == b'C'
+                && urb.transfer == TRANSFER_INTERRUPT
+                && urb.endpoint == 0x81
+                && urb.data.len() == T16000M_REPORT_LEN
+                && address.is_none_or(|address| urb.address == address)
+        })
+        .collect();
+
+    // without the enumeration the first device that sent a report is taken
+    let address = address.or(reports.first().map(|urb| urb.address));
+
+    let mut events = Vec::new();
+    let mut previous = Report::default().values();
+    let start = reports.first().map(|urb| urb.time).unwrap_or_default();
+
+    for urb in reports.iter().filter(|urb| Some(urb.address) == address) {
+        let values = Report::parse(urb.data).values();
+        let time = urb.time.saturating_sub(start).as_micros() as u64;
+
+        // like evdev, only changed values produce events
+        for (&(event_type, code, value), &(_, _, old)) in values.iter().zip(previous.iter()) {
+            if value != old {
+                events.push(RecordedEvent {
+                    device: 0,
+                    time,
+                    event_type: event_type.0,
+                    code,
+                    value,
+                });
+            }
+        }
+
+        previous = values;
+    }
+
+    Ok(Recording {
+        devices: vec![capabilities(name)],
+        events,
+    })
+}
+
+/// What the kernel reports for a T.16000M
+fn capabilities(name: String) -> DeviceCapabilities {
+    // hid-input derives fuzz and flat from the logical range
+    let range = |maximum: i32| AxisRange {
+        minimum: 0,
+        maximum,
+        fuzz: maximum >> 8,
+        flat: maximum >> 4,
+        resolution: 0,
+    };
+
+    let hat = AxisRange {
+        minimum: -1,
+        maximum: 1,
+        fuzz: 0,
+        flat: 0,
+        resolution: 0,
+    };
+
+    DeviceCapabilities {
+        name,
+        vendor: T16000M_VENDOR,
+        product: T16000M_PRODUCT,
+        keys: T16000M_BUTTONS.to_vec(),
+        axes: vec![
+            (Axis::ABS_X, range(16383)),
+            (Axis::ABS_Y, range(16383)),
+            (Axis::ABS_RZ, range(255)),
+            (Axis::ABS_THROTTLE, range(255)),
+            (Axis::ABS_HAT0X, hat),
+            (Axis::ABS_HAT0Y, hat),
+        ],
+    }
+}
+
+impl Default for Report {
+    /// What evdev assumes before the first report
+    fn default() -> Self {
+        Self {
+            buttons: 0,
+            hat: 0x0f,
+            x: 0,
+            y: 0,
+            rz: 0,
+            throttle: 0,
+        }
+    }
+}
+
+impl Report {
+    /// 16 buttons, a 4 bit hat switch and 4 bits padding, 14 bit x and y axes with 2 bits padding
+    /// each, 8 bit rz and throttle axes
+    fn parse(data: &[u8]) -> Self {
+        let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+
+        Self {
+            buttons: word(0),
+            hat: data[2] & 0x0f,
+            x: word(3) & 0x3fff,
+            y: word(5) & 0x3fff,
+            rz: data[7],
+            throttle: data[8],
+        }
+    }
+
+    /// Event type, code and value of every button and axis
+    fn values(&self) -> Vec<(EventType, u16, i32)> {
+        let (hat_x, hat_y) = HAT_DIRECTIONS
+            .get(self.hat as usize)
+            .copied()
+            .unwrap_or((0, 0));
+
+        let buttons = (0..16).map(|bit| {
+            (
+                EventType::KEY,
+                BTN_JOYSTICK + bit,
+                ((self.buttons >> bit) & 1) as i32,
+            )
+        });
+
+        let axes = [
+            (AbsoluteAxisCode::ABS_X, self.x as i32),
+            (AbsoluteAxisCode::ABS_Y, self.y as i32),
+            (AbsoluteAxisCode::ABS_RZ, self.rz as i32),
+            (AbsoluteAxisCode::ABS_THROTTLE, self.throttle as i32),
+            (AbsoluteAxisCode::ABS_HAT0X, hat_x),
+            (AbsoluteAxisCode::ABS_HAT0Y, hat_y),
+        ]
+        .map(|(axis, value)| (EventType::ABSOLUTE, axis.0, value));
+
+        buttons.chain(axes).collect()
+    }
+}
+
+/// Setup packets of control transfers with the completion that answered them
+fn control_transfers<'a>(urbs: &'a [Urb<'a>]) -> impl Iterator<Item = ([u8; 8], &'a Urb<'a>)> {
+    urbs.iter()
+        .enumerate()
+        .filter(|(_, urb)| urb.transfer == TRANSFER_CONTROL && urb.kind == b'S')
+        .filter_map(|(index, submission)| {
+            // ids are kernel addresses that get reused, the next completion is the answer
+            let completion = urbs[index + 1..]
+                .iter()
+                .find(|urb| urb.kind == b'C' && urb.id == submission.id)?;
+
+            Some((submission.setup?, completion))
+        })
+}
+
+/// All URBs of a pcapng file with usbmon packets
+fn urbs(bytes: &[u8]) -> Result<Vec<Urb<'_>>> {
+    let mut urbs = Vec::new();
+    let mut link_types = Vec::new();
+    let mut offset = 0;
+
+    while offset + 12 <= bytes.len() {
+        let block_type = read_u32(bytes, offset)?;
+        let length = read_u32(bytes, offset + 4)? as usize;
+
+        if length < 12 || offset + length > bytes.len() {
+            bail!("truncated block at offset {offset}");
+        }
+
+        let body = &bytes[offset + 8..offset + length - 4];
+
+        match block_type {
+            SECTION_HEADER_BLOCK => {
+                if read_u32(body, 0)? != BYTE_ORDER_MAGIC {
+                    bail!("only little endian captures are supported");
+                }
+
+                // interface ids are counted per section
+                link_types.clear();
+            }
+            INTERFACE_DESCRIPTION_BLOCK => link_types.push(read_u16(body, 0)?),
+            ENHANCED_PACKET_BLOCK => {
+                let interface = read_u32(body, 0)? as usize;
+                let captured = read_u32(body, 12)? as usize;
+                let packet = body
+                    .get(20..20 + captured)
+                    .ok_or(anyhow!("truncated packet at offset {offset}"))?;
+
+                let header_len = match link_types.get(interface) {
+                    Some(&LINKTYPE_USB_LINUX) => 48,
+                    Some(&LINKTYPE_USB_LINUX_MMAPPED) => 64,
+                    Some(link_type) => bail!("unsupported link type {link_type}, not usbmon"),
+                    None => bail!("packet of unknown interface {interface}"),
+                };
+
+                urbs.push(urb(packet, header_len)?);
+            }
+            // statistics and other blocks
+            _ => (),
+        }
+
+        offset += length;
+    }
+
+    Ok(urbs)
+}
+
+fn urb(packet: &[u8], header_len: usize) -> Result<Urb<'_>> {
+    if packet.len() < header_len {
+        bail!("truncated usbmon header");
+    }
+
+    let seconds = u64::from_le_bytes(packet[16..24].try_into()?);
+    let micros = read_u32(packet, 24)?;
+    let captured = read_u32(packet, 36)? as usize;
+
+    Ok(Urb {
+        id: u64::from_le_bytes(packet[0..8].try_into()?),
+        kind: packet[8],
+        transfer: packet[9],
+        endpoint: packet[10],
+        address: (read_u16(packet, 12)?, packet[11]),
+        time: Duration::from_secs(seconds) + Duration::from_micros(micros as u64),
+        // a zero flag means the setup packet is present
+        setup: (packet[14] == 0)
+            .then(|| packet[40..48].try_into())
+            .transpose()?,
+        data: &packet[header_len..(header_len + captured).min(packet.len())],
+    })
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16> {
+    let slice = bytes
+        .get(offset..offset + 2)
+        .ok_or(anyhow!("unexpected end of data"))?;
+
+    Ok(u16::from_le_bytes(slice.try_into()?))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    let slice = bytes
+        .get(offset..offset + 4)
+        .ok_or(anyhow!("unexpected end of data"))?;
+
+    Ok(u32::from_le_bytes(slice.try_into()?))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, path::Path};
+
+    use evdev::{AbsoluteAxisCode, EventType, KeyCode};
+
+    use crate::{
+        descriptor::{OutputType, VJoyDescriptor},
+        mapper::Mapper,
+        mappings::{Axis, Button},
+        recording::Recording,
+    };
+
+    use super::{load, Report};
+
+    fn capture(name: &str) -> Recording {
+        load(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("captures")
+                .join(name),
+        )
+        .unwrap()
+    }
+
+    fn values(recording: &Recording) -> Vec<(u16, i32)> {
+        recording
+            .events
+            .iter()
+            .map(|event| (event.code, event.value))
+            .collect()
+    }
+
+    #[test]
+    fn captured_devices() {
+        let left = capture("thrustmaster_left.pcapng");
+        let right = capture("thrustmaster_right.pcapng");
+
+        assert_eq!(left.devices[0].name, "Thrustmaster T.16000M");
+        assert_eq!(left.devices[0].vendor, 0x044f);
+        assert_eq!(left.devices[0].product, 0xb10a);
+        assert!(left.devices[0].has_key(Button::BTN_TRIGGER));
+
+        // both captures contain a single report with the sticks at rest
+        assert_eq!(
+            values(&left),
+            [
+                (AbsoluteAxisCode::ABS_X.0, 8192),
+                (AbsoluteAxisCode::ABS_Y.0, 8192),
+                (AbsoluteAxisCode::ABS_RZ.0, 128),
+                (AbsoluteAxisCode::ABS_THROTTLE.0, 160),
+            ]
+        );
+        assert_eq!(
+            values(&right),
+            [
+                (AbsoluteAxisCode::ABS_X.0, 8192),
+                (AbsoluteAxisCode::ABS_Y.0, 8192),
+                (AbsoluteAxisCode::ABS_RZ.0, 128),
+                (AbsoluteAxisCode::ABS_THROTTLE.0, 97),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_layout() {
+        // trigger and the last button, hat pointing right, x at its maximum
+        let report = Report::parse(&[0x01, 0x80, 0xf2, 0xff, 0x3f, 0x00, 0x20, 0x80, 0x00]);
+
+        assert_eq!(report.buttons, 0x8001);
+        assert_eq!(report.hat, 2);
+        assert_eq!(report.x, 16383);
+        assert_eq!(report.y, 8192);
+
+        let pressed: Vec<_> = report
+            .values()
+            .into_iter()
+            .filter(|&(event_type, _, value)| event_type == EventType::KEY && value == 1)
+            .map(|(_, code, _)| code)
+            .collect();
+        assert_eq!(pressed, [KeyCode::BTN_TRIGGER.0, KeyCode::BTN_DEAD.0]);
+
+        let hat: Vec<_> = report
+            .values()
+            .into_iter()
+            .filter(|&(_, code, _)| {
+                code == AbsoluteAxisCode::ABS_HAT0X.0 || code == AbsoluteAxisCode::ABS_HAT0Y.0
+            })
+            .map(|(_, _, value)| value)
+            .collect();
+        assert_eq!(hat, [1, 0]);
+    }
+
+    #[test]
+    fn mapped_captures() {
+        let recording = Recording::merge([
+            capture("thrustmaster_left.pcapng"),
+            capture("thrustmaster_right.pcapng"),
+        ]);
+
+        let mapper = Mapper::new(VJoyDescriptor {
+            input_devices: recording.devices.iter().map(|d| d.name.clone()).collect(),
+            output_device: OutputType::Combined("Combined".to_string()),
+            axis_mappings: HashMap::from([
+                ((0, Axis::ABS_THROTTLE), Axis::ABS_THROTTLE),
+                ((1, Axis::ABS_THROTTLE), Axis::ABS_RUDDER),
+            ]),
+            ..VJoyDescriptor::default()
+        });
+
+        let mut mapped: Vec<_> = recording
+            .map(&mapper)
+            .iter()
+            .map(|mapped| (mapped.event.code(), mapped.event.value()))
+            .collect();
+        mapped.sort();
+
+        assert_eq!(
+            mapped,
+            [
+                (AbsoluteAxisCode::ABS_THROTTLE.0, 160),
+                (AbsoluteAxisCode::ABS_RUDDER.0, 97),
+            ]
+        );
+    }
+}
@@ -0,0 +1,521 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::{Args as ClapArgs, Parser, Subcommand};
+use serde::de::DeserializeOwned;
+
+use vjoy_linux::control::{InjectEvent, Request};
+
+/// Linux vjoy cli
+#[derive(Debug, Parser)]
+#[command(version = "0.1")]
+#[command(about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    legacy: LegacyArgs,
+}
+
+impl Args {
+    /// Resolves the subcommand, falling back to the legacy flag set
+    pub fn into_command(self) -> Result<Command> {
+        match self.command {
+            Some(command) => Ok(command),
+            None => self.legacy.try_into(),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create the virtual devices of a descriptor and map input into them
+    Run(RunArgs),
+
+    /// Generate a stub descriptor from connected input devices
+    Generate(GenerateArgs),
+
+    /// List connected input devices
+    List,
+
+    /// Check a descriptor file for errors
+    Validate(ValidateArgs),
+
+    /// Show raw and mapped values of the input devices of a descriptor
+    Monitor(MonitorArgs),
+
+    /// Send a command to a running instance
+    Ctl(CtlArgs),
+
+    /// Record the events of input devices until Ctrl+C is pressed
+    Record(RecordArgs),
+
+    /// Map a recording into the virtual devices of a descriptor
+    Replay(ReplayArgs),
+
+    /// Rewrite a descriptor of an older schema version in the current one
+    Migrate(MigrateArgs),
+
+    /// Print a descriptor with all of its includes applied
+    Resolve(ResolveArgs),
+
+    /// Convert a descriptor between RON, JSON, TOML and YAML (picked by file extension)
+    Convert(ConvertArgs),
+
+    /// Measure the range and center of every axis and store them in a descriptor
+    Calibrate(CalibrateArgs),
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct RunArgs {
+    /// VJoyDescriptor file
+    #[arg(short = 'f', long = "file")]
+    pub descriptor_file: PathBuf,
+
+    /// Enable debug output
+    #[arg(long = "debug", default_value_t = false)]
+    pub debug: bool,
+
+    /// Program that should be started (parsed like a shell command line)
+    #[arg(short = 'p', long = "program", conflicts_with = "program_args")]
+    pub program: Option<String>,
+
+    /// Program and its arguments that should be started
+    #[arg(last = true, value_name = "PROGRAM")]
+    pub program_args: Vec<String>,
+
+    /// Additional environment variable for the program (KEY=VALUE)
+    #[arg(short = 'e', long = "env", value_parser = parse_env)]
+    pub env: Vec<(String, String)>,
+
+    /// Don't hide the physical devices from SDL games started by vjoy-linux
+    #[arg(long = "no-sdl-hints", default_value_t = false)]
+    pub no_sdl_hints: bool,
+
+    /// Seconds to wait for the virtual devices to show up before starting the program
+    #[arg(long = "device-timeout", default_value_t = 5)]
+    pub device_timeout: u64,
+
+    /// Measure the latency of every mapped event and print it on exit
+    #[arg(long = "latency", default_value_t = false)]
+    pub latency: bool,
+
+    /// Don't reload the descriptor file when it changes
+    #[arg(long = "no-watch", default_value_t = false)]
+    pub no_watch: bool,
+
+    /// Control socket path (defaults to $XDG_RUNTIME_DIR/vjoy-linux.sock)
+    #[arg(short = 's', long = "socket")]
+    pub socket: Option<PathBuf>,
+
+    /// Don't open the control socket
+    #[arg(
+        long = "no-control",
+        default_value_t = false,
+        conflicts_with = "socket"
+    )]
+    pub no_control: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct GenerateArgs {
+    /// Input Devices (Comma separated)
+    #[arg(short = 'i', long = "input")]
+    pub input_devices: String,
+
+    /// Output Device (Comma separated for passthrough)
+    #[arg(short = 'd', long = "device")]
+    pub output_device: Option<String>,
+
+    /// Output File, its extension picks the format (.ron, .json, .toml, .yaml)
+    #[arg(short = 'o', long = "output")]
+    pub output_file: Option<PathBuf>,
+
+    /// Annotate every mapping with the name of its device and the range of axes
+    #[arg(long = "comments", default_value_t = false)]
+    pub comments: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ValidateArgs {
+    /// VJoyDescriptor file
+    #[arg(short = 'f', long = "file")]
+    pub descriptor_file: PathBuf,
+
+    /// Check against a capability snapshot instead of the connected devices
+    #[arg(short = 'c', long = "capabilities", conflicts_with = "offline")]
+    pub capabilities: Option<PathBuf>,
+
+    /// Save the capabilities of the connected devices as snapshot
+    #[arg(long = "save-capabilities", conflicts_with_all = ["capabilities", "offline"])]
+    pub save_capabilities: Option<PathBuf>,
+
+    /// Skip all checks that need device capabilities
+    #[arg(long = "offline", default_value_t = false)]
+    pub offline: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct MonitorArgs {
+    /// VJoyDescriptor file
+    #[arg(short = 'f', long = "file")]
+    pub descriptor_file: PathBuf,
+
+    /// Also create the virtual devices and pass the mapped input into them
+    #[arg(long = "emit", default_value_t = false)]
+    pub emit: bool,
+
+    /// Show the latency of mapped events per device
+    #[arg(long = "latency", default_value_t = false)]
+    pub latency: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct RecordArgs {
+    /// Record the input devices of a descriptor file
+    #[arg(short = 'f', long = "file", required_unless_present = "input_devices")]
+    pub descriptor_file: Option<PathBuf>,
+
+    /// Input Devices (Comma separated)
+    #[arg(short = 'i', long = "input", conflicts_with = "descriptor_file")]
+    pub input_devices: Option<String>,
+
+    /// Recording file
+    #[arg(short = 'o', long = "output")]
+    pub output_file: PathBuf,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ReplayArgs {
+    /// VJoyDescriptor file
+    #[arg(short = 'f', long = "file")]
+    pub descriptor_file: PathBuf,
+
+    /// Recording file or usbmon capture (.pcapng) of a T.16000M, repeated for one file per input
+    /// device
+    #[arg(short = 'r', long = "recording", required = true)]
+    pub recordings: Vec<PathBuf>,
+
+    /// Playback speed, 2.0 plays twice as fast
+    #[arg(long = "speed", default_value_t = 1.0)]
+    pub speed: f64,
+
+    /// Print the mapped events instead of creating virtual devices
+    #[arg(long = "mock", default_value_t = false)]
+    pub mock: bool,
+
+    /// Enable debug output
+    #[arg(long = "debug", default_value_t = false)]
+    pub debug: bool,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct MigrateArgs {
+    /// VJoyDescriptor file
+    #[arg(short = 'f', long = "file")]
+    pub descriptor_file: PathBuf,
+
+    /// Output File (defaults to rewriting the descriptor file, keeping a .bak copy)
+    #[arg(short = 'o', long = "output")]
+    pub output_file: Option<PathBuf>,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ResolveArgs {
+    /// VJoyDescriptor file
+    #[arg(short = 'f', long = "file")]
+    pub descriptor_file: PathBuf,
+
+    /// Output File, its extension picks the format (defaults to printing the descriptor as RON)
+    #[arg(short = 'o', long = "output")]
+    pub output_file: Option<PathBuf>,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct ConvertArgs {
+    /// VJoyDescriptor file
+    #[arg(short = 'f', long = "file")]
+    pub descriptor_file: PathBuf,
+
+    /// Output File, its extension picks the format
+    #[arg(short = 'o', long = "output")]
+    pub output_file: PathBuf,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct CalibrateArgs {
+    /// VJoyDescriptor file
+    #[arg(short = 'f', long = "file")]
+    pub descriptor_file: PathBuf,
+
+    /// Output File (defaults to rewriting the descriptor file, keeping a .bak copy)
+    #[arg(short = 'o', long = "output")]
+    pub output_file: Option<PathBuf>,
+}
+
+#[derive(Debug, ClapArgs)]
+pub struct CtlArgs {
+    /// Control socket path (defaults to $XDG_RUNTIME_DIR/vjoy-linux.sock)
+    #[arg(short = 's', long = "socket")]
+    pub socket: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: CtlCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CtlCommand {
+    /// Reload the descriptor file
+    Reload,
+
+    /// Switch to a profile, or back to the base mappings without a name
+    Profile { name: Option<String> },
+
+    /// List the profiles of the descriptor
+    Profiles,
+
+    /// List input and output devices
+    Devices,
+
+    /// Print the current button and axis values
+    State,
+
+    /// Emit events on an output device
+    Inject {
+        /// Output device index
+        #[arg(short = 'o', long = "output", default_value_t = 0)]
+        output: usize,
+
+        /// Button event (e.g. BTN_0=1)
+        #[arg(short = 'k', long = "key")]
+        keys: Vec<String>,
+
+        /// Axis event (e.g. ABS_X=512)
+        #[arg(short = 'a', long = "axis")]
+        axes: Vec<String>,
+    },
+
+    /// Send a raw JSON request
+    Raw { request: String },
+}
+
+impl TryFrom<CtlCommand> for Request {
+    type Error = anyhow::Error;
+
+    fn try_from(command: CtlCommand) -> Result<Self> {
+        Ok(match command {
+            CtlCommand::Reload => Request::Reload,
+            CtlCommand::Profile { name } => Request::Profile { name },
+            CtlCommand::Profiles => Request::Profiles,
+            CtlCommand::Devices => Request::Devices,
+            CtlCommand::State => Request::State,
+            CtlCommand::Inject { output, keys, axes } => {
+                let mut events = Vec::new();
+
+                for key in keys.iter() {
+                    let (code, value) = parse_assignment(key)?;
+                    events.push(InjectEvent::Key { code, value });
+                }
+
+                for axis in axes.iter() {
+                    let (code, value) = parse_assignment(axis)?;
+                    events.push(InjectEvent::Axis { code, value });
+                }
+
+                Request::Inject { output, events }
+            }
+            CtlCommand::Raw { request } => serde_json::from_str(&request)?,
+        })
+    }
+}
+
+fn parse_env(variable: &str) -> Result<(String, String), String> {
+    variable
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or(format!("expected KEY=VALUE, got {variable}"))
+}
+
+/// Parses `CODE=value`, where `CODE` is written like in a descriptor
+fn parse_assignment<T: DeserializeOwned>(assignment: &str) -> Result<(T, i32)> {
+    let (code, value) = assignment
+        .split_once('=')
+        .ok_or(anyhow!("expected CODE=value, got {assignment}"))?;
+
+    Ok((
+        ron::from_str(code.trim()).map_err(|err| anyhow!("unknown code ({code}): {err}"))?,
+        value.trim().parse()?,
+    ))
+}
+
+/// Flag set from before the subcommands existed
+#[derive(Debug, ClapArgs)]
+#[command(next_help_heading = "Legacy options")]
+struct LegacyArgs {
+    /// VJoyDescriptor file (same as `run -f`)
+    #[arg(short = 'f', long = "file")]
+    descriptor_file: Option<PathBuf>,
+
+    /// Enable debug output (same as `run --debug`)
+    #[arg(long = "debug", default_value_t = false)]
+    debug: bool,
+
+    /// Generator Input Devices (same as `generate -i`)
+    #[arg(short = 'i', long = "input")]
+    input_devices: Option<String>,
+
+    /// Generator Output Device (same as `generate -d`)
+    #[arg(short = 'd', long = "device")]
+    output_device: Option<String>,
+
+    /// Generator File (same as `generate -o`)
+    #[arg(short = 'o', long = "output")]
+    generator_file: Option<PathBuf>,
+
+    /// Program that should be started (same as `run -p`)
+    #[arg(short = 'p', long = "program")]
+    program: Option<String>,
+}
+
+impl TryFrom<LegacyArgs> for Command {
+    type Error = anyhow::Error;
+
+    fn try_from(args: LegacyArgs) -> Result<Self> {
+        if let Some(input_devices) = args.input_devices {
+            return Ok(Command::Generate(GenerateArgs {
+                input_devices,
+                output_device: args.output_device,
+                output_file: args.generator_file,
+                comments: false,
+            }));
+        }
+
+        Ok(Command::Run(RunArgs {
+            descriptor_file: args
+                .descriptor_file
+                .ok_or(anyhow!("missing descriptor file (-f <path to file>)"))?,
+            debug: args.debug,
+            program: args.program,
+            program_args: Vec::new(),
+            env: Vec::new(),
+            no_sdl_hints: false,
+            device_timeout: 5,
+            latency: false,
+            // scripts written for the legacy flags don't expect a watcher or a socket, which
+            // would also clash between several instances
+            no_watch: true,
+            socket: None,
+            no_control: true,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use vjoy_linux::{
+        control::{InjectEvent, Request},
+        mappings::{Axis, Button},
+    };
+
+    use super::{Args, Command};
+
+    #[test]
+    fn legacy_flags() {
+        let command = Args::parse_from(["vjoy-linux", "-f", "file.ron", "--debug"])
+            .into_command()
+            .unwrap();
+        assert!(matches!(
+            command,
+            Command::Run(args) if args.debug && args.no_watch && args.no_control
+        ));
+
+        let command = Args::parse_from(["vjoy-linux", "-i", "a,b", "-o", "out.ron"])
+            .into_command()
+            .unwrap();
+        assert!(matches!(command, Command::Generate(args) if args.input_devices == "a,b"));
+
+        assert!(Args::parse_from(["vjoy-linux"]).into_command().is_err());
+    }
+
+    #[test]
+    fn subcommands() {
+        let command = Args::parse_from(["vjoy-linux", "run", "-f", "file.ron", "-p", "game"])
+            .into_command()
+            .unwrap();
+        assert!(matches!(command, Command::Run(args) if args.program.as_deref() == Some("game")));
+
+        assert!(Args::try_parse_from(["vjoy-linux", "-f", "file.ron", "list"]).is_err());
+        assert!(Args::try_parse_from(["vjoy-linux", "generate", "-f", "file.ron"]).is_err());
+    }
+
+    #[test]
+    fn program_args() {
+        let command = Args::parse_from([
+            "vjoy-linux",
+            "run",
+            "-f",
+            "file.ron",
+            "-e",
+            "A=b=c",
+            "--",
+            "game",
+            "--flag",
+        ])
+        .into_command()
+        .unwrap();
+
+        let Command::Run(args) = command else {
+            panic!("expected run command");
+        };
+
+        assert_eq!(args.program_args, ["game", "--flag"]);
+        assert_eq!(args.env, [("A".to_string(), "b=c".to_string())]);
+
+        assert!(Args::try_parse_from([
+            "vjoy-linux",
+            "run",
+            "-f",
+            "file.ron",
+            "-p",
+            "game",
+            "--",
+            "game"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn ctl_inject() {
+        let command = Args::parse_from([
+            "vjoy-linux",
+            "ctl",
+            "inject",
+            "-o",
+            "1",
+            "-k",
+            "BTN_0=1",
+            "-a",
+            "ABS_X=-20",
+        ])
+        .into_command()
+        .unwrap();
+
+        let Command::Ctl(args) = command else {
+            panic!("expected ctl command");
+        };
+
+        let request: Request = args.command.try_into().unwrap();
+
+        assert!(matches!(
+            request,
+            Request::Inject { output: 1, events } if matches!(events[..], [
+                InjectEvent::Key { code: Button::BTN_0, value: 1 },
+                InjectEvent::Axis { code: Axis::ABS_X, value: -20 },
+            ])
+        ));
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use crate::{
+    descriptor::{InputDeviceEntry, Layer, VJoyDescriptor, CURRENT_VERSION},
+    format::{self, Format},
+};
+
+/// Descriptors shipped with vjoy-linux, included by name
+const TEMPLATES: &[(&str, &str)] = &[
+    (
+        "t16000m_combined",
+        include_str!("../example_descriptor/thrustmaster_t16000_combined.ron"),
+    ),
+    (
+        "t16000m_passthrough",
+        include_str!("../example_descriptor/thrustmaster_t16000_passthrough.ron"),
+    ),
+];
+
+/// Where a descriptor comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Origin {
+    File(PathBuf),
+    Template(&'static str),
+}
+
+/// Reads only the includes of a descriptor file, all other fields are skipped
+#[derive(Deserialize)]
+struct IncludeProbe {
+    #[serde(default)]
+    include: Vec<String>,
+}
+
+/// Directory of the user's templates, `$XDG_CONFIG_HOME/vjoy-linux/templates`
+pub fn templates_dir() -> PathBuf {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config_dir) => PathBuf::from(config_dir),
+        None => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config"),
+    };
+
+    config_dir.join("vjoy-linux").join("templates")
+}
+
+/// Whether a descriptor source includes other descriptors
+pub fn has_includes(source: &str, format: Format) -> bool {
+    format
+        .parse::<IncludeProbe>(source)
+        .is_ok_and(|probe| !probe.include.is_empty())
+}
+
+/// Loads a descriptor file and applies it on top of its includes
+///
+/// Includes are applied in the order they are listed, so later ones override earlier ones, and
+/// the including file overrides all of them. Includes can include further descriptors.
+pub fn resolve(path: &Path) -> Result<VJoyDescriptor> {
+    load_layer(&Origin::File(path.to_path_buf()), &[], &mut Vec::new())?.into_descriptor()
+}
+
+/// RON source of a descriptor file with all includes applied, the file itself if it is a RON file
+/// without includes
+pub fn resolved_source(path: &Path) -> Result<String> {
+    let format = Format::from_path(path);
+    let source = Origin::File(path.to_path_buf()).source()?;
+
+    if has_includes(&source, format) {
+        return format::write_descriptor(&resolve(path)?.into(), Format::Ron);
+    }
+
+    match format {
+        Format::Ron => Ok(source),
+        _ => format::convert(&source, format, Format::Ron),
+    }
+}
+
+/// Source of a descriptor file as it is validated, and whether that is the file itself
+///
+/// Other formats and files with includes are validated as their [`resolved_source`], locations in
+/// it don't refer to the file.
+pub fn checked_source(path: &Path) -> Result<(String, bool)> {
+    let format = Format::from_path(path);
+    let source = Origin::File(path.to_path_buf()).source()?;
+
+    if format == Format::Ron && !has_includes(&source, format) {
+        return Ok((source, true));
+    }
+
+    Ok((resolved_source(path)?, false))
+}
+
+/// Every file that resolving `path` reads, starting with `path` itself
+///
+/// Includes that can't be read or parsed are listed without their own includes, so that fixing
+/// them can be noticed. Templates shipped with vjoy-linux aren't files.
+pub fn source_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    collect_files(&Origin::File(path.to_path_buf()), &mut files);
+
+    files
+}
+
+fn collect_files(origin: &Origin, files: &mut Vec<PathBuf>) {
+    if let Origin::File(path) = origin.canonical() {
+        // also ends cycles, which resolving reports
+        if files.contains(&path) {
+            return;
+        }
+
+        files.push(path);
+    }
+
+    let Ok(source) = origin.source() else {
+        return;
+    };
+    let Ok(probe) = origin.format().parse::<IncludeProbe>(&source) else {
+        return;
+    };
+
+    for include in probe.include.iter() {
+        if let Ok(origin) = origin.locate(include) {
+            collect_files(&origin, files);
+        }
+    }
+}
+
+/// Descriptor of `origin` with its includes applied, aliases the descriptor doesn't define are
+/// looked up in `inherited`
+fn load_layer(
+    origin: &Origin,
+    inherited: &[InputDeviceEntry],
+    stack: &mut Vec<Origin>,
+) -> Result<Layer> {
+    let canonical = origin.canonical();
+
+    if stack.contains(&canonical) {
+        bail!("{origin} includes itself");
+    }
+
+    let source = origin.source()?;
+    let file = format::parse_descriptor(&source, origin.format())
+        .map_err(|err| anyhow!("failed to parse {origin}: {err}"))?;
+
+    // older versions would silently drop it
+    if file.include.is_empty() && has_includes(&source, origin.format()) {
+        bail!("{origin}: include requires version: {CURRENT_VERSION}");
+    }
+
+    stack.push(canonical);
+
+    let mut merged = Layer::default();
+
+    for include in file.include.iter() {
+        let layer = {
+            // the closest device list, so that includes can use the aliases of their includer
+            let devices = [
+                file.input_devices.as_slice(),
+                merged.input_devices.as_deref().unwrap_or_default(),
+                inherited,
+            ]
+            .into_iter()
+            .find(|devices| !devices.is_empty())
+            .unwrap_or_default();
+
+            load_layer(&origin.locate(include)?, devices, stack)?
+        };
+
+        merged
+            .apply(layer)
+            .map_err(|err| anyhow!("{origin}: {err}"))?;
+    }
+
+    stack.pop();
+
+    let devices = merged.input_devices.clone().unwrap_or_default();
+    let devices = if devices.is_empty() {
+        inherited
+    } else {
+        &devices
+    };
+
+    let layer = Layer::new(file, devices).map_err(|err| anyhow!("{origin}: {err}"))?;
+
+    merged
+        .apply(layer)
+        .map_err(|err| anyhow!("{origin}: {err}"))?;
+
+    Ok(merged)
+}
+
+impl Origin {
+    /// Origin of an entry of `include`: paths (containing a `/` or having a file extension) are
+    /// relative to the including file, anything else is the name of a template in
+    /// [`templates_dir`] or one shipped with vjoy-linux
+    fn locate(&self, include: &str) -> Result<Origin> {
+        if include.contains('/') || Path::new(include).extension().is_some() {
+            let dir = match self {
+                Origin::File(path) => path.parent().unwrap_or(Path::new("")),
+                Origin::Template(_) => Path::new(""),
+            };
+
+            return Ok(Origin::File(dir.join(include)));
+        }
+
+        let path = templates_dir().join(format!("{include}.ron"));
+
+        if path.exists() {
+            return Ok(Origin::File(path));
+        }
+
+        TEMPLATES
+            .iter()
+            .find(|(name, _)| *name == include)
+            .map(|(name, _)| Origin::Template(name))
+            .ok_or(anyhow!(
+                "unknown template ({include}), templates are looked up in {}",
+                templates_dir().display()
+            ))
+    }
+
+    fn format(&self) -> Format {
+        match self {
+            Origin::File(path) => Format::from_path(path),
+            Origin::Template(_) => Format::Ron,
+        }
+    }
+
+    /// Same origin for every way of writing the path
+    fn canonical(&self) -> Origin {
+        match self {
+            Origin::File(path) => Origin::File(path.canonicalize().unwrap_or(path.clone())),
+            Origin::Template(name) => Origin::Template(name),
+        }
+    }
+
+    fn source(&self) -> Result<String> {
+        match self {
+            Origin::File(path) => fs::read_to_string(path)
+                .map_err(|err| anyhow!("failed to open {}: {err:?}", path.display())),
+            Origin::Template(name) => Ok(TEMPLATES
+                .iter()
+                .find(|(template, _)| template == name)
+                .map(|(_, source)| source.to_string())
+                .unwrap_or_default()),
+        }
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Template(name) => write!(f, "template {name}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use anyhow::Result;
+
+    use crate::{
+        descriptor::OutputType,
+        mappings::{Axis, Button},
+    };
+
+    use super::{resolve, source_files};
+
+    /// Writes `files` into a fresh directory
+    fn files(test: &str, files: &[(&str, &str)]) -> Result<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("vjoy-linux-compose-{test}"));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+
+        for (name, source) in files {
+            fs::write(dir.join(name), source)?;
+        }
+
+        Ok(dir)
+    }
+
+    #[test]
+    fn precedence() -> Result<()> {
+        let dir = files(
+            "precedence",
+            &[
+                (
+                    "base.ron",
+                    r#"(
+                        version: 2,
+                        input_devices: [
+                            (name: "T.16000M", alias: "left"),
+                            (name: "T.16000M", alias: "right"),
+                        ],
+                        output_device: Combined("Base"),
+                        key_mappings: {
+                            ("left", BTN_TRIGGER): BTN_0,
+                            ("right", BTN_TRIGGER): BTN_1,
+                        },
+                        axis_mappings: { ("left", ABS_X): ABS_X },
+                        profiles: { "landing": (key_mappings: { ("left", BTN_THUMB): BTN_2 }) },
+                        grab_inputs: true,
+                    )"#,
+                ),
+                (
+                    "throttle.ron",
+                    r#"(
+                        version: 2,
+                        axis_mappings: { ("right", ABS_THROTTLE): ABS_THROTTLE },
+                        key_mappings: { ("right", BTN_TRIGGER): BTN_5 },
+                    )"#,
+                ),
+                (
+                    "pilot.ron",
+                    r#"(
+                        version: 2,
+                        include: ["base.ron", "throttle.ron"],
+                        key_mappings: { ("left", BTN_TRIGGER): BTN_3 },
+                        profiles: { "landing": (key_mappings: { ("right", BTN_THUMB): BTN_4 }) },
+                        grab_inputs: false,
+                    )"#,
+                ),
+            ],
+        )?;
+
+        let desc = resolve(&dir.join("pilot.ron"))?;
+
+        assert_eq!(desc.input_devices, ["T.16000M", "T.16000M"]);
+        assert!(matches!(desc.output_device, OutputType::Combined(ref name) if name == "Base"));
+        assert!(!desc.grab_inputs);
+
+        // the including file overrides all includes, later includes override earlier ones
+        assert_eq!(desc.key_mappings[&(0, Button::BTN_TRIGGER)], Button::BTN_3);
+        assert_eq!(desc.key_mappings[&(1, Button::BTN_TRIGGER)], Button::BTN_5);
+        assert_eq!(desc.axis_mappings.len(), 2);
+
+        let landing = &desc.profiles["landing"];
+        assert_eq!(landing.key_mappings[&(0, Button::BTN_THUMB)], Button::BTN_2);
+        assert_eq!(landing.key_mappings[&(1, Button::BTN_THUMB)], Button::BTN_4);
+
+        // the base itself is unaffected
+        let base = resolve(&dir.join("base.ron"))?;
+        assert_eq!(base.key_mappings[&(0, Button::BTN_TRIGGER)], Button::BTN_0);
+        assert!(base.grab_inputs);
+
+        Ok(())
+    }
+
+    #[test]
+    fn templates() -> Result<()> {
+        let dir = files(
+            "templates",
+            &[
+                (
+                    "pilot.ron",
+                    r#"(
+                        version: 2,
+                        include: ["t16000m_combined"],
+                        axis_mappings: { (1, ABS_X): ABS_RUDDER },
+                    )"#,
+                ),
+                (
+                    "unknown.ron",
+                    r#"(version: 2, include: ["no_such_template"])"#,
+                ),
+            ],
+        )?;
+
+        let desc = resolve(&dir.join("pilot.ron"))?;
+        assert_eq!(desc.input_devices.len(), 2);
+        assert_eq!(desc.axis_mappings[&(1, Axis::ABS_X)], Axis::ABS_RUDDER);
+        assert_eq!(
+            desc.key_mappings[&(0, Button::BTN_TRIGGER)],
+            Button::BTN_TRIGGER
+        );
+
+        assert!(resolve(&dir.join("unknown.ron")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_includes() -> Result<()> {
+        let dir = files(
+            "invalid",
+            &[
+                ("a.ron", r#"(version: 2, include: ["./b.ron"])"#),
+                ("b.ron", r#"(version: 2, include: ["a.ron"])"#),
+                (
+                    "devices.ron",
+                    r#"(
+                        version: 2,
+                        include: ["t16000m_combined"],
+                        input_devices: ["Pedals"],
+                    )"#,
+                ),
+                (
+                    "legacy.ron",
+                    r#"(
+                        include: ["t16000m_combined"],
+                        input_devices: [],
+                        output_device: Combined("Legacy"),
+                        key_mappings: {},
+                        axis_mappings: {},
+                    )"#,
+                ),
+            ],
+        )?;
+
+        let err = resolve(&dir.join("a.ron")).unwrap_err();
+        assert!(err.to_string().contains("includes itself"));
+
+        assert!(resolve(&dir.join("devices.ron")).is_err());
+        assert!(resolve(&dir.join("legacy.ron")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn included_files() -> Result<()> {
+        let dir = files(
+            "included_files",
+            &[
+                (
+                    "a.ron",
+                    r#"(version: 2, include: ["b.ron", "t16000m_combined"])"#,
+                ),
+                ("b.ron", r#"(version: 2, include: ["./c.ron", "./a.ron"])"#),
+                ("c.ron", "not a descriptor"),
+            ],
+        )?;
+
+        let dir = dir.canonicalize()?;
+
+        // the shipped template isn't a file, the cycle back to a.ron is listed once
+        assert_eq!(
+            source_files(&dir.join("a.ron")),
+            [dir.join("a.ron"), dir.join("b.ron"), dir.join("c.ron")]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use mio::{
+    event::Event,
+    net::{UnixListener, UnixStream},
+    Interest, Registry, Token,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::mappings::{Axis, Button};
+
+/// One request per line, answered by exactly one `Response` line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Reload,
+    /// Switch to a named profile, or back to the base mappings if `name` is missing
+    Profile {
+        #[serde(default)]
+        name: Option<String>,
+    },
+    Profiles,
+    Devices,
+    State,
+    Inject {
+        output: usize,
+        events: Vec<InjectEvent>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InjectEvent {
+    Key { code: Button, value: i32 },
+    Axis { code: Axis, value: i32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Ok {
+        #[serde(default, skip_serializing_if = "Value::is_null")]
+        data: Value,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl From<Result<Value>> for Response {
+    fn from(result: Result<Value>) -> Self {
+        match result {
+            Ok(data) => Response::Ok { data },
+            Err(err) => Response::Error {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+pub fn default_socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("vjoy-linux.sock"),
+        None => env::temp_dir().join("vjoy-linux.sock"),
+    }
+}
+
+/// Control socket with its connections, driven by the readiness events of the runtime
+pub struct ControlServer {
+    listener: UnixListener,
+    token: Token,
+    connections: HashMap<Token, Connection>,
+    next_connection: usize,
+}
+
+struct Connection {
+    stream: UnixStream,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    /// The client won't send anything anymore, but might still wait for responses
+    closed: bool,
+}
+
+impl ControlServer {
+    /// Binds the socket, connections get tokens counting up from `first_connection`
+    pub fn bind(
+        path: &Path,
+        registry: &Registry,
+        token: Token,
+        first_connection: usize,
+    ) -> Result<Self> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                bail!("{} exists and is not a socket", path.display());
+            }
+
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                bail!("control socket {} is already in use", path.display());
+            }
+
+            // left over from a previous process
+            fs::remove_file(path)?;
+        }
+
+        let mut listener = UnixListener::bind(path)?;
+        registry.register(&mut listener, token, Interest::READABLE)?;
+
+        Ok(Self {
+            listener,
+            token,
+            connections: HashMap::new(),
+            next_connection: first_connection,
+        })
+    }
+
+    pub fn owns(&self, token: Token) -> bool {
+        token == self.token || self.connections.contains_key(&token)
+    }
+
+    /// Accepts new connections or reads from one, returns the complete requests that were received
+    ///
+    /// Each request has to be answered with `respond`. Invalid requests are answered right away.
+    pub fn handle_event(
+        &mut self,
+        registry: &Registry,
+        event: &Event,
+    ) -> Result<Vec<(Token, Request)>> {
+        if event.token() == self.token {
+            self.accept(registry)?;
+
+            return Ok(Vec::new());
+        }
+
+        let token = event.token();
+
+        let Some(connection) = self.connections.get_mut(&token) else {
+            return Ok(Vec::new());
+        };
+
+        let requests = match connection.receive() {
+            Ok(requests) => requests,
+            Err(_) => {
+                self.close(registry, token);
+                return Ok(Vec::new());
+            }
+        };
+
+        if requests.is_empty() && !connection.is_alive() {
+            self.close(registry, token);
+        }
+
+        Ok(requests
+            .into_iter()
+            .map(|request| (token, request))
+            .collect())
+    }
+
+    pub fn respond(&mut self, registry: &Registry, token: Token, response: &Response) {
+        let Some(connection) = self.connections.get_mut(&token) else {
+            // the client is already gone
+            return;
+        };
+
+        if connection.send(response).is_err() || !connection.is_alive() {
+            self.close(registry, token);
+        }
+    }
+
+    fn accept(&mut self, registry: &Registry) -> Result<()> {
+        loop {
+            let mut stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err.into()),
+            };
+
+            let token = Token(self.next_connection);
+            self.next_connection += 1;
+
+            registry.register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)?;
+
+            self.connections.insert(
+                token,
+                Connection {
+                    stream,
+                    input: Vec::new(),
+                    output: Vec::new(),
+                    closed: false,
+                },
+            );
+        }
+    }
+
+    fn close(&mut self, registry: &Registry, token: Token) {
+        if let Some(mut connection) = self.connections.remove(&token) {
+            let _ = registry.deregister(&mut connection.stream);
+        }
+    }
+}
+
+impl Connection {
+    fn receive(&mut self) -> io::Result<Vec<Request>> {
+        let mut buffer = [0; 4096];
+
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(count) => self.input.extend_from_slice(&buffer[..count]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut requests = Vec::new();
+
+        while let Some(end) = self.input.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str::<Request>(&line) {
+                Ok(request) => requests.push(request),
+                Err(err) => self.send(&Response::Error {
+                    message: format!("invalid request: {err}"),
+                })?,
+            }
+        }
+
+        // also flushes responses that didn't fit into the socket before
+        self.flush()?;
+
+        Ok(requests)
+    }
+
+    fn send(&mut self, response: &Response) -> io::Result<()> {
+        serde_json::to_writer(&mut self.output, response)?;
+        self.output.push(b'\n');
+
+        self.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(count) => {
+                    self.output.drain(..count);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_alive(&self) -> bool {
+        !self.closed || !self.output.is_empty()
+    }
+}
+
+/// Sends a single request to a running instance
+pub fn send(path: &Path, request: &Request) -> Result<Response> {
+    let mut stream = std::os::unix::net::UnixStream::connect(path)
+        .map_err(|err| anyhow!("failed to connect to {}: {err}", path.display()))?;
+
+    serde_json::to_writer(&mut stream, request)?;
+    stream.write_all(b"\n")?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env, fs,
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixStream,
+        process,
+    };
+
+    use mio::{Events, Poll, Token};
+    use serde_json::json;
+
+    use crate::mappings::Button;
+
+    use super::{ControlServer, InjectEvent, Request, Response};
+
+    #[test]
+    fn request_format() {
+        let request: Request = serde_json::from_str(
+            r#"{"command": "inject", "output": 0, "events": [{"type": "key", "code": "BTN_0", "value": 1}]}"#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            request,
+            Request::Inject { output: 0, events }
+                if matches!(events[..], [InjectEvent::Key { code: Button::BTN_0, value: 1 }])
+        ));
+
+        let request: Request = serde_json::from_str(r#"{"command": "profile"}"#).unwrap();
+        assert!(matches!(request, Request::Profile { name: None }));
+    }
+
+    #[test]
+    fn response_format() {
+        assert_eq!(
+            serde_json::to_value(Response::Ok { data: json!(null) }).unwrap(),
+            json!({"status": "ok"})
+        );
+
+        assert_eq!(
+            serde_json::to_value(Response::Error {
+                message: "unknown profile".to_string()
+            })
+            .unwrap(),
+            json!({"status": "error", "message": "unknown profile"})
+        );
+    }
+
+    #[test]
+    fn server_round_trip() {
+        let path = env::temp_dir().join(format!("vjoy-linux-test-{}.sock", process::id()));
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(16);
+        let mut server = ControlServer::bind(&path, poll.registry(), Token(0), 1).unwrap();
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        client
+            .write_all(b"{\"command\": \"state\"}\nnot json\n")
+            .unwrap();
+
+        let mut requests = Vec::new();
+
+        while requests.is_empty() {
+            poll.poll(&mut events, None).unwrap();
+
+            for event in events.iter() {
+                assert!(server.owns(event.token()));
+                requests.extend(server.handle_event(poll.registry(), event).unwrap());
+            }
+        }
+
+        assert!(matches!(requests[..], [(Token(1), Request::State)]));
+
+        server.respond(
+            poll.registry(),
+            Token(1),
+            &Response::Ok {
+                data: json!({"inputs": []}),
+            },
+        );
+
+        // the invalid line was answered right away
+        let mut lines = BufReader::new(client).lines();
+        assert!(lines.next().unwrap().unwrap().contains("invalid request"));
+        assert_eq!(
+            lines.next().unwrap().unwrap(),
+            r#"{"status":"ok","data":{"inputs":[]}}"#
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn existing_files() {
+        let path = env::temp_dir().join(format!("vjoy-linux-test-{}.file", process::id()));
+        let poll = Poll::new().unwrap();
+
+        // a path that isn't a socket is never removed
+        fs::write(&path, "data").unwrap();
+        let err = ControlServer::bind(&path, poll.registry(), Token(0), 1)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("is not a socket"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+        fs::remove_file(&path).unwrap();
+
+        // a socket left over by a previous process is replaced
+        let stale = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        drop(stale);
+        ControlServer::bind(&path, poll.registry(), Token(0), 1).unwrap();
+
+        fs::remove_file(path).unwrap();
+    }
+}
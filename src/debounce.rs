@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use evdev::{EventType, InputEvent};
+
+use crate::mappings::{Button, EventCode};
+
+/// Drops the bouncing state changes of buttons, per input device or per button
+///
+/// The first change of a button passes right away and starts its debounce window, further changes
+/// within the window are bounces. If the button ended up in another state when the window is over,
+/// that state is passed by [`Debouncer::settle`].
+#[derive(Debug, Default)]
+pub struct Debouncer {
+    devices: HashMap<usize, Duration>,
+    buttons: HashMap<(usize, Button), Duration>,
+    contacts: HashMap<(usize, Button), Contact>,
+    bounces: HashMap<(usize, Button), u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Contact {
+    /// Last state that was passed
+    value: i32,
+    /// Last state the device sent
+    raw: i32,
+    /// Start of the debounce window
+    since: SystemTime,
+}
+
+impl Debouncer {
+    /// Debounce windows in milliseconds, of all buttons of a device and of single buttons
+    pub fn new(devices: &HashMap<usize, u64>, buttons: &HashMap<(usize, Button), u64>) -> Self {
+        let mut debouncer = Self::default();
+        debouncer.set_windows(devices, buttons);
+
+        debouncer
+    }
+
+    /// Replaces the debounce windows, the state of the buttons and the bounce counts are kept
+    pub fn set_windows(
+        &mut self,
+        devices: &HashMap<usize, u64>,
+        buttons: &HashMap<(usize, Button), u64>,
+    ) {
+        self.devices = devices
+            .iter()
+            .map(|(&index, &millis)| (index, Duration::from_millis(millis)))
+            .collect();
+        self.buttons = buttons
+            .iter()
+            .map(|(&button, &millis)| (button, Duration::from_millis(millis)))
+            .collect();
+    }
+
+    /// Whether a state change of a button passes, `false` for bounces
+    ///
+    /// Events with a timestamp before the start of the window, e.g. of the clock being adjusted,
+    /// always pass.
+    pub fn accept(&mut self, index: usize, button: Button, value: i32, time: SystemTime) -> bool {
+        let window = self.window(index, button);
+
+        if window.is_zero() {
+            return true;
+        }
+
+        let Some(contact) = self.contacts.get_mut(&(index, button)) else {
+            let contact = Contact {
+                value,
+                raw: value,
+                since: time,
+            };
+            self.contacts.insert((index, button), contact);
+
+            return true;
+        };
+
+        contact.raw = value;
+
+        let bouncing = time
+            .duration_since(contact.since)
+            .is_ok_and(|elapsed| elapsed < window);
+
+        if bouncing {
+            *self.bounces.entry((index, button)).or_default() += 1;
+            return false;
+        }
+
+        if contact.value != value {
+            contact.value = value;
+            contact.since = time;
+        }
+
+        true
+    }
+
+    /// Time until the first window with a dropped state change is over
+    pub fn pending(&self, now: SystemTime) -> Option<Duration> {
+        self.contacts
+            .iter()
+            .filter(|(_, contact)| contact.raw != contact.value)
+            .map(|(&(index, button), contact)| {
+                (contact.since + self.window(index, button))
+                    .duration_since(now)
+                    .unwrap_or_default()
+            })
+            .min()
+    }
+
+    /// Key events for the buttons whose window is over and that rest in another state than the
+    /// one last passed, each starts a new window
+    pub fn settle(&mut self, now: SystemTime) -> Vec<(usize, InputEvent)> {
+        let Self {
+            devices,
+            buttons,
+            contacts,
+            ..
+        } = self;
+        let mut settled = Vec::new();
+
+        for (&(index, button), contact) in contacts.iter_mut() {
+            let window = window(devices, buttons, index, button);
+
+            let over = now
+                .duration_since(contact.since)
+                .map_or(true, |elapsed| elapsed >= window);
+
+            if contact.raw == contact.value || !over {
+                continue;
+            }
+
+            contact.value = contact.raw;
+            contact.since = now;
+
+            if let Some(code) = button.code() {
+                settled.push((index, InputEvent::new(EventType::KEY.0, code, contact.raw)));
+            }
+        }
+
+        settled
+    }
+
+    /// Forgets the state of the buttons of a device, e.g. after it failed
+    pub fn reset(&mut self, index: usize) {
+        self.contacts.retain(|&(device, _), _| device != index);
+    }
+
+    /// Number of dropped state changes of a button
+    pub fn bounces(&self, index: usize, button: Button) -> u64 {
+        self.bounces
+            .get(&(index, button))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// One line per button that bounced, using `names` for the input devices
+    pub fn report(&self, names: &[String]) -> String {
+        let mut bounces: Vec<_> = self.bounces.iter().collect();
+        bounces.sort_by_key(|((device, button), _)| (*device, button.code()));
+
+        bounces
+            .into_iter()
+            .map(|(&(device, button), count)| {
+                let name = names.get(device).map(String::as_str).unwrap_or("?");
+
+                format!("device {device} ({name}) {button:?}: {count} bounces\n")
+            })
+            .collect()
+    }
+
+    fn window(&self, index: usize, button: Button) -> Duration {
+        window(&self.devices, &self.buttons, index, button)
+    }
+}
+
+/// Window of a single button if it has one, otherwise of its device
+fn window(
+    devices: &HashMap<usize, Duration>,
+    buttons: &HashMap<(usize, Button), Duration>,
+    index: usize,
+    button: Button,
+) -> Duration {
+    match buttons.get(&(index, button)) {
+        Some(window) => *window,
+        None => devices.get(&index).copied().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        time::{Duration, SystemTime},
+    };
+
+    use evdev::KeyCode;
+
+    use crate::mappings::Button;
+
+    use super::Debouncer;
+
+    /// Whether each of `presses` passes, sent at the given milliseconds
+    fn accepted(debouncer: &mut Debouncer, start: SystemTime, presses: &[(u64, i32)]) -> Vec<bool> {
+        presses
+            .iter()
+            .map(|&(millis, value)| {
+                let time = start + Duration::from_millis(millis);
+                debouncer.accept(0, Button::BTN_TRIGGER, value, time)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bounces() {
+        let mut debouncer = Debouncer::new(&HashMap::from([(0, 10)]), &HashMap::new());
+        let start = SystemTime::now();
+
+        assert_eq!(
+            accepted(
+                &mut debouncer,
+                start,
+                &[(0, 1), (2, 0), (3, 1), (20, 0), (21, 1), (22, 0), (40, 1)]
+            ),
+            [true, false, false, true, false, false, true]
+        );
+        assert_eq!(debouncer.bounces(0, Button::BTN_TRIGGER), 4);
+
+        // nothing is left to settle, the buttons rest in the passed state
+        assert_eq!(debouncer.pending(start), None);
+
+        // other devices aren't debounced
+        assert!(debouncer.accept(1, Button::BTN_TRIGGER, 0, start));
+
+        let report = debouncer.report(&["Box".to_string()]);
+        assert_eq!(report, "device 0 (Box) BTN_TRIGGER: 4 bounces\n");
+    }
+
+    #[test]
+    fn settled() {
+        let mut debouncer = Debouncer::new(&HashMap::from([(0, 10)]), &HashMap::new());
+        let start = SystemTime::now();
+
+        // released within the window, which must not leave the button pressed
+        assert_eq!(
+            accepted(&mut debouncer, start, &[(0, 1), (4, 0)]),
+            [true, false]
+        );
+
+        assert_eq!(
+            debouncer.pending(start + Duration::from_millis(4)),
+            Some(Duration::from_millis(6))
+        );
+        assert!(debouncer
+            .settle(start + Duration::from_millis(5))
+            .is_empty());
+
+        let settled = debouncer.settle(start + Duration::from_millis(10));
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].0, 0);
+        assert_eq!(settled[0].1.code(), KeyCode::BTN_TRIGGER.0);
+        assert_eq!(settled[0].1.value(), 0);
+
+        assert_eq!(debouncer.pending(start), None);
+    }
+
+    #[test]
+    fn per_button() {
+        let mut debouncer = Debouncer::new(
+            &HashMap::from([(0, 10)]),
+            &HashMap::from([((0, Button::BTN_TRIGGER), 0), ((0, Button::BTN_THUMB), 30)]),
+        );
+        let start = SystemTime::now();
+
+        // excluded from the window of its device
+        assert_eq!(
+            accepted(&mut debouncer, start, &[(0, 1), (2, 0)]),
+            [true, true]
+        );
+
+        let thumb = |millis| start + Duration::from_millis(millis);
+        assert!(debouncer.accept(0, Button::BTN_THUMB, 1, thumb(0)));
+        assert!(!debouncer.accept(0, Button::BTN_THUMB, 0, thumb(20)));
+        assert!(debouncer.accept(0, Button::BTN_THUMB, 0, thumb(30)));
+
+        assert!(debouncer.accept(0, Button::BTN_TOP, 1, thumb(0)));
+        assert!(!debouncer.accept(0, Button::BTN_TOP, 0, thumb(5)));
+    }
+}
@@ -1,12 +1,26 @@
-use std::{collections::HashMap, fmt::Debug, fs, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug},
+    fs,
+    hash::Hash,
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    input_device::InputDevice,
-    mappings::{Axis, Button},
+    backend::{open_inputs, Backend, EvdevBackend},
+    calibration::Calibration,
+    capabilities::DeviceCapabilities,
+    compose,
+    filter::AxisFilter,
+    format::{self, Format},
+    mappings::{Axis, Button, EventCode},
+};
+use anyhow::{anyhow, bail, Result};
+use ron::{
+    error::{Position, SpannedError},
+    from_str,
 };
-use anyhow::{bail, Result};
-use ron::ser::{to_string_pretty, PrettyConfig};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GenerationDescription {
@@ -14,7 +28,7 @@ pub struct GenerationDescription {
     pub output: OutputType,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OutputType {
     Combined(String),
     Passthrough(Vec<String>),
@@ -46,97 +60,872 @@ impl From<String> for OutputType {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "DescriptorFile", into = "DescriptorFile")]
 pub struct VJoyDescriptor {
     pub input_devices: Vec<String>,
+    /// Alias of each input device, mappings in the file can refer to a device by it
+    pub aliases: Vec<Option<String>>,
     pub output_device: OutputType,
 
     pub key_mappings: HashMap<(usize, Button), Button>,
     pub axis_mappings: HashMap<(usize, Axis), Axis>,
+
+    /// Named sets of mappings that replace entries of the base mappings while active
+    pub profiles: HashMap<String, Profile>,
+
+    /// Values the mapped axes are set to on shutdown, instead of the center of their range
+    pub neutral_axes: HashMap<(usize, Axis), i32>,
+
+    /// Range the source axes actually reach, raw values are stretched to the advertised range
+    /// before they are mapped
+    pub calibration: HashMap<(usize, Axis), Calibration>,
+
+    /// Filters applied in order to the raw values of source axes, e.g. against jitter
+    pub axis_filters: HashMap<(usize, Axis), Vec<AxisFilter>>,
+
+    /// Debounce window in milliseconds of the buttons of each input device, state changes within
+    /// it are dropped as bounces
+    pub device_debounce: HashMap<usize, u64>,
+    /// Debounce window in milliseconds of single buttons, instead of the one of their device
+    pub button_debounce: HashMap<(usize, Button), u64>,
+
+    /// Grab the input devices, so that only vjoy-linux receives their events
+    pub grab_inputs: bool,
+
+    /// What happens when an input device fails, e.g. because it was unplugged
+    pub on_device_error: ErrorPolicy,
+}
+
+/// Descriptor without devices and mappings, for test fixtures to override fields of
+#[cfg(test)]
+impl Default for VJoyDescriptor {
+    fn default() -> Self {
+        Self {
+            input_devices: Vec::new(),
+            aliases: Vec::new(),
+            output_device: OutputType::Combined(String::new()),
+            key_mappings: HashMap::new(),
+            axis_mappings: HashMap::new(),
+            profiles: HashMap::new(),
+            neutral_axes: HashMap::new(),
+            calibration: HashMap::new(),
+            axis_filters: HashMap::new(),
+            device_debounce: HashMap::new(),
+            button_debounce: HashMap::new(),
+            grab_inputs: false,
+            on_device_error: ErrorPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorPolicy {
+    /// Report the error and try to open the device again until it is back
+    #[default]
+    Retry,
+    /// Report the error and continue without the device
+    Log,
+    /// Stop vjoy-linux
+    Exit,
+}
+
+impl ErrorPolicy {
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub key_mappings: HashMap<(usize, Button), Button>,
+    pub axis_mappings: HashMap<(usize, Axis), Axis>,
+}
+
+/// Refers to an input device by its position in `input_devices` or by its alias
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DeviceRef {
+    Index(usize),
+    Alias(String),
+}
+
+/// Entry of `input_devices` in the file, either just the device name or the name with an alias
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum InputDeviceEntry {
+    Name(String),
+    Aliased { name: String, alias: String },
+}
+
+/// Schema version of descriptors written by this version of vjoy-linux
+pub const CURRENT_VERSION: u32 = 2;
+
+/// Descriptor as written in the file, where devices can be referenced by alias
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DescriptorFile {
+    pub version: u32,
+
+    /// Descriptor files or template names whose entries this file is applied on top of
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+
+    #[serde(default)]
+    pub input_devices: Vec<InputDeviceEntry>,
+    #[serde(
+        default,
+        with = "plain_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub output_device: Option<OutputType>,
+
+    #[serde(default, serialize_with = "sorted")]
+    pub key_mappings: HashMap<(DeviceRef, Button), Button>,
+    #[serde(default, serialize_with = "sorted")]
+    pub axis_mappings: HashMap<(DeviceRef, Axis), Axis>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted_profiles",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub profiles: HashMap<String, ProfileFile>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub neutral_axes: HashMap<(DeviceRef, Axis), i32>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub calibration: HashMap<(DeviceRef, Axis), Calibration>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub axis_filters: HashMap<(DeviceRef, Axis), Vec<AxisFilter>>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted_devices",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub device_debounce: HashMap<DeviceRef, u64>,
+    #[serde(
+        default,
+        serialize_with = "sorted",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    pub button_debounce: HashMap<(DeviceRef, Button), u64>,
+
+    #[serde(
+        default,
+        with = "plain_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub grab_inputs: Option<bool>,
+
+    #[serde(
+        default,
+        with = "plain_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub on_device_error: Option<ErrorPolicy>,
+}
+
+/// Optional fields that are written as plain values instead of `Some(..)`, so that they can be
+/// left out of a file to be inherited from its includes
+mod plain_option {
+    use super::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => value.serialize(serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<T>, D::Error> {
+        T::deserialize(deserializer).map(Some)
+    }
+}
+
+/// Descriptor whose devices are referenced by index, built up by applying a file and its includes
+/// on top of each other
+#[derive(Debug, Default)]
+pub(crate) struct Layer {
+    pub input_devices: Option<Vec<InputDeviceEntry>>,
+    pub output_device: Option<OutputType>,
+    pub key_mappings: HashMap<(usize, Button), Button>,
+    pub axis_mappings: HashMap<(usize, Axis), Axis>,
+    pub profiles: HashMap<String, Profile>,
+    pub neutral_axes: HashMap<(usize, Axis), i32>,
+    pub calibration: HashMap<(usize, Axis), Calibration>,
+    pub axis_filters: HashMap<(usize, Axis), Vec<AxisFilter>>,
+    pub device_debounce: HashMap<usize, u64>,
+    pub button_debounce: HashMap<(usize, Button), u64>,
+    pub grab_inputs: Option<bool>,
+    pub on_device_error: Option<ErrorPolicy>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ProfileFile {
+    #[serde(default, serialize_with = "sorted")]
+    pub key_mappings: HashMap<(DeviceRef, Button), Button>,
+    #[serde(default, serialize_with = "sorted")]
+    pub axis_mappings: HashMap<(DeviceRef, Axis), Axis>,
+}
+
+/// Order of mapping entries in written files: by device, then by event code
+pub(crate) fn entry_order<K: EventCode + Debug>(
+    device: &DeviceRef,
+    code: &K,
+) -> (DeviceRef, Option<u16>, String) {
+    // codes of different names can be the same, e.g. BTN_TRIGGER and BTN_JOYSTICK
+    (device.clone(), code.code(), format!("{code:?}"))
+}
+
+/// Writes mappings in [`entry_order`], so that the same descriptor always results in the same file
+fn sorted<K: EventCode + Debug + Serialize, V: Serialize, S: Serializer>(
+    mappings: &HashMap<(DeviceRef, K), V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut entries: Vec<_> = mappings.iter().collect();
+    entries.sort_by_cached_key(|((device, code), _)| entry_order(device, code));
+
+    serializer.collect_map(entries)
+}
+
+fn sorted_devices<V: Serialize, S: Serializer>(
+    values: &HashMap<DeviceRef, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(values.iter().collect::<BTreeMap<_, _>>())
+}
+
+fn sorted_profiles<S: Serializer>(
+    profiles: &HashMap<String, ProfileFile>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(profiles.iter().collect::<BTreeMap<_, _>>())
+}
+
+impl fmt::Display for DeviceRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceRef::Index(index) => write!(f, "{index}"),
+            DeviceRef::Alias(alias) => write!(f, "{alias}"),
+        }
+    }
+}
+
+impl InputDeviceEntry {
+    pub fn name(&self) -> &str {
+        match self {
+            InputDeviceEntry::Name(name) | InputDeviceEntry::Aliased { name, .. } => name,
+        }
+    }
+
+    pub fn alias(&self) -> Option<&str> {
+        match self {
+            InputDeviceEntry::Name(_) => None,
+            InputDeviceEntry::Aliased { alias, .. } => Some(alias),
+        }
+    }
+}
+
+/// Schema version of a descriptor source in `format`, files without a version are version 1
+pub fn schema_version(source: &str, format: Format) -> Result<u32> {
+    Ok(format.parse::<VersionProbe>(source)?.version)
+}
+
+/// Rewrites a descriptor source of an older schema version in the current one, keeping its format
+/// and all of its mappings; `None` if it already is current
+///
+/// Comments and formatting of the original are not kept.
+pub fn migrate(source: &str, format: Format) -> Result<Option<String>> {
+    let version = schema_version(source, format)
+        .map_err(|err| anyhow!("failed to parse descriptor file: {err}"))?;
+
+    if version == CURRENT_VERSION {
+        return Ok(None);
+    }
+
+    let file = format::parse_descriptor(source, format)?;
+
+    Ok(Some(format::write_descriptor(&file, format)?))
+}
+
+/// Reads only the version of a descriptor file, all other fields are skipped
+#[derive(Deserialize)]
+struct VersionProbe {
+    #[serde(default = "legacy_version")]
+    version: u32,
+}
+
+/// Files without a version field
+fn legacy_version() -> u32 {
+    1
+}
+
+impl DescriptorFile {
+    /// Schema version of a descriptor source
+    pub fn version(source: &str) -> Result<u32, SpannedError> {
+        Ok(from_str::<VersionProbe>(source)?.version)
+    }
+
+    /// Parses a descriptor source of any known schema version, older versions are migrated to the
+    /// current one
+    pub fn parse(source: &str) -> Result<Self, SpannedError> {
+        match Self::version(source)? {
+            1 => Ok(from_str::<DescriptorFileV1>(source)?.into()),
+            CURRENT_VERSION => from_str(source),
+            version => Err(SpannedError {
+                code: ron::Error::Message(format!(
+                    "descriptor version {version} is not supported, the newest known version is \
+                     {CURRENT_VERSION}"
+                )),
+                position: Position { line: 1, col: 1 },
+            }),
+        }
+    }
+
+    /// Index of the referenced input device, `None` for unknown aliases
+    pub fn resolve(&self, device: &DeviceRef) -> Option<usize> {
+        resolve_device(&self.input_devices, device)
+    }
+}
+
+fn resolve_device(devices: &[InputDeviceEntry], device: &DeviceRef) -> Option<usize> {
+    match device {
+        DeviceRef::Index(index) => Some(*index),
+        DeviceRef::Alias(alias) => devices
+            .iter()
+            .position(|entry| entry.alias() == Some(alias)),
+    }
+}
+
+fn resolve_mappings<K: Copy + Eq + Hash + Debug, V: Clone>(
+    devices: &[InputDeviceEntry],
+    mappings: &HashMap<(DeviceRef, K), V>,
+) -> Result<HashMap<(usize, K), V>> {
+    let mut resolved = HashMap::new();
+
+    for ((device, code), value) in mappings.iter() {
+        let index =
+            resolve_device(devices, device).ok_or(anyhow!("unknown device alias ({device})"))?;
+
+        if resolved.insert((index, *code), value.clone()).is_some() {
+            bail!("({index}, {code:?}) is mapped twice, by index and by alias");
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Resolves values keyed by device only, like [`resolve_mappings`]
+fn resolve_devices<V: Clone>(
+    devices: &[InputDeviceEntry],
+    values: &HashMap<DeviceRef, V>,
+) -> Result<HashMap<usize, V>> {
+    let mut resolved = HashMap::new();
+
+    for (device, value) in values.iter() {
+        let index =
+            resolve_device(devices, device).ok_or(anyhow!("unknown device alias ({device})"))?;
+
+        if resolved.insert(index, value.clone()).is_some() {
+            bail!("device {index} is listed twice, by index and by alias");
+        }
+    }
+
+    Ok(resolved)
+}
+
+impl Layer {
+    /// Resolves the device references of `file` against its own input devices, or against
+    /// `inherited` if it doesn't list any
+    pub fn new(file: DescriptorFile, inherited: &[InputDeviceEntry]) -> Result<Self> {
+        let devices = if file.input_devices.is_empty() {
+            inherited
+        } else {
+            &file.input_devices
+        };
+
+        for (index, entry) in devices.iter().enumerate() {
+            if let Some(alias) = entry.alias() {
+                if devices[..index].iter().any(|e| e.alias() == Some(alias)) {
+                    bail!("device alias ({alias}) is used more than once");
+                }
+            }
+        }
+
+        let profiles = file
+            .profiles
+            .iter()
+            .map(|(name, profile)| {
+                Ok((
+                    name.clone(),
+                    Profile {
+                        key_mappings: resolve_mappings(devices, &profile.key_mappings)?,
+                        axis_mappings: resolve_mappings(devices, &profile.axis_mappings)?,
+                    },
+                ))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            key_mappings: resolve_mappings(devices, &file.key_mappings)?,
+            axis_mappings: resolve_mappings(devices, &file.axis_mappings)?,
+            profiles,
+            neutral_axes: resolve_mappings(devices, &file.neutral_axes)?,
+            calibration: resolve_mappings(devices, &file.calibration)?,
+            axis_filters: resolve_mappings(devices, &file.axis_filters)?,
+            device_debounce: resolve_devices(devices, &file.device_debounce)?,
+            button_debounce: resolve_mappings(devices, &file.button_debounce)?,
+            input_devices: (!file.input_devices.is_empty()).then_some(file.input_devices),
+            output_device: file.output_device,
+            grab_inputs: file.grab_inputs,
+            on_device_error: file.on_device_error,
+        })
+    }
+
+    /// Applies `layer` on top of this one, its entries take precedence
+    ///
+    /// Mappings are merged entry by entry, profiles of the same name as well. The input devices
+    /// can only be repeated (e.g. to give them different aliases), not changed, since the mappings
+    /// below refer to them by index.
+    pub fn apply(&mut self, layer: Layer) -> Result<()> {
+        if let Some(devices) = layer.input_devices {
+            if let Some(current) = &self.input_devices {
+                let names = |devices: &[InputDeviceEntry]| {
+                    devices
+                        .iter()
+                        .map(|entry| entry.name().to_string())
+                        .collect::<Vec<_>>()
+                };
+
+                if names(current) != names(&devices) {
+                    bail!(
+                        "input_devices {:?} differ from {:?} of the included descriptors",
+                        names(&devices),
+                        names(current)
+                    );
+                }
+            }
+
+            self.input_devices = Some(devices);
+        }
+
+        self.key_mappings.extend(layer.key_mappings);
+        self.axis_mappings.extend(layer.axis_mappings);
+        self.neutral_axes.extend(layer.neutral_axes);
+        self.calibration.extend(layer.calibration);
+        self.axis_filters.extend(layer.axis_filters);
+        self.device_debounce.extend(layer.device_debounce);
+        self.button_debounce.extend(layer.button_debounce);
+
+        for (name, profile) in layer.profiles {
+            let merged = self.profiles.entry(name).or_default();
+            merged.key_mappings.extend(profile.key_mappings);
+            merged.axis_mappings.extend(profile.axis_mappings);
+        }
+
+        if layer.output_device.is_some() {
+            self.output_device = layer.output_device;
+        }
+
+        if layer.grab_inputs.is_some() {
+            self.grab_inputs = layer.grab_inputs;
+        }
+
+        if layer.on_device_error.is_some() {
+            self.on_device_error = layer.on_device_error;
+        }
+
+        Ok(())
+    }
+
+    pub fn into_descriptor(self) -> Result<VJoyDescriptor> {
+        let input_devices = self.input_devices.unwrap_or_default();
+
+        Ok(VJoyDescriptor {
+            aliases: input_devices
+                .iter()
+                .map(|entry| entry.alias().map(|alias| alias.to_string()))
+                .collect(),
+            input_devices: input_devices
+                .iter()
+                .map(|entry| entry.name().to_string())
+                .collect(),
+            output_device: self
+                .output_device
+                .ok_or(anyhow!("output_device is missing"))?,
+            key_mappings: self.key_mappings,
+            axis_mappings: self.axis_mappings,
+            profiles: self.profiles,
+            neutral_axes: self.neutral_axes,
+            calibration: self.calibration,
+            axis_filters: self.axis_filters,
+            device_debounce: self.device_debounce,
+            button_debounce: self.button_debounce,
+            grab_inputs: self.grab_inputs.unwrap_or_default(),
+            on_device_error: self.on_device_error.unwrap_or_default(),
+        })
+    }
+}
+
+/// Refers to the device by its alias if it has one
+pub(crate) fn device_refs<K: Copy + Eq + Hash, V: Clone>(
+    aliases: &[Option<String>],
+    mappings: &HashMap<(usize, K), V>,
+) -> HashMap<(DeviceRef, K), V> {
+    mappings
+        .iter()
+        .map(|(&(index, code), value)| {
+            let device = match aliases.get(index) {
+                Some(Some(alias)) => DeviceRef::Alias(alias.clone()),
+                _ => DeviceRef::Index(index),
+            };
+
+            ((device, code), value.clone())
+        })
+        .collect()
+}
+
+/// Refers to the devices by their alias if they have one, like [`device_refs`]
+fn device_keys<V: Clone>(
+    aliases: &[Option<String>],
+    values: &HashMap<usize, V>,
+) -> HashMap<DeviceRef, V> {
+    values
+        .iter()
+        .map(|(&index, value)| {
+            let device = match aliases.get(index) {
+                Some(Some(alias)) => DeviceRef::Alias(alias.clone()),
+                _ => DeviceRef::Index(index),
+            };
+
+            (device, value.clone())
+        })
+        .collect()
+}
+
+impl TryFrom<DescriptorFile> for VJoyDescriptor {
+    type Error = anyhow::Error;
+
+    fn try_from(file: DescriptorFile) -> Result<Self> {
+        if file.version != CURRENT_VERSION {
+            bail!(
+                "descriptor version {} is not supported, expected {CURRENT_VERSION}",
+                file.version
+            );
+        }
+
+        if !file.include.is_empty() {
+            bail!("includes are only resolved when loading a descriptor file");
+        }
+
+        Layer::new(file, &[])?.into_descriptor()
+    }
+}
+
+impl From<VJoyDescriptor> for DescriptorFile {
+    fn from(descriptor: VJoyDescriptor) -> Self {
+        let aliases = &descriptor.aliases;
+
+        Self {
+            version: CURRENT_VERSION,
+            include: Vec::new(),
+            input_devices: descriptor
+                .input_devices
+                .iter()
+                .enumerate()
+                .map(|(index, name)| match aliases.get(index) {
+                    Some(Some(alias)) => InputDeviceEntry::Aliased {
+                        name: name.clone(),
+                        alias: alias.clone(),
+                    },
+                    _ => InputDeviceEntry::Name(name.clone()),
+                })
+                .collect(),
+            key_mappings: device_refs(aliases, &descriptor.key_mappings),
+            axis_mappings: device_refs(aliases, &descriptor.axis_mappings),
+            profiles: descriptor
+                .profiles
+                .iter()
+                .map(|(name, profile)| {
+                    (
+                        name.clone(),
+                        ProfileFile {
+                            key_mappings: device_refs(aliases, &profile.key_mappings),
+                            axis_mappings: device_refs(aliases, &profile.axis_mappings),
+                        },
+                    )
+                })
+                .collect(),
+            neutral_axes: device_refs(aliases, &descriptor.neutral_axes),
+            calibration: device_refs(aliases, &descriptor.calibration),
+            axis_filters: device_refs(aliases, &descriptor.axis_filters),
+            device_debounce: device_keys(aliases, &descriptor.device_debounce),
+            button_debounce: device_refs(aliases, &descriptor.button_debounce),
+            output_device: Some(descriptor.output_device),
+            grab_inputs: descriptor.grab_inputs.then_some(true),
+            on_device_error: (!descriptor.on_device_error.is_default())
+                .then_some(descriptor.on_device_error),
+        }
+    }
 }
 
 impl VJoyDescriptor {
+    /// Loads a descriptor file with its includes applied
+    pub fn load(path: &Path) -> Result<Self> {
+        compose::resolve(path)
+    }
+
+    /// Writes the descriptor in the format of the file extension
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(
+            path,
+            format::write_descriptor(&self.clone().into(), Format::from_path(path))?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Parses a descriptor of any known schema version, without includes
+    pub fn parse(source: &str) -> Result<Self> {
+        DescriptorFile::parse(source)
+            .map_err(|err| anyhow!("failed to parse descriptor file: {err:?}"))?
+            .try_into()
+    }
+
+    /// Descriptor with the mappings of `profile` applied on top of the base mappings
+    pub fn with_profile(&self, profile: Option<&str>) -> Result<Self> {
+        let mut descriptor = Self {
+            profiles: HashMap::new(),
+            ..self.clone()
+        };
+
+        if let Some(name) = profile {
+            let profile = self
+                .profiles
+                .get(name)
+                .ok_or(anyhow!("unknown profile ({name})"))?;
+
+            descriptor.key_mappings.extend(&profile.key_mappings);
+            descriptor.axis_mappings.extend(&profile.axis_mappings);
+        }
+
+        Ok(descriptor)
+    }
+
+    pub fn check(&self) -> Result<()> {
+        if !self.output_device.is_combined()
+            && self.output_device.count() != self.input_devices.len()
+        {
+            bail!("passthrough count must match input count!");
+        }
+
+        for ((index, axis), filters) in self.axis_filters.iter() {
+            for filter in filters {
+                filter
+                    .check()
+                    .map_err(|err| anyhow!("filter of ({index}, {axis:?}): {err}"))?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn generate_from_cli(
         input_devices: String,
         output_device: Option<String>,
         output_file: Option<PathBuf>,
+        comments: bool,
     ) -> Result<()> {
-        let descriptor = Self::generate_descriptor(GenerationDescription {
-            input: input_devices.split(',').map(|s| s.to_string()).collect(),
-            output: output_device
-                .unwrap_or("Combined Joystick".to_string())
-                .into(),
-        })?;
+        let output_file = output_file.unwrap_or("stub_descriptor.ron".into());
 
-        fs::write(
-            output_file.unwrap_or("stub_descriptor.ron".into()),
-            &to_string_pretty(&descriptor, PrettyConfig::default())?,
+        let generated = Self::generate_file(
+            GenerationDescription {
+                input: input_devices.split(',').map(|s| s.to_string()).collect(),
+                output: output_device
+                    .unwrap_or("Combined Joystick".to_string())
+                    .into(),
+            },
+            &EvdevBackend,
+            Format::from_path(&output_file),
+            comments,
         )?;
 
+        fs::write(output_file, generated)?;
+
         Ok(())
     }
 
-    pub fn generate_descriptor(generation: GenerationDescription) -> Result<Self> {
+    /// Generated descriptor written in `format`, optionally with a comment on every mapping
+    /// naming its device (and the range of axes)
+    pub fn generate_file(
+        generation: GenerationDescription,
+        backend: &dyn Backend,
+        format: Format,
+        comments: bool,
+    ) -> Result<String> {
+        let capabilities = generation_capabilities(&generation, backend)?;
+        let descriptor = Self::generate_from_capabilities(generation, &capabilities)?;
+        let written = format::write_descriptor(&descriptor.into(), format)?;
+
+        if comments {
+            format::annotate(&written, format, &capabilities)
+        } else {
+            Ok(written)
+        }
+    }
+
+    /// Maps everything the devices support, the first device is passed through and the others
+    /// are stubbed in combined mode
+    pub fn generate_descriptor(
+        generation: GenerationDescription,
+        backend: &dyn Backend,
+    ) -> Result<Self> {
+        let capabilities = generation_capabilities(&generation, backend)?;
+
+        Self::generate_from_capabilities(generation, &capabilities)
+    }
+
+    fn generate_from_capabilities(
+        generation: GenerationDescription,
+        capabilities: &[DeviceCapabilities],
+    ) -> Result<Self> {
         if !generation.output.is_combined() && generation.output.count() != generation.input.len() {
             bail!("passthrough count must match input count!");
         }
 
-        let mut stub_devices = InputDevice::find_unique_input_devices(&generation.input)?.0;
-        let passthrough_device = stub_devices.remove(0);
-
         let mut key_mappings = HashMap::new();
         let mut axis_mappings = HashMap::new();
 
-        if let Some(keys) = passthrough_device.device().supported_keys() {
-            for key in keys.iter() {
-                key_mappings.insert((0, key.into()), key.into());
-            }
-        }
+        for (index, device) in capabilities.iter().enumerate() {
+            let stub = index > 0 && generation.output.is_combined();
 
-        if let Some(axes) = passthrough_device.device().supported_absolute_axes() {
-            for axis in axes.iter() {
-                axis_mappings.insert((0, axis.into()), axis.into());
+            for &key in device.keys.iter() {
+                key_mappings.insert((index, key), if stub { Button::Stub } else { key });
             }
-        }
 
-        for (index, stub_device) in stub_devices.iter().enumerate() {
-            if let Some(keys) = stub_device.device().supported_keys() {
-                for key in keys.iter() {
-                    key_mappings.insert(
-                        (index + 1, key.into()),
-                        if generation.output.is_combined() {
-                            Button::Stub
-                        } else {
-                            key.into()
-                        },
-                    );
-                }
-            }
-
-            if let Some(axes) = stub_device.device().supported_absolute_axes() {
-                for axis in axes.iter() {
-                    axis_mappings.insert(
-                        (index + 1, axis.into()),
-                        if generation.output.is_combined() {
-                            Axis::Stub
-                        } else {
-                            axis.into()
-                        },
-                    );
-                }
+            for &(axis, _) in device.axes.iter() {
+                axis_mappings.insert((index, axis), if stub { Axis::Stub } else { axis });
             }
         }
 
         Ok(Self {
             input_devices: generation.input,
+            aliases: Vec::new(),
             output_device: generation.output,
 
             key_mappings,
             axis_mappings,
+            profiles: HashMap::new(),
+            neutral_axes: HashMap::new(),
+            calibration: HashMap::new(),
+            axis_filters: HashMap::new(),
+            device_debounce: HashMap::new(),
+            button_debounce: HashMap::new(),
+            grab_inputs: false,
+            on_device_error: ErrorPolicy::default(),
         })
     }
 }
 
+fn generation_capabilities(
+    generation: &GenerationDescription,
+    backend: &dyn Backend,
+) -> Result<Vec<DeviceCapabilities>> {
+    open_inputs(backend, &generation.input)?
+        .iter()
+        .map(|source| source.capabilities())
+        .collect()
+}
+
+/// Schema of files written before the version field was introduced, devices are only referenced
+/// by index
+#[derive(Deserialize)]
+struct DescriptorFileV1 {
+    input_devices: Vec<String>,
+    output_device: OutputType,
+
+    key_mappings: HashMap<(usize, Button), Button>,
+    axis_mappings: HashMap<(usize, Axis), Axis>,
+
+    #[serde(default)]
+    profiles: HashMap<String, ProfileV1>,
+    #[serde(default)]
+    neutral_axes: HashMap<(usize, Axis), i32>,
+    #[serde(default)]
+    grab_inputs: bool,
+    #[serde(default)]
+    on_device_error: ErrorPolicy,
+}
+
+#[derive(Deserialize)]
+struct ProfileV1 {
+    #[serde(default)]
+    key_mappings: HashMap<(usize, Button), Button>,
+    #[serde(default)]
+    axis_mappings: HashMap<(usize, Axis), Axis>,
+}
+
+impl From<DescriptorFileV1> for DescriptorFile {
+    fn from(file: DescriptorFileV1) -> Self {
+        let no_aliases = &[];
+
+        Self {
+            version: CURRENT_VERSION,
+            include: Vec::new(),
+            input_devices: file
+                .input_devices
+                .into_iter()
+                .map(InputDeviceEntry::Name)
+                .collect(),
+            output_device: Some(file.output_device),
+            key_mappings: device_refs(no_aliases, &file.key_mappings),
+            axis_mappings: device_refs(no_aliases, &file.axis_mappings),
+            profiles: file
+                .profiles
+                .into_iter()
+                .map(|(name, profile)| {
+                    (
+                        name,
+                        ProfileFile {
+                            key_mappings: device_refs(no_aliases, &profile.key_mappings),
+                            axis_mappings: device_refs(no_aliases, &profile.axis_mappings),
+                        },
+                    )
+                })
+                .collect(),
+            neutral_axes: device_refs(no_aliases, &file.neutral_axes),
+            calibration: HashMap::new(),
+            axis_filters: HashMap::new(),
+            device_debounce: HashMap::new(),
+            button_debounce: HashMap::new(),
+            grab_inputs: file.grab_inputs.then_some(true),
+            on_device_error: (!file.on_device_error.is_default()).then_some(file.on_device_error),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::{collections::HashMap, fs};
@@ -144,9 +933,15 @@ mod test {
     use anyhow::Result;
     use ron::ser::{to_string_pretty, PrettyConfig};
 
-    use crate::VJoyDescriptor;
+    use crate::{
+        capabilities::{AxisRange, DeviceCapabilities},
+        format::Format,
+        mappings::{Axis, Button},
+        mock::MockBackend,
+        VJoyDescriptor,
+    };
 
-    use super::OutputType;
+    use super::{OutputType, Profile};
 
     #[test]
     fn create_empty_description_file() -> Result<()> {
@@ -156,36 +951,289 @@ mod test {
                 "Thrustmaster T.16000M".to_string(),
             ],
             output_device: OutputType::Combined("".to_string()),
-            key_mappings: HashMap::new(),
-            axis_mappings: HashMap::new(),
+            ..VJoyDescriptor::default()
         };
 
         fs::write(
-            "example_descriptor.ron",
+            std::env::temp_dir().join("vjoy-linux-example-descriptor.ron"),
             &to_string_pretty(&desc, PrettyConfig::default())?,
         )?;
 
         Ok(())
     }
 
+    fn t16000m() -> DeviceCapabilities {
+        let range = AxisRange {
+            minimum: 0,
+            maximum: 16383,
+            fuzz: 63,
+            flat: 1023,
+            resolution: 0,
+        };
+
+        DeviceCapabilities {
+            name: "Thrustmaster T.16000M".to_string(),
+            vendor: 0x044f,
+            product: 0xb10a,
+            keys: vec![Button::BTN_TRIGGER, Button::BTN_THUMB, Button::BTN_THUMB2],
+            axes: vec![
+                (Axis::ABS_X, range),
+                (Axis::ABS_Y, range),
+                (Axis::ABS_RZ, range),
+            ],
+        }
+    }
+
     #[test]
     fn generate_stub_description_file() -> Result<()> {
-        let desc = VJoyDescriptor::generate_descriptor(super::GenerationDescription {
+        let backend = MockBackend::new();
+        backend.add_input(t16000m());
+        backend.add_input(t16000m());
+
+        let desc = VJoyDescriptor::generate_descriptor(
+            super::GenerationDescription {
+                input: vec![
+                    "Thrustmaster T.16000M".to_string(),
+                    "Thrustmaster T.16000M".to_string(),
+                ],
+                output: OutputType::Passthrough(vec![
+                    "Thrustmaster 1".to_string(),
+                    "Thrustmaster 2".to_string(),
+                ]),
+            },
+            &backend,
+        )?;
+
+        assert_eq!(desc.key_mappings.len(), 6);
+        assert_eq!(desc.axis_mappings.len(), 6);
+        assert_eq!(
+            desc.key_mappings[&(1, Button::BTN_THUMB)],
+            Button::BTN_THUMB
+        );
+        assert_eq!(desc.axis_mappings[&(1, Axis::ABS_RZ)], Axis::ABS_RZ);
+
+        fs::write(
+            std::env::temp_dir().join("vjoy-linux-stub-descriptor.ron"),
+            &to_string_pretty(&desc, PrettyConfig::default())?,
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn generated_file() -> Result<()> {
+        let backend = MockBackend::new();
+        backend.add_input(t16000m());
+        backend.add_input(t16000m());
+
+        let generate = |format, comments| {
+            VJoyDescriptor::generate_file(
+                super::GenerationDescription {
+                    input: vec![
+                        "Thrustmaster T.16000M".to_string(),
+                        "Thrustmaster T.16000M".to_string(),
+                    ],
+                    output: OutputType::Passthrough(vec![
+                        "Thrustmaster 1".to_string(),
+                        "Thrustmaster 2".to_string(),
+                    ]),
+                },
+                &backend,
+                format,
+                comments,
+            )
+        };
+
+        let plain = generate(Format::Ron, false)?;
+        assert_eq!(plain, generate(Format::Ron, false)?);
+
+        // by device, then by code
+        let position = |entry: &str| plain.find(entry).unwrap();
+        assert!(position("(0, BTN_TRIGGER)") < position("(0, BTN_THUMB)"));
+        assert!(position("(0, BTN_THUMB2)") < position("(1, BTN_TRIGGER)"));
+        assert!(position("(0, ABS_X)") < position("(0, ABS_RZ)"));
+        assert!(position("(0, ABS_RZ)") < position("(1, ABS_X)"));
+
+        let commented = generate(Format::Ron, true)?;
+        assert!(commented.contains("(1, BTN_THUMB): BTN_THUMB, // Thrustmaster T.16000M\n"));
+        assert!(commented.contains("(0, ABS_X): ABS_X, // Thrustmaster T.16000M, 0 to 16383\n"));
+        assert_eq!(
+            VJoyDescriptor::parse(&commented)?.key_mappings,
+            VJoyDescriptor::parse(&plain)?.key_mappings
+        );
+
+        let toml = generate(Format::Toml, true)?;
+        assert!(toml.contains(r#""1:ABS_RZ" = "ABS_RZ" # Thrustmaster T.16000M, 0 to 16383"#));
+        assert!(generate(Format::Json, true).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_combined() -> Result<()> {
+        let backend = MockBackend::new();
+        backend.add_input(t16000m());
+        backend.add_input(t16000m());
+
+        let generation = || super::GenerationDescription {
             input: vec![
                 "Thrustmaster T.16000M".to_string(),
                 "Thrustmaster T.16000M".to_string(),
             ],
-            output: OutputType::Passthrough(vec![
-                "Thrustmaster 1".to_string(),
-                "Thrustmaster 2".to_string(),
+            output: OutputType::Combined("Combined Joystick".to_string()),
+        };
+
+        let desc = VJoyDescriptor::generate_descriptor(generation(), &backend)?;
+
+        // only the first device is passed through
+        assert_eq!(
+            desc.key_mappings[&(0, Button::BTN_THUMB)],
+            Button::BTN_THUMB
+        );
+        assert_eq!(desc.key_mappings[&(1, Button::BTN_THUMB)], Button::Stub);
+        assert_eq!(desc.axis_mappings[&(0, Axis::ABS_X)], Axis::ABS_X);
+        assert_eq!(desc.axis_mappings[&(1, Axis::ABS_X)], Axis::Stub);
+
+        // a device that isn't connected
+        assert!(VJoyDescriptor::generate_descriptor(generation(), &MockBackend::new()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn profile_overrides() -> Result<()> {
+        let desc = VJoyDescriptor {
+            input_devices: vec!["Thrustmaster T.16000M".to_string()],
+            key_mappings: HashMap::from([
+                ((0, Button::BTN_TRIGGER), Button::BTN_0),
+                ((0, Button::BTN_THUMB), Button::BTN_1),
             ]),
-        })?;
+            axis_mappings: HashMap::from([((0, Axis::ABS_X), Axis::ABS_X)]),
+            profiles: HashMap::from([(
+                "landing".to_string(),
+                Profile {
+                    key_mappings: HashMap::from([((0, Button::BTN_TRIGGER), Button::BTN_5)]),
+                    axis_mappings: HashMap::new(),
+                },
+            )]),
+            ..VJoyDescriptor::default()
+        };
 
-        fs::write(
-            "stub_descriptor.ron",
-            &to_string_pretty(&desc, PrettyConfig::default())?,
+        let landing = desc.with_profile(Some("landing"))?;
+        assert_eq!(
+            landing.key_mappings[&(0, Button::BTN_TRIGGER)],
+            Button::BTN_5
+        );
+        assert_eq!(landing.key_mappings[&(0, Button::BTN_THUMB)], Button::BTN_1);
+        assert!(landing.profiles.is_empty());
+
+        let base = desc.with_profile(None)?;
+        assert_eq!(base.key_mappings[&(0, Button::BTN_TRIGGER)], Button::BTN_0);
+
+        assert!(desc.with_profile(Some("unknown")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn aliases() -> Result<()> {
+        let desc = VJoyDescriptor::parse(
+            r#"(
+                version: 2,
+                input_devices: [
+                    (name: "Thrustmaster T.16000M", alias: "left_stick"),
+                    (name: "Thrustmaster T.16000M", alias: "right_stick"),
+                    "Pedals",
+                ],
+                output_device: Combined("Combined"),
+                key_mappings: {
+                    ("right_stick", BTN_TRIGGER): BTN_0,
+                    (0, BTN_TRIGGER): BTN_1,
+                },
+                axis_mappings: { (2, ABS_RZ): ABS_RZ },
+                neutral_axes: { ("left_stick", ABS_X): 0 },
+            )"#,
         )?;
 
+        assert_eq!(desc.input_devices[2], "Pedals");
+        assert_eq!(desc.key_mappings[&(1, Button::BTN_TRIGGER)], Button::BTN_0);
+        assert_eq!(desc.key_mappings[&(0, Button::BTN_TRIGGER)], Button::BTN_1);
+        assert_eq!(desc.neutral_axes[&(0, Axis::ABS_X)], 0);
+
+        // devices with an alias are written with it
+        let written = to_string_pretty(&desc, PrettyConfig::default())?;
+        assert!(written.contains(r#"("left_stick", BTN_TRIGGER)"#));
+        assert!(written.contains("(2, ABS_RZ)"));
+
+        let parsed = VJoyDescriptor::parse(&written)?;
+        assert_eq!(parsed.key_mappings, desc.key_mappings);
+        assert_eq!(parsed.aliases, desc.aliases);
+
+        let unknown = r#"(
+            version: 2,
+            input_devices: [(name: "Left", alias: "left")],
+            output_device: Combined("Combined"),
+            key_mappings: { ("right", BTN_TRIGGER): BTN_0 },
+            axis_mappings: {},
+        )"#;
+        assert!(VJoyDescriptor::parse(unknown).is_err());
+
+        let twice = r#"(
+            version: 2,
+            input_devices: [(name: "Left", alias: "left")],
+            output_device: Combined("Combined"),
+            key_mappings: { ("left", BTN_TRIGGER): BTN_0, (0, BTN_TRIGGER): BTN_1 },
+            axis_mappings: {},
+        )"#;
+        assert!(VJoyDescriptor::parse(twice).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn versions() -> Result<()> {
+        let legacy = r#"(
+            input_devices: ["Left", "Right"],
+            output_device: Combined("Combined"),
+            key_mappings: { (1, BTN_TRIGGER): BTN_0 },
+            axis_mappings: { (0, ABS_X): ABS_X },
+            profiles: { "landing": (key_mappings: { (0, BTN_TRIGGER): BTN_1 }) },
+            grab_inputs: true,
+        )"#;
+        assert_eq!(super::schema_version(legacy, Format::Ron)?, 1);
+
+        let desc = VJoyDescriptor::parse(legacy)?;
+        assert_eq!(desc.key_mappings[&(1, Button::BTN_TRIGGER)], Button::BTN_0);
+        assert!(desc.grab_inputs);
+
+        let migrated = super::migrate(legacy, Format::Ron)?.unwrap();
+        assert_eq!(
+            super::schema_version(&migrated, Format::Ron)?,
+            super::CURRENT_VERSION
+        );
+        assert!(super::migrate(&migrated, Format::Ron)?.is_none());
+
+        let parsed = VJoyDescriptor::parse(&migrated)?;
+        assert_eq!(parsed.key_mappings, desc.key_mappings);
+        assert_eq!(parsed.axis_mappings, desc.axis_mappings);
+        assert_eq!(
+            parsed.profiles["landing"].key_mappings,
+            desc.profiles["landing"].key_mappings
+        );
+
+        let newer = legacy.replacen("(", "(version: 3,", 1);
+        assert!(VJoyDescriptor::parse(&newer).is_err());
+
+        // only the current version rejects unknown fields
+        let unknown = legacy.replacen("(", "(version: 2, key_mapings: {},", 1);
+        assert!(VJoyDescriptor::parse(&unknown).is_err());
+        let unknown = legacy.replacen("(", "(key_mapings: {},", 1);
+        assert!(VJoyDescriptor::parse(&unknown).is_ok());
+
+        for example in fs::read_dir("example_descriptor")? {
+            VJoyDescriptor::load(&example?.path())?;
+        }
+
         Ok(())
     }
 }
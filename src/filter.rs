@@ -0,0 +1,342 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    f64::consts::PI,
+    time::{Duration, SystemTime},
+};
+
+use evdev::{EventType, InputEvent};
+use serde::{Deserialize, Serialize};
+
+use crate::mappings::{Axis, EventCode};
+
+/// Time between two events that is assumed when their timestamps don't tell
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Time between two steps of smoothed axes catching up with an axis at rest, see
+/// [`AxisFilters::settle`]
+pub const SETTLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Filter for the calibrated values of a source axis, e.g. against the jitter of pedals at rest
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AxisFilter {
+    /// Drops changes smaller than the threshold, like the fuzz of the kernel
+    Threshold(i32),
+    /// Average of the last n values
+    MovingAverage(usize),
+    /// Exponential smoothing with the weight (0 to 1) of each new value
+    Exponential(f64),
+    /// 1€ filter, smooths slow movements strongly and fast ones barely: `min_cutoff` (Hz) is the
+    /// cutoff frequency at rest, `beta` raises it with the speed of the axis (in units per
+    /// second)
+    OneEuro {
+        min_cutoff: f64,
+        beta: f64,
+        #[serde(default = "default_derivative_cutoff")]
+        derivative_cutoff: f64,
+    },
+}
+
+fn default_derivative_cutoff() -> f64 {
+    1.0
+}
+
+/// State of the filters of every filtered source axis
+#[derive(Debug, Default)]
+pub struct AxisFilters {
+    chains: HashMap<(usize, Axis), Vec<Stage>>,
+}
+
+/// A filter with the values it has seen
+#[derive(Debug)]
+struct Stage {
+    filter: AxisFilter,
+    input: Option<i32>,
+    output: Option<f64>,
+    /// Last values, for `MovingAverage`
+    window: VecDeque<i32>,
+    /// Smoothed speed, for `OneEuro`
+    derivative: f64,
+    time: Option<SystemTime>,
+}
+
+impl AxisFilter {
+    /// Describes what is wrong with the parameters
+    pub fn check(&self) -> Result<(), String> {
+        match *self {
+            AxisFilter::Threshold(threshold) if threshold < 1 => {
+                Err(format!("threshold {threshold} must be at least 1"))
+            }
+            AxisFilter::MovingAverage(count) if count < 1 => Err(format!(
+                "moving average of {count} values must be of at least 1"
+            )),
+            AxisFilter::Exponential(weight) if !(weight > 0.0 && weight <= 1.0) => Err(format!(
+                "exponential smoothing weight {weight} must be above 0 and at most 1"
+            )),
+            AxisFilter::OneEuro {
+                min_cutoff,
+                beta,
+                derivative_cutoff,
+            } if !(min_cutoff > 0.0 && beta >= 0.0 && derivative_cutoff > 0.0) => {
+                Err("1€ filter cutoffs must be above 0 and beta must not be negative".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl AxisFilters {
+    pub fn new(filters: &HashMap<(usize, Axis), Vec<AxisFilter>>) -> Self {
+        Self {
+            chains: filters
+                .iter()
+                .map(|(&axis, filters)| (axis, filters.iter().map(Stage::new).collect()))
+                .collect(),
+        }
+    }
+
+    /// Value of a source axis with its filters applied in order, `None` if one of them drops it
+    ///
+    /// Values of axes without filters are passed through.
+    pub fn filter(
+        &mut self,
+        index: usize,
+        axis: Axis,
+        value: i32,
+        time: SystemTime,
+    ) -> Option<i32> {
+        match self.chains.get_mut(&(index, axis)) {
+            Some(chain) => apply(chain, value, time),
+            None => Some(value),
+        }
+    }
+
+    /// Whether every filter has caught up with the last value of its axis
+    pub fn is_settled(&self) -> bool {
+        self.chains.values().flatten().all(Stage::is_settled)
+    }
+
+    /// Feeds the last value of every axis whose filters lag behind it again
+    ///
+    /// Devices only send events when an axis moves, so smoothed axes would otherwise stop short
+    /// of where the axis came to rest.
+    pub fn settle(&mut self) -> Vec<(usize, InputEvent)> {
+        let now = SystemTime::now();
+
+        self.chains
+            .iter_mut()
+            .filter_map(|(&(index, axis), chain)| {
+                // earlier filters might drop the same value, e.g. a threshold
+                let first = chain.iter().position(|stage| !stage.is_settled())?;
+                let input = chain[first].input?;
+
+                let value = apply(&mut chain[first..], input, now)?;
+                let axis = axis.code()?;
+
+                Some((index, InputEvent::new(EventType::ABSOLUTE.0, axis, value)))
+            })
+            .collect()
+    }
+}
+
+fn apply(chain: &mut [Stage], value: i32, time: SystemTime) -> Option<i32> {
+    chain
+        .iter_mut()
+        .try_fold(value, |value, stage| stage.apply(value, time))
+}
+
+impl Stage {
+    fn new(filter: &AxisFilter) -> Self {
+        Self {
+            filter: *filter,
+            input: None,
+            output: None,
+            window: VecDeque::new(),
+            derivative: 0.0,
+            time: None,
+        }
+    }
+
+    fn apply(&mut self, value: i32, time: SystemTime) -> Option<i32> {
+        let interval = self
+            .time
+            .and_then(|last| time.duration_since(last).ok())
+            .unwrap_or_default()
+            .max(MIN_INTERVAL)
+            .as_secs_f64();
+
+        let previous = self.output;
+        let raw = f64::from(value);
+
+        self.input = Some(value);
+        self.time = Some(time);
+
+        let output = match (self.filter, previous) {
+            (AxisFilter::Threshold(threshold), Some(previous))
+                if (raw - previous).abs() < f64::from(threshold) =>
+            {
+                return None;
+            }
+            (AxisFilter::MovingAverage(count), _) => {
+                self.window.push_back(value);
+
+                while self.window.len() > count.max(1) {
+                    self.window.pop_front();
+                }
+
+                self.window.iter().map(|&v| f64::from(v)).sum::<f64>() / self.window.len() as f64
+            }
+            (AxisFilter::Exponential(weight), Some(previous)) => smooth(weight, raw, previous),
+            (
+                AxisFilter::OneEuro {
+                    min_cutoff,
+                    beta,
+                    derivative_cutoff,
+                },
+                Some(previous),
+            ) => {
+                let speed = (raw - previous) / interval;
+                self.derivative =
+                    smooth(weight(derivative_cutoff, interval), speed, self.derivative);
+
+                let cutoff = min_cutoff + beta * self.derivative.abs();
+                smooth(weight(cutoff, interval), raw, previous)
+            }
+            // the first value is taken as it is
+            _ => raw,
+        };
+
+        self.output = Some(output);
+
+        Some(output.round() as i32)
+    }
+
+    fn is_settled(&self) -> bool {
+        match (self.filter, self.input, self.output) {
+            (AxisFilter::Threshold(_), ..) => true,
+            (_, Some(input), Some(output)) => output.round() as i32 == input,
+            _ => true,
+        }
+    }
+}
+
+fn smooth(weight: f64, value: f64, previous: f64) -> f64 {
+    weight * value + (1.0 - weight) * previous
+}
+
+/// Weight of a new value for exponential smoothing with a cutoff frequency
+fn weight(cutoff: f64, interval: f64) -> f64 {
+    let tau = 1.0 / (2.0 * PI * cutoff);
+
+    1.0 / (1.0 + tau / interval)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::mappings::Axis;
+
+    use super::{AxisFilter, AxisFilters};
+
+    fn filters(filters: Vec<AxisFilter>) -> AxisFilters {
+        AxisFilters::new(&HashMap::from([((0, Axis::ABS_RZ), filters)]))
+    }
+
+    /// Filtered values of `values`, sent 10ms apart
+    fn filtered(filters: &mut AxisFilters, values: &[i32]) -> Vec<Option<i32>> {
+        let start = SystemTime::now();
+
+        values
+            .iter()
+            .enumerate()
+            .map(|(n, &value)| {
+                let time = start + Duration::from_millis(10 * n as u64);
+                filters.filter(0, Axis::ABS_RZ, value, time)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn threshold() {
+        let mut filters = filters(vec![AxisFilter::Threshold(3)]);
+
+        assert_eq!(
+            filtered(&mut filters, &[100, 102, 98, 103, 105, 200]),
+            [Some(100), None, None, Some(103), None, Some(200)]
+        );
+        assert!(filters.is_settled());
+
+        // other axes are passed through
+        let now = SystemTime::now();
+        assert_eq!(filters.filter(1, Axis::ABS_RZ, 101, now), Some(101));
+        assert_eq!(filters.filter(0, Axis::ABS_X, 101, now), Some(101));
+    }
+
+    #[test]
+    fn moving_average() {
+        let mut filters = filters(vec![AxisFilter::MovingAverage(3)]);
+
+        assert_eq!(
+            filtered(&mut filters, &[0, 30, 60, 90]),
+            [Some(0), Some(15), Some(30), Some(60)]
+        );
+
+        // catches up with the last value without further events
+        assert!(!filters.is_settled());
+
+        let settled: Vec<_> =
+            std::iter::from_fn(|| filters.settle().first().map(|(_, event)| event.value()))
+                .collect();
+
+        assert_eq!(settled, [80, 90]);
+        assert!(filters.is_settled());
+    }
+
+    #[test]
+    fn exponential() {
+        let mut filters = filters(vec![AxisFilter::Threshold(2), AxisFilter::Exponential(0.5)]);
+
+        assert_eq!(
+            filtered(&mut filters, &[0, 100, 101, 100]),
+            [Some(0), Some(50), None, None]
+        );
+
+        for _ in 0..10 {
+            filters.settle();
+        }
+
+        assert!(filters.is_settled());
+    }
+
+    #[test]
+    fn one_euro() {
+        let mut filters = filters(vec![AxisFilter::OneEuro {
+            min_cutoff: 1.0,
+            beta: 0.01,
+            derivative_cutoff: 1.0,
+        }]);
+
+        // jitter at rest is smoothed strongly
+        let jitter = filtered(&mut filters, &[1000, 1004, 996, 1004, 996, 1004]);
+        assert!(jitter
+            .iter()
+            .flatten()
+            .all(|&value| (999..=1001).contains(&value)));
+
+        // fast movements are followed closely
+        let moved = filtered(&mut filters, &[4000, 8000, 12000]);
+        assert!(moved[2].unwrap() > 9000, "{moved:?}");
+    }
+
+    #[test]
+    fn checked() {
+        assert!(AxisFilter::Threshold(0).check().is_err());
+        assert!(AxisFilter::MovingAverage(0).check().is_err());
+        assert!(AxisFilter::Exponential(1.5).check().is_err());
+        assert!(AxisFilter::Exponential(f64::NAN).check().is_err());
+        assert!(AxisFilter::Exponential(0.3).check().is_ok());
+    }
+}
@@ -0,0 +1,567 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug},
+    hash::Hash,
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Result};
+use ron::ser::{to_string_pretty, PrettyConfig};
+use serde::{de::DeserializeOwned, Deserialize, Serialize, Serializer};
+
+use crate::{
+    calibration::Calibration,
+    capabilities::DeviceCapabilities,
+    descriptor::{
+        entry_order, schema_version, DescriptorFile, DeviceRef, ErrorPolicy, InputDeviceEntry,
+        OutputType, ProfileFile, CURRENT_VERSION,
+    },
+    filter::AxisFilter,
+    mappings::{Axis, Button, EventCode},
+};
+
+/// File format of a descriptor, picked by the file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ron,
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// Descriptor in formats whose maps only have string keys
+///
+/// Mapping keys are written as `"<device>:<code>"`, e.g. `"left_stick:BTN_TRIGGER"` or
+/// `"0:ABS_X"`, keys of whole devices as `"<device>"`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextFile {
+    version: u32,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
+
+    #[serde(default)]
+    input_devices: Vec<InputDeviceEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output_device: Option<OutputType>,
+
+    #[serde(default, serialize_with = "sorted::<Button, _, _>")]
+    key_mappings: BTreeMap<String, Button>,
+    #[serde(default, serialize_with = "sorted::<Axis, _, _>")]
+    axis_mappings: BTreeMap<String, Axis>,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, TextProfile>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted::<Axis, _, _>",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    neutral_axes: BTreeMap<String, i32>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted::<Axis, _, _>",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    calibration: BTreeMap<String, Calibration>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted::<Axis, _, _>",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    axis_filters: BTreeMap<String, Vec<AxisFilter>>,
+
+    #[serde(
+        default,
+        serialize_with = "sorted_devices",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    device_debounce: BTreeMap<String, u64>,
+    #[serde(
+        default,
+        serialize_with = "sorted::<Button, _, _>",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    button_debounce: BTreeMap<String, u64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    grab_inputs: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    on_device_error: Option<ErrorPolicy>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextProfile {
+    #[serde(default, serialize_with = "sorted::<Button, _, _>")]
+    key_mappings: BTreeMap<String, Button>,
+    #[serde(default, serialize_with = "sorted::<Axis, _, _>")]
+    axis_mappings: BTreeMap<String, Axis>,
+}
+
+/// Schema of text descriptors without a version field, devices are only referenced by index
+#[derive(Debug, Deserialize)]
+struct TextFileV1 {
+    input_devices: Vec<String>,
+    output_device: OutputType,
+
+    key_mappings: BTreeMap<String, Button>,
+    axis_mappings: BTreeMap<String, Axis>,
+
+    #[serde(default)]
+    profiles: BTreeMap<String, TextProfile>,
+    #[serde(default)]
+    neutral_axes: BTreeMap<String, i32>,
+    #[serde(default)]
+    grab_inputs: bool,
+    #[serde(default)]
+    on_device_error: ErrorPolicy,
+}
+
+impl Format {
+    /// Format of a file by its extension, RON for anything unknown
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Format::Json,
+            Some("toml") => Format::Toml,
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => Format::Ron,
+        }
+    }
+
+    pub(crate) fn parse<T: DeserializeOwned>(self, source: &str) -> Result<T> {
+        Ok(match self {
+            Format::Ron => ron::from_str(source)?,
+            Format::Json => serde_json::from_str(source)?,
+            Format::Toml => toml::from_str(source)?,
+            Format::Yaml => serde_yaml::from_str(source)?,
+        })
+    }
+
+    fn write<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            Format::Ron => to_string_pretty(value, PrettyConfig::default())?,
+            Format::Json => serde_json::to_string_pretty(value)?,
+            Format::Toml => toml::to_string_pretty(value)?,
+            Format::Yaml => serde_yaml::to_string(value)?,
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Ron => write!(f, "RON"),
+            Format::Json => write!(f, "JSON"),
+            Format::Toml => write!(f, "TOML"),
+            Format::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+/// Parses a descriptor in `format`
+pub(crate) fn parse_descriptor(source: &str, format: Format) -> Result<DescriptorFile> {
+    if format == Format::Ron {
+        return DescriptorFile::parse(source).map_err(|err| anyhow!("{err}"));
+    }
+
+    match schema_version(source, format)? {
+        1 => format.parse::<TextFileV1>(source)?.try_into(),
+        CURRENT_VERSION => format.parse::<TextFile>(source)?.try_into(),
+        version => {
+            bail!("descriptor version {version} is not supported, expected {CURRENT_VERSION}")
+        }
+    }
+}
+
+/// Writes a descriptor in `format`
+pub(crate) fn write_descriptor(file: &DescriptorFile, format: Format) -> Result<String> {
+    if format == Format::Ron {
+        return format.write(file);
+    }
+
+    format.write(&TextFile::try_from(file)?)
+}
+
+/// Converts a descriptor source between formats, includes are kept as they are
+pub fn convert(source: &str, from: Format, to: Format) -> Result<String> {
+    write_descriptor(&parse_descriptor(source, from)?, to)
+}
+
+/// Appends a comment to every mapping entry of a descriptor written in `format`, naming the device
+/// of the entry and for axes their range
+///
+/// Only entries that refer to their device by index are annotated.
+pub fn annotate(
+    written: &str,
+    format: Format,
+    capabilities: &[DeviceCapabilities],
+) -> Result<String> {
+    let marker = match format {
+        Format::Ron => "//",
+        Format::Toml | Format::Yaml => "#",
+        Format::Json => bail!("JSON does not support comments"),
+    };
+
+    let mut comments = Vec::new();
+
+    for (index, device) in capabilities.iter().enumerate() {
+        let device_ref = DeviceRef::Index(index);
+
+        for key in device.keys.iter() {
+            comments.push((entry_start(format, &device_ref, key)?, device.name.clone()));
+        }
+
+        for (axis, range) in device.axes.iter() {
+            comments.push((
+                entry_start(format, &device_ref, axis)?,
+                format!("{}, {} to {}", device.name, range.minimum, range.maximum),
+            ));
+        }
+    }
+
+    let mut annotated = String::new();
+
+    for line in written.lines() {
+        annotated.push_str(line);
+
+        let entry = line.trim_start();
+
+        if let Some((_, comment)) = comments.iter().find(|(start, _)| entry.starts_with(start)) {
+            annotated.push_str(&format!(" {marker} {comment}"));
+        }
+
+        annotated.push('\n');
+    }
+
+    Ok(annotated)
+}
+
+/// How a line of a mapping entry starts in `format`
+fn entry_start<K: Serialize>(format: Format, device: &DeviceRef, code: &K) -> Result<String> {
+    Ok(match format {
+        Format::Ron => format!("({device}, {}):", ron::to_string(code)?),
+        Format::Json => format!("\"{}\":", key_text(device, code)?),
+        Format::Toml => format!("\"{}\" =", key_text(device, code)?),
+        Format::Yaml => format!("{}:", key_text(device, code)?),
+    })
+}
+
+fn key_text<K: Serialize>(device: &DeviceRef, code: &K) -> Result<String> {
+    Ok(format!("{device}:{}", ron::to_string(code)?))
+}
+
+fn parse_key<K: DeserializeOwned>(key: &str) -> Result<(DeviceRef, K)> {
+    let (device, code) = key.rsplit_once(':').ok_or(anyhow!(
+        "mapping key ({key}) is not of the form <device>:<code>"
+    ))?;
+
+    let code = ron::from_str(code).map_err(|err| anyhow!("mapping key ({key}): {err}"))?;
+
+    Ok((parse_device(device), code))
+}
+
+fn parse_device(device: &str) -> DeviceRef {
+    match device.parse() {
+        Ok(index) => DeviceRef::Index(index),
+        Err(_) => DeviceRef::Alias(device.to_string()),
+    }
+}
+
+/// Writes mappings in [`entry_order`] instead of the order of their keys as text
+fn sorted<K: DeserializeOwned + EventCode + Debug, V: Serialize, S: Serializer>(
+    mappings: &BTreeMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut entries: Vec<_> = mappings.iter().collect();
+    entries.sort_by_cached_key(|(key, _)| {
+        parse_key::<K>(key)
+            .ok()
+            .map(|(device, code)| entry_order(&device, &code))
+    });
+
+    serializer.collect_map(entries)
+}
+
+/// Writes values of whole devices in the order of their [`DeviceRef`], indices before aliases
+fn sorted_devices<V: Serialize, S: Serializer>(
+    values: &BTreeMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut entries: Vec<_> = values.iter().collect();
+    entries.sort_by_cached_key(|(device, _)| parse_device(device));
+
+    serializer.collect_map(entries)
+}
+
+fn text_mappings<K: Serialize, V: Clone>(
+    mappings: &HashMap<(DeviceRef, K), V>,
+) -> Result<BTreeMap<String, V>> {
+    mappings
+        .iter()
+        .map(|((device, code), value)| Ok((key_text(device, code)?, value.clone())))
+        .collect()
+}
+
+fn file_mappings<K: DeserializeOwned + Eq + Hash, V: Clone>(
+    mappings: &BTreeMap<String, V>,
+) -> Result<HashMap<(DeviceRef, K), V>> {
+    mappings
+        .iter()
+        .map(|(key, value)| Ok((parse_key(key)?, value.clone())))
+        .collect()
+}
+
+impl TryFrom<TextFile> for DescriptorFile {
+    type Error = anyhow::Error;
+
+    fn try_from(file: TextFile) -> Result<Self> {
+        Ok(Self {
+            version: file.version,
+            include: file.include,
+            input_devices: file.input_devices,
+            output_device: file.output_device,
+            key_mappings: file_mappings(&file.key_mappings)?,
+            axis_mappings: file_mappings(&file.axis_mappings)?,
+            profiles: file
+                .profiles
+                .iter()
+                .map(|(name, profile)| {
+                    Ok((
+                        name.clone(),
+                        ProfileFile {
+                            key_mappings: file_mappings(&profile.key_mappings)?,
+                            axis_mappings: file_mappings(&profile.axis_mappings)?,
+                        },
+                    ))
+                })
+                .collect::<Result<_>>()?,
+            neutral_axes: file_mappings(&file.neutral_axes)?,
+            calibration: file_mappings(&file.calibration)?,
+            axis_filters: file_mappings(&file.axis_filters)?,
+            device_debounce: file
+                .device_debounce
+                .iter()
+                .map(|(device, &window)| (parse_device(device), window))
+                .collect(),
+            button_debounce: file_mappings(&file.button_debounce)?,
+            grab_inputs: file.grab_inputs,
+            on_device_error: file.on_device_error,
+        })
+    }
+}
+
+impl TryFrom<TextFileV1> for DescriptorFile {
+    type Error = anyhow::Error;
+
+    fn try_from(file: TextFileV1) -> Result<Self> {
+        // without aliases, keys naming a device by anything but its index fail to resolve
+        TextFile {
+            version: CURRENT_VERSION,
+            include: Vec::new(),
+            input_devices: file
+                .input_devices
+                .into_iter()
+                .map(InputDeviceEntry::Name)
+                .collect(),
+            output_device: Some(file.output_device),
+            key_mappings: file.key_mappings,
+            axis_mappings: file.axis_mappings,
+            profiles: file.profiles,
+            neutral_axes: file.neutral_axes,
+            calibration: BTreeMap::new(),
+            axis_filters: BTreeMap::new(),
+            device_debounce: BTreeMap::new(),
+            button_debounce: BTreeMap::new(),
+            grab_inputs: file.grab_inputs.then_some(true),
+            on_device_error: (!file.on_device_error.is_default()).then_some(file.on_device_error),
+        }
+        .try_into()
+    }
+}
+
+impl TryFrom<&DescriptorFile> for TextFile {
+    type Error = anyhow::Error;
+
+    fn try_from(file: &DescriptorFile) -> Result<Self> {
+        Ok(Self {
+            version: file.version,
+            include: file.include.clone(),
+            input_devices: file.input_devices.clone(),
+            output_device: file.output_device.clone(),
+            key_mappings: text_mappings(&file.key_mappings)?,
+            axis_mappings: text_mappings(&file.axis_mappings)?,
+            profiles: file
+                .profiles
+                .iter()
+                .map(|(name, profile)| {
+                    Ok((
+                        name.clone(),
+                        TextProfile {
+                            key_mappings: text_mappings(&profile.key_mappings)?,
+                            axis_mappings: text_mappings(&profile.axis_mappings)?,
+                        },
+                    ))
+                })
+                .collect::<Result<_>>()?,
+            neutral_axes: text_mappings(&file.neutral_axes)?,
+            calibration: text_mappings(&file.calibration)?,
+            axis_filters: text_mappings(&file.axis_filters)?,
+            device_debounce: file
+                .device_debounce
+                .iter()
+                .map(|(device, &window)| (device.to_string(), window))
+                .collect(),
+            button_debounce: text_mappings(&file.button_debounce)?,
+            grab_inputs: file.grab_inputs,
+            on_device_error: file.on_device_error,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use anyhow::Result;
+
+    use crate::{
+        descriptor::{migrate, schema_version, VJoyDescriptor, CURRENT_VERSION},
+        mappings::{Axis, Button},
+    };
+
+    use super::{convert, parse_descriptor, Format};
+
+    const DESCRIPTOR: &str = r#"(
+        version: 2,
+        input_devices: [(name: "Stick", alias: "stick"), "Pedals"],
+        output_device: Passthrough(["Virtual Stick", "Virtual Pedals"]),
+        key_mappings: {
+            ("stick", BTN_TRIGGER): BTN_0,
+            ("stick", Unknown(300)): Unknown(301),
+        },
+        axis_mappings: { (1, ABS_RZ): ABS_RUDDER },
+        profiles: { "landing": (key_mappings: { ("stick", BTN_THUMB): BTN_1 }) },
+        neutral_axes: { (1, ABS_RZ): 0 },
+        calibration: { ("stick", ABS_X): (min: 120, center: 8100, max: 16250) },
+        axis_filters: { (1, ABS_RZ): [Threshold(4), OneEuro(min_cutoff: 1.0, beta: 0.001)] },
+        device_debounce: { "stick": 15, 1: 5 },
+        button_debounce: { ("stick", BTN_THUMB): 30 },
+        on_device_error: Log,
+    )"#;
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let original = VJoyDescriptor::parse(DESCRIPTOR)?;
+        assert_eq!(original.device_debounce[&0], 15);
+
+        for format in [Format::Json, Format::Toml, Format::Yaml] {
+            let converted = convert(DESCRIPTOR, Format::Ron, format)?;
+            assert!(converted.contains("stick:BTN_TRIGGER"), "{format}");
+
+            let descriptor: VJoyDescriptor = parse_descriptor(&converted, format)?.try_into()?;
+            assert_eq!(descriptor.key_mappings, original.key_mappings, "{format}");
+            assert_eq!(descriptor.axis_mappings, original.axis_mappings, "{format}");
+            assert_eq!(descriptor.neutral_axes, original.neutral_axes, "{format}");
+            assert_eq!(descriptor.calibration, original.calibration, "{format}");
+            assert_eq!(descriptor.axis_filters, original.axis_filters, "{format}");
+            assert_eq!(
+                descriptor.device_debounce, original.device_debounce,
+                "{format}"
+            );
+            assert_eq!(descriptor.button_debounce, original.button_debounce);
+            assert_eq!(descriptor.aliases, original.aliases, "{format}");
+            assert_eq!(descriptor.on_device_error, original.on_device_error);
+            assert_eq!(
+                descriptor.profiles["landing"].key_mappings,
+                original.profiles["landing"].key_mappings
+            );
+
+            let back = convert(&converted, format, Format::Ron)?;
+            let descriptor = VJoyDescriptor::parse(&back)?;
+            assert_eq!(descriptor.key_mappings, original.key_mappings, "{format}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn text_formats() -> Result<()> {
+        assert_eq!(Format::from_path(Path::new("a.toml")), Format::Toml);
+        assert_eq!(Format::from_path(Path::new("a.yml")), Format::Yaml);
+        assert_eq!(Format::from_path(Path::new("a.ron")), Format::Ron);
+        assert_eq!(Format::from_path(Path::new("descriptor")), Format::Ron);
+
+        let toml = r#"
+            version = 2
+            input_devices = ["Stick"]
+            output_device = { Combined = "Joystick" }
+
+            [key_mappings]
+            "0:BTN_TRIGGER" = "BTN_0"
+
+            [axis_mappings]
+            "0:ABS_X" = "ABS_RX"
+        "#;
+
+        let descriptor: VJoyDescriptor = parse_descriptor(toml, Format::Toml)?.try_into()?;
+        assert_eq!(
+            descriptor.key_mappings[&(0, Button::BTN_TRIGGER)],
+            Button::BTN_0
+        );
+        assert_eq!(descriptor.axis_mappings[&(0, Axis::ABS_X)], Axis::ABS_RX);
+
+        let json = r#"{
+            "version": 2,
+            "input_devices": ["Stick"],
+            "output_device": { "Combined": "Joystick" },
+            "key_mappings": { "BTN_TRIGGER": "BTN_0" }
+        }"#;
+        assert!(parse_descriptor(json, Format::Json).is_err());
+
+        // version 1 requires the mappings, as in RON
+        let unversioned = r#"{ "input_devices": [], "output_device": { "Combined": "J" } }"#;
+        assert!(parse_descriptor(unversioned, Format::Json).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_text_formats() -> Result<()> {
+        let legacy = r#"{
+            "input_devices": ["Left", "Right"],
+            "output_device": { "Combined": "Joystick" },
+            "key_mappings": { "1:BTN_TRIGGER": "BTN_0" },
+            "axis_mappings": { "0:ABS_X": "ABS_X" },
+            "profiles": { "landing": { "key_mappings": { "0:BTN_TRIGGER": "BTN_1" } } },
+            "grab_inputs": true
+        }"#;
+        assert_eq!(schema_version(legacy, Format::Json)?, 1);
+
+        let descriptor: VJoyDescriptor = parse_descriptor(legacy, Format::Json)?.try_into()?;
+        assert_eq!(
+            descriptor.key_mappings[&(1, Button::BTN_TRIGGER)],
+            Button::BTN_0
+        );
+        assert!(descriptor.grab_inputs);
+
+        // migrated in the same format
+        let migrated = migrate(legacy, Format::Json)?.unwrap();
+        assert_eq!(schema_version(&migrated, Format::Json)?, CURRENT_VERSION);
+
+        let parsed: VJoyDescriptor = parse_descriptor(&migrated, Format::Json)?.try_into()?;
+        assert_eq!(parsed.key_mappings, descriptor.key_mappings);
+        assert_eq!(parsed.axis_mappings, descriptor.axis_mappings);
+        assert_eq!(
+            parsed.profiles["landing"].key_mappings,
+            descriptor.profiles["landing"].key_mappings
+        );
+
+        Ok(())
+    }
+}
@@ -1,72 +1,124 @@
-use std::{
-    path::PathBuf,
-    sync::mpsc::{channel, Receiver, Sender},
-    thread,
-};
+use std::{fmt, io, os::fd::AsRawFd};
 
-use anyhow::{bail, Result};
+use anyhow::Result;
 use evdev::{enumerate, Device, EventSummary};
+use mio::{unix::SourceFd, Interest, Registry, Token};
 
+use crate::{backend::EventSource, capabilities::DeviceCapabilities};
+
+/// evdev device events are read from
 pub struct InputDevice {
-    index: usize,
     path: String,
     device: Device,
-    sender: Sender<(usize, EventSummary)>,
 }
 
-impl InputDevice {
-    pub fn find_unique_input_devices(
-        input_device_names: &[String],
-    ) -> Result<(Vec<Self>, Receiver<(usize, EventSummary)>)> {
-        let mut input_devices: Vec<(PathBuf, Device)> = Vec::new();
-
-        for name in input_device_names {
-            for (path, device) in enumerate() {
-                if let Some(device_name) = device.name() {
-                    if name == device_name && !input_devices.iter().any(|(p, _)| path == *p) {
-                        input_devices.push((path, device));
-                        break;
-                    }
-                }
-            }
-        }
+/// Why an input device stopped delivering events
+#[derive(Debug)]
+pub enum DeviceError {
+    Disconnected,
+    PermissionDenied,
+    Io(io::Error),
+}
 
-        if input_devices.len() != input_device_names.len() {
-            bail!("could not find all input devices!");
-        }
+impl InputDevice {
+    /// Opens the first device named `name` at a path that isn't in `used_paths`
+    pub fn open(name: &str, used_paths: &[&str]) -> Option<Self> {
+        enumerate()
+            .map(|(path, device)| (path.to_string_lossy().to_string(), device))
+            .find(|(path, device)| {
+                device.name() == Some(name) && !used_paths.contains(&path.as_str())
+            })
+            .map(|(path, device)| Self { path, device })
+    }
 
-        let (sender, receiver) = channel();
-
-        Ok((
-            input_devices
-                .into_iter()
-                .enumerate()
-                .map(|(index, (p, d))| Self {
-                    index,
-                    path: p.into_os_string().into_string().unwrap(),
-                    device: d,
-                    sender: sender.clone(),
-                })
-                .collect(),
-            receiver,
-        ))
+    pub fn device(&self) -> &Device {
+        &self.device
     }
+}
 
-    pub fn path(&self) -> &str {
+impl EventSource for InputDevice {
+    fn path(&self) -> &str {
         &self.path
     }
 
-    pub fn device(&self) -> &Device {
-        &self.device
+    fn capabilities(&self) -> Result<DeviceCapabilities> {
+        DeviceCapabilities::from_device(&self.device)
+    }
+
+    fn grab(&mut self) -> io::Result<()> {
+        self.device.grab()
     }
 
-    pub fn start_event_loop(mut self) {
-        thread::spawn(move || -> Result<()> {
-            loop {
-                for event in self.device.fetch_events()? {
-                    self.sender.send((self.index, event.destructure()))?;
-                }
+    fn ungrab(&mut self) -> io::Result<()> {
+        self.device.ungrab()
+    }
+
+    fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        self.device.set_nonblocking(true)?;
+
+        registry.register(
+            &mut SourceFd(&self.device.as_raw_fd()),
+            token,
+            Interest::READABLE,
+        )
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        registry.deregister(&mut SourceFd(&self.device.as_raw_fd()))
+    }
+
+    fn read_events(&mut self, events: &mut Vec<EventSummary>) -> Result<(), DeviceError> {
+        loop {
+            match self.device.fetch_events() {
+                Ok(fetched) => events.extend(fetched.map(|event| event.destructure())),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err.into()),
             }
-        });
+        }
+    }
+}
+
+impl From<io::Error> for DeviceError {
+    fn from(err: io::Error) -> Self {
+        match (err.raw_os_error(), err.kind()) {
+            (Some(libc::ENODEV), _) => DeviceError::Disconnected,
+            (_, io::ErrorKind::PermissionDenied) => DeviceError::PermissionDenied,
+            _ => DeviceError::Io(err),
+        }
+    }
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::Disconnected => write!(f, "disconnected"),
+            DeviceError::PermissionDenied => write!(f, "permission denied"),
+            DeviceError::Io(err) => write!(f, "I/O error: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use super::DeviceError;
+
+    #[test]
+    fn error_kinds() {
+        let error = |err: io::Error| DeviceError::from(err);
+
+        assert!(matches!(
+            error(io::Error::from_raw_os_error(libc::ENODEV)),
+            DeviceError::Disconnected
+        ));
+        assert!(matches!(
+            error(io::Error::from_raw_os_error(libc::EACCES)),
+            DeviceError::PermissionDenied
+        ));
+        assert!(matches!(
+            error(io::Error::from_raw_os_error(libc::EIO)),
+            DeviceError::Io(_)
+        ));
     }
 }
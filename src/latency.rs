@@ -0,0 +1,176 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use evdev::EventSummary;
+
+const BUCKETS: usize = 32;
+
+/// Durations counted in power of two buckets of microseconds
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventKind {
+    Key,
+    Axis,
+}
+
+/// Time from the kernel timestamp of an input event until its mapped event was emitted
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    histograms: BTreeMap<(usize, EventKind), Histogram>,
+}
+
+impl Histogram {
+    pub fn record(&mut self, latency: Duration) {
+        let micros = latency.as_micros() as u64;
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+
+        self.total / self.count as u32
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Upper bound of the bucket that contains the `p`th percentile
+    pub fn percentile(&self, p: u64) -> Duration {
+        let rank = (self.count * p).div_ceil(100).max(1);
+        let mut seen = 0;
+
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+
+            if seen >= rank {
+                let upper = Duration::from_micros((1 << bucket) - 1);
+                return upper.min(self.max);
+            }
+        }
+
+        self.max
+    }
+}
+
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} events, mean {:?}, p50 {:?}, p99 {:?}, max {:?}",
+            self.count,
+            self.mean(),
+            self.percentile(50),
+            self.percentile(99),
+            self.max
+        )
+    }
+}
+
+impl LatencyStats {
+    /// Records the latency of `input` from input device `device`, right after its mapped event was
+    /// emitted
+    pub fn record(&mut self, device: usize, input: &EventSummary) {
+        let (timestamp, kind) = match input {
+            EventSummary::Key(event, ..) => (event.timestamp(), EventKind::Key),
+            EventSummary::AbsoluteAxis(event, ..) => (event.timestamp(), EventKind::Axis),
+            _ => return,
+        };
+
+        // the clock might have been adjusted in between
+        let latency = SystemTime::now()
+            .duration_since(timestamp)
+            .unwrap_or_default();
+
+        self.histograms
+            .entry((device, kind))
+            .or_default()
+            .record(latency);
+    }
+
+    pub fn histogram(&self, device: usize, kind: EventKind) -> Option<&Histogram> {
+        self.histograms.get(&(device, kind))
+    }
+
+    /// One line per device and kind of event, using `names` for the input devices
+    pub fn report(&self, names: &[String]) -> String {
+        self.histograms
+            .iter()
+            .map(|(&(device, kind), histogram)| {
+                let name = names.get(device).map(String::as_str).unwrap_or("?");
+                let kind = match kind {
+                    EventKind::Key => "keys",
+                    EventKind::Axis => "axes",
+                };
+
+                format!("device {device} ({name}) {kind}: {histogram}\n")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use evdev::{AbsoluteAxisCode, EventType, InputEvent};
+
+    use super::{EventKind, Histogram, LatencyStats};
+
+    #[test]
+    fn percentiles() {
+        let mut histogram = Histogram::default();
+
+        for micros in 1..=100 {
+            histogram.record(Duration::from_micros(micros));
+        }
+
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.mean(), Duration::from_nanos(50_500));
+        // 50 lies in the bucket of 32..=63
+        assert_eq!(histogram.percentile(50), Duration::from_micros(63));
+        assert_eq!(histogram.percentile(99), Duration::from_micros(100));
+        assert_eq!(histogram.max(), Duration::from_micros(100));
+
+        assert_eq!(Histogram::default().percentile(50), Duration::ZERO);
+    }
+
+    #[test]
+    fn per_device_and_kind() {
+        let mut stats = LatencyStats::default();
+
+        let event = InputEvent::new_now(EventType::ABSOLUTE.0, AbsoluteAxisCode::ABS_X.0, 5);
+        stats.record(1, &event.destructure());
+
+        let axes = stats.histogram(1, EventKind::Axis).unwrap();
+        assert_eq!(axes.count(), 1);
+        assert!(axes.max() < Duration::from_secs(1));
+
+        assert!(stats.histogram(1, EventKind::Key).is_none());
+        assert!(stats.histogram(0, EventKind::Axis).is_none());
+
+        let report = stats.report(&["Left".to_string(), "Right".to_string()]);
+        assert!(report.starts_with("device 1 (Right) axes: 1 events"));
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    env,
+    os::unix::process::ExitStatusExt,
+    process::{Command, ExitStatus},
+    thread,
+};
+
+use anyhow::{anyhow, bail, Result};
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGUSR1, SIGUSR2},
+    iterator::Signals,
+};
+
+use vjoy_linux::capabilities::DeviceCapabilities;
+
+/// Signals a terminal sends to its whole foreground process group, the program receives them without
+/// forwarding
+const GROUP_SIGNALS: [libc::c_int; 3] = [SIGINT, SIGQUIT, SIGHUP];
+
+/// Signals that are usually sent to vjoy-linux alone, e.g. by `kill`
+const FORWARDED_SIGNALS: [libc::c_int; 3] = [SIGTERM, SIGUSR1, SIGUSR2];
+
+const SDL_IGNORE_VARIABLES: [&str; 2] = [
+    "SDL_GAMECONTROLLER_IGNORE_DEVICES",
+    "SDL_JOYSTICK_IGNORE_DEVICES",
+];
+
+/// Builds the command to start from either a shell-style `program` string or trailing arguments
+pub fn build_command(
+    program: Option<&str>,
+    trailing_args: &[String],
+    env: &[(String, String)],
+) -> Result<Option<Command>> {
+    let words = match (program, trailing_args) {
+        (None, []) => return Ok(None),
+        (Some(program), []) => {
+            shlex::split(program).ok_or(anyhow!("failed to parse program ({program})"))?
+        }
+        (None, args) => args.to_vec(),
+        (Some(_), _) => bail!("program can either be passed with -p or after --, not both"),
+    };
+
+    let (program, args) = words
+        .split_first()
+        .ok_or(anyhow!("program must not be empty"))?;
+
+    let mut command = Command::new(program);
+    command.args(args);
+    command.envs(env.iter().map(|(key, value)| (key, value)));
+
+    Ok(Some(command))
+}
+
+/// Environment that hides the physical devices from SDL, so games only see the virtual ones
+///
+/// Values already set in the environment are kept and extended.
+pub fn sdl_ignore_hints(capabilities: &[DeviceCapabilities]) -> Vec<(String, String)> {
+    ignore_hints(capabilities, |key| env::var(key).ok())
+}
+
+fn ignore_hints(
+    capabilities: &[DeviceCapabilities],
+    existing: impl Fn(&str) -> Option<String>,
+) -> Vec<(String, String)> {
+    let mut ids: Vec<String> = capabilities
+        .iter()
+        .map(|device| format!("0x{:04x}/0x{:04x}", device.vendor, device.product))
+        .collect();
+
+    ids.sort();
+    ids.dedup();
+
+    if ids.is_empty() {
+        return Vec::new();
+    }
+
+    SDL_IGNORE_VARIABLES
+        .iter()
+        .map(|&key| {
+            let value = match existing(key) {
+                Some(existing) if !existing.is_empty() => format!("{existing},{}", ids.join(",")),
+                _ => ids.join(","),
+            };
+
+            (key.to_string(), value)
+        })
+        .collect()
+}
+
+/// Runs the command to completion and passes termination signals on to it
+///
+/// vjoy-linux keeps running until the program has exited, so the virtual devices stay available
+/// while it shuts down. The program stays in the process group of vjoy-linux, so job control of the
+/// terminal (e.g. Ctrl+Z) applies to both.
+pub fn run_forwarding_signals(command: &mut Command) -> Result<ExitStatus> {
+    let mut signals = Signals::new(GROUP_SIGNALS.iter().chain(&FORWARDED_SIGNALS))?;
+    let handle = signals.handle();
+
+    let mut child = command
+        .spawn()
+        .map_err(|err| anyhow!("failed to start program: {err}"))?;
+    let pid = child.id() as libc::pid_t;
+
+    let forwarder = thread::spawn(move || {
+        for signal in signals.forever() {
+            // the program got the signals of the terminal itself, they would arrive twice
+            if FORWARDED_SIGNALS.contains(&signal) {
+                // SAFETY: kill only takes plain integers
+                unsafe { libc::kill(pid, signal) };
+            }
+        }
+    });
+
+    let status = child.wait();
+
+    handle.close();
+    forwarder
+        .join()
+        .map_err(|_| anyhow!("signal forwarding panicked"))?;
+
+    Ok(status?)
+}
+
+/// Exit code a shell would report for the status
+pub fn exit_code(status: ExitStatus) -> u8 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code as u8,
+        (None, Some(signal)) => 128 + signal as u8,
+        (None, None) => 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use vjoy_linux::capabilities::DeviceCapabilities;
+
+    use super::{build_command, exit_code, ignore_hints, run_forwarding_signals};
+
+    fn words(command: &Command) -> Vec<String> {
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|s| s.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn quoted_program() {
+        let command = build_command(Some(r#"game --name "Pilot One" 'a b'"#), &[], &[])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(words(&command), ["game", "--name", "Pilot One", "a b"]);
+    }
+
+    #[test]
+    fn trailing_args() {
+        let command = build_command(
+            None,
+            &["game".to_string(), "Pilot One".to_string()],
+            &[("SDL_DEBUG".to_string(), "1".to_string())],
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(words(&command), ["game", "Pilot One"]);
+        assert_eq!(command.get_envs().count(), 1);
+    }
+
+    #[test]
+    fn invalid_programs() {
+        assert!(build_command(None, &[], &[]).unwrap().is_none());
+        assert!(build_command(Some(""), &[], &[]).is_err());
+        assert!(build_command(Some("game 'unterminated"), &[], &[]).is_err());
+        assert!(build_command(Some("game"), &["game".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn exit_codes() {
+        let status = Command::new("sh").args(["-c", "exit 3"]).status().unwrap();
+        assert_eq!(exit_code(status), 3);
+
+        let status = Command::new("sh")
+            .args(["-c", "kill -9 $$"])
+            .status()
+            .unwrap();
+        assert_eq!(exit_code(status), 137);
+    }
+
+    #[test]
+    fn process_group() {
+        // SAFETY: getpgrp has no preconditions
+        let group = unsafe { libc::getpgrp() };
+
+        // the fifth field of the stat file is the process group
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            &format!(r#"read -r _ _ _ _ group _ < /proc/$$/stat; test "$group" = {group}"#),
+        ]);
+
+        assert!(run_forwarding_signals(&mut command).unwrap().success());
+    }
+
+    #[test]
+    fn sdl_hints() {
+        let device = |vendor, product| DeviceCapabilities {
+            name: "Joystick".to_string(),
+            vendor,
+            product,
+            keys: Vec::new(),
+            axes: Vec::new(),
+        };
+
+        let capabilities = [
+            device(0x044f, 0xb10a),
+            device(0x044f, 0xb10a),
+            device(0x231d, 0x0126),
+        ];
+
+        let hints = ignore_hints(&capabilities, |_| None);
+        assert_eq!(
+            hints,
+            [
+                (
+                    "SDL_GAMECONTROLLER_IGNORE_DEVICES".to_string(),
+                    "0x044f/0xb10a,0x231d/0x0126".to_string()
+                ),
+                (
+                    "SDL_JOYSTICK_IGNORE_DEVICES".to_string(),
+                    "0x044f/0xb10a,0x231d/0x0126".to_string()
+                ),
+            ]
+        );
+
+        let hints = ignore_hints(&capabilities[..1], |_| Some("0x28de/0x1142".to_string()));
+        assert_eq!(hints[0].1, "0x28de/0x1142,0x044f/0xb10a");
+
+        assert!(ignore_hints(&[], |_| None).is_empty());
+    }
+}
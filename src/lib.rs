@@ -0,0 +1,67 @@
+//! Maps one or more input devices onto virtual joysticks.
+//!
+//! - [`descriptor`] and [`mappings`] describe a mapping, [`format`] reads and writes it as RON,
+//!   JSON, TOML or YAML, [`compose`] applies descriptors on top of each other, [`validate`] checks it
+//! - [`mapper`] is the mapping engine, it turns input events into output events without any I/O,
+//!   [`calibration`] measures the range of worn axes and stretches their values before mapping,
+//!   [`filter`] smooths the values of jittering axes, [`debounce`] drops bouncing button presses,
+//!   [`pipeline`] runs input events through the debouncer, the filters and the mapper
+//! - [`backend`] abstracts where events are read from and written to, [`input_device`] and
+//!   [`output_device`] read from evdev devices and write to uinput devices, [`mock`] keeps
+//!   everything in memory
+//! - [`recording`] records the events of input devices and replays them, [`capture`] turns USB
+//!   captures of a T.16000M into recordings
+//! - [`runtime`] ties everything together in an event loop, controlled through [`control`]
+//!
+//! ```
+//! use vjoy_linux::{
+//!     evdev::{EventType, InputEvent, KeyCode},
+//!     Mapper, VJoyDescriptor,
+//! };
+//!
+//! let descriptor = VJoyDescriptor::parse(
+//!     r#"(
+//!         input_devices: ["Thrustmaster T.16000M"],
+//!         output_device: Combined("Joystick"),
+//!         key_mappings: { (0, BTN_TRIGGER): BTN_0 },
+//!         axis_mappings: {},
+//!     )"#,
+//! )?;
+//!
+//! let mapper = Mapper::new(descriptor);
+//!
+//! let input = InputEvent::new(EventType::KEY.0, KeyCode::BTN_TRIGGER.0, 1);
+//! let mapped = mapper.map(0, &input.destructure()).unwrap();
+//!
+//! assert_eq!(mapped.output, 0);
+//! assert_eq!(mapped.event.code(), KeyCode::BTN_0.0);
+//! # anyhow::Ok(())
+//! ```
+
+pub mod backend;
+pub mod calibration;
+pub mod capabilities;
+pub mod capture;
+pub mod compose;
+pub mod control;
+pub mod debounce;
+pub mod descriptor;
+pub mod filter;
+pub mod format;
+pub mod input_device;
+pub mod latency;
+pub mod mapper;
+pub mod mappings;
+pub mod mock;
+pub mod output_device;
+pub mod pipeline;
+pub mod recording;
+pub mod runtime;
+pub mod state;
+pub mod validate;
+mod watcher;
+
+pub use descriptor::VJoyDescriptor;
+pub use evdev;
+pub use mapper::{MappedEvent, Mapper};
+pub use mappings::{Axis, Button};
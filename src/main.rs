@@ -1,179 +1,451 @@
-mod descriptor;
-mod input_device;
-mod mappings;
-mod output_device;
+mod cli;
+mod launcher;
+mod monitor;
 
-use std::{fs::read_to_string, path::PathBuf, process::Command, sync::mpsc::Receiver, thread};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::Duration,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use clap::Parser;
-use descriptor::VJoyDescriptor;
-use evdev::{AbsoluteAxisCode, EventSummary, EventType, InputEvent, KeyCode};
-use input_device::InputDevice;
-use output_device::{Output, OutputDevice};
-use ron::from_str;
-
-/// Linux vjoy cli
-#[derive(Debug, Parser)]
-#[command(version = "0.1")]
-#[command(about, long_about = None)]
-struct Args {
-    /// VJoyDescriptor file
-    #[arg(short = 'f', long = "file")]
-    descriptor_file: Option<PathBuf>,
-
-    /// Enable debug output
-    #[arg(long = "debug", default_value_t = false)]
-    debug: bool,
-
-    /// Generator Input Devices (Comma separated)
-    #[arg(short = 'i', long = "input")]
-    input_devices: Option<String>,
-
-    /// Generator Output Device
-    #[arg(short = 'd', long = "device")]
-    output_device: Option<String>,
-
-    /// Generator File
-    #[arg(short = 'o', long = "output")]
-    generator_file: Option<PathBuf>,
-
-    /// Program that should be started
-    #[arg(short = 'p', long = "program")]
-    program: Option<String>,
+use cli::{
+    Args, CalibrateArgs, Command, ConvertArgs, CtlArgs, GenerateArgs, MigrateArgs, MonitorArgs,
+    RecordArgs, ReplayArgs, ResolveArgs, RunArgs, ValidateArgs,
+};
+use vjoy_linux::{
+    backend::{open_inputs, Backend, EvdevBackend},
+    calibration,
+    capabilities::DeviceCapabilities,
+    capture, compose,
+    control::{self, Response},
+    descriptor,
+    evdev::{AbsoluteAxisCode, EventType, KeyCode},
+    format::{self, Format},
+    mappings::EventCode,
+    mock::MockBackend,
+    output_device::Output,
+    recording::{self, Recording, Replay},
+    runtime::Runtime,
+    validate, Mapper, VJoyDescriptor,
+};
+
+fn main() -> Result<ExitCode> {
+    match Args::parse().into_command()? {
+        Command::Run(args) => return run(args),
+        Command::Generate(args) => generate(args)?,
+        Command::List => list()?,
+        Command::Validate(args) => validate(args)?,
+        Command::Monitor(args) => monitor(args)?,
+        Command::Ctl(args) => ctl(args)?,
+        Command::Record(args) => record(args)?,
+        Command::Replay(args) => replay(args)?,
+        Command::Migrate(args) => migrate(args)?,
+        Command::Resolve(args) => resolve(args)?,
+        Command::Convert(args) => convert(args)?,
+        Command::Calibrate(args) => calibrate(args)?,
+    }
+
+    Ok(ExitCode::SUCCESS)
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+fn run(args: RunArgs) -> Result<ExitCode> {
+    let descriptor = VJoyDescriptor::load(&args.descriptor_file)?;
+    descriptor.check()?;
 
-    if let Some(input_devices) = args.input_devices {
-        VJoyDescriptor::generate_from_cli(input_devices, args.output_device, args.generator_file)?;
+    let mut runtime = Runtime::new(
+        args.descriptor_file.clone(),
+        descriptor,
+        Box::new(EvdevBackend),
+        args.debug,
+    )?;
 
-        return Ok(());
+    let mut env = Vec::new();
+
+    if !args.no_sdl_hints {
+        env.extend(launcher::sdl_ignore_hints(runtime.capabilities()));
     }
 
-    let descriptor: VJoyDescriptor = from_str(
-        &read_to_string(
-            args.descriptor_file
-                .ok_or(anyhow!("missing descriptor file (-f <path to file>)"))?,
-        )
-        .map_err(|err| anyhow!("failed to open descriptor file: {err:?}"))?,
-    )
-    .map_err(|err| anyhow!("failed to parse descriptor file: {err:?}"))?;
+    // explicitly given variables take precedence
+    env.extend(args.env);
+
+    if args.latency {
+        runtime.measure_latency();
+    }
 
-    let (input_devices, receiver) =
-        InputDevice::find_unique_input_devices(&descriptor.input_devices)?;
+    if !args.no_watch {
+        runtime.watch_descriptor()?;
+    }
+
+    if !args.no_control {
+        let socket = args.socket.unwrap_or_else(control::default_socket_path);
+        runtime.listen(&socket)?;
+
+        if args.debug {
+            println!("control socket: {}", socket.display());
+        }
+    }
+
+    let command = launcher::build_command(args.program.as_deref(), &args.program_args, &env)?;
+
+    let Some(mut command) = command else {
+        runtime.shutdown_on_signals()?;
+        runtime.run()?;
+
+        return Ok(ExitCode::SUCCESS);
+    };
 
     if args.debug {
+        println!("command: {command:?}");
+    }
+
+    // games usually only look for controllers on startup
+    runtime.wait_for_output_devices(Duration::from_secs(args.device_timeout))?;
+
+    let handle = runtime.handle();
+    let runtime_thread = thread::spawn(move || runtime.run());
+
+    let status = launcher::run_forwarding_signals(&mut command);
+
+    // destroy the virtual devices before exiting
+    handle.shutdown()?;
+    runtime_thread
+        .join()
+        .map_err(|_| anyhow!("event loop panicked"))??;
+
+    let status = status?;
+
+    Ok(ExitCode::from(launcher::exit_code(status)))
+}
+
+fn generate(args: GenerateArgs) -> Result<()> {
+    VJoyDescriptor::generate_from_cli(
+        args.input_devices,
+        args.output_device,
+        args.output_file,
+        args.comments,
+    )
+}
+
+fn list() -> Result<()> {
+    for (path, device) in evdev::enumerate() {
+        let id = device.input_id();
+
         println!(
-            "input devices: {:#?}",
-            input_devices
-                .iter()
-                .map(|d| (d.path(), d.device().name()))
-                .collect::<Vec<_>>()
+            "{}: {} ({:04x}:{:04x})",
+            path.display(),
+            device.name().unwrap_or("<unnamed>"),
+            id.vendor(),
+            id.product()
         );
     }
 
-    let output = OutputDevice::new(&descriptor, &input_devices)?;
-
-    input_devices
-        .into_iter()
-        .for_each(|device| device.start_event_loop());
+    Ok(())
+}
 
-    match args.program {
-        Some(command) => {
-            thread::spawn(move || event_loop(output, receiver, descriptor, args.debug));
+fn validate(args: ValidateArgs) -> Result<()> {
+    let original = fs::read_to_string(&args.descriptor_file)
+        .map_err(|err| anyhow!("failed to open descriptor file: {err:?}"))?;
 
-            println!("command: {command}");
+    let format = Format::from_path(&args.descriptor_file);
+    let (source, located) = compose::checked_source(&args.descriptor_file)?;
 
-            let mut split = command.split_whitespace();
-            let mut command = Command::new(split.next().unwrap());
+    if !located {
+        println!(
+            "note: {} is checked as the output of `vjoy-linux resolve`, without locations",
+            args.descriptor_file.display()
+        );
+    }
 
-            while let Some(s) = split.next() {
-                command.arg(s);
+    let capabilities = if args.offline {
+        None
+    } else if let Some(snapshot) = &args.capabilities {
+        Some(DeviceCapabilities::load(snapshot)?)
+    } else if let Ok(descriptor) = VJoyDescriptor::parse(&source) {
+        match DeviceCapabilities::from_connected(&descriptor.input_devices) {
+            Ok(capabilities) => Some(capabilities),
+            Err(err) => {
+                println!("warning: {err} (skipping device checks, use --capabilities <snapshot>)");
+                None
             }
+        }
+    } else {
+        None
+    };
 
-            if args.debug {
-                println!("command: {command:?}");
-            }
+    if let (Some(path), Some(capabilities)) = (&args.save_capabilities, &capabilities) {
+        DeviceCapabilities::save(capabilities, path)?;
+    }
+
+    let mut diagnostics = validate::validate(&source, capabilities.as_deref());
 
-            let output = command.output();
-            println!("{output:#?}");
+    if !located {
+        for diagnostic in diagnostics.iter_mut() {
+            diagnostic.location = None;
         }
-        None => {
-            event_loop(output, receiver, descriptor, args.debug)?;
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+
+    for diagnostic in diagnostics.iter() {
+        println!("{}:{diagnostic}", args.descriptor_file.display());
+    }
+
+    if let Ok(version) = descriptor::schema_version(&original, format) {
+        if version < descriptor::CURRENT_VERSION {
+            println!(
+                "note: {} uses schema version {version}, `vjoy-linux migrate` updates it to {}",
+                args.descriptor_file.display(),
+                descriptor::CURRENT_VERSION
+            );
         }
     }
 
+    if errors > 0 {
+        bail!("{errors} error(s) in {}", args.descriptor_file.display());
+    }
+
+    println!("{} is valid", args.descriptor_file.display());
+
     Ok(())
 }
 
-fn event_loop(
-    mut output: Output,
-    receiver: Receiver<(usize, EventSummary)>,
-    descriptor: VJoyDescriptor,
-    debug: bool,
-) -> Result<()> {
-    loop {
-        let (index, input) = receiver.recv()?;
-
-        match input {
-            EventSummary::Key(_, key_code, state) => {
-                if debug {
-                    println!("device {index} sent key event {key_code:?} in state {state}");
-                }
+fn migrate(args: MigrateArgs) -> Result<()> {
+    let source = fs::read_to_string(&args.descriptor_file)
+        .map_err(|err| anyhow!("failed to open descriptor file: {err:?}"))?;
 
-                if let Some(button) = descriptor.key_mappings.get(&(index, key_code.into())) {
-                    if let Ok(code) = TryInto::<KeyCode>::try_into(*button) {
-                        match &mut output {
-                            Output::Combined(output_device) => {
-                                output_device.emit(&[InputEvent::new(
-                                    EventType::KEY.0,
-                                    code.0,
-                                    state,
-                                )])?;
-                            }
-                            Output::Passthrough(output_devices) => {
-                                output_devices[index].emit(&[InputEvent::new(
-                                    EventType::KEY.0,
-                                    code.0,
-                                    state,
-                                )])?;
-                            }
-                        }
-                    }
-                }
+    let format = Format::from_path(&args.descriptor_file);
+    let version = descriptor::schema_version(&source, format)
+        .map_err(|err| anyhow!("failed to parse descriptor file: {err}"))?;
+
+    let Some(migrated) = descriptor::migrate(&source, format)? else {
+        println!(
+            "{} already uses schema version {version}",
+            args.descriptor_file.display()
+        );
+
+        return Ok(());
+    };
+
+    let output_file = output_or_backup(&args.descriptor_file, args.output_file)?;
+
+    fs::write(&output_file, migrated)?;
+
+    println!(
+        "migrated {} from schema version {version} to {}",
+        output_file.display(),
+        descriptor::CURRENT_VERSION
+    );
+
+    Ok(())
+}
+
+/// `output_file` if given, otherwise `descriptor_file` itself after saving the original next to it
+/// as `.bak`
+fn output_or_backup(descriptor_file: &Path, output_file: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(output_file) = output_file {
+        return Ok(output_file);
+    }
+
+    let mut backup = descriptor_file.as_os_str().to_owned();
+    backup.push(".bak");
+
+    fs::copy(descriptor_file, &backup)?;
+    println!("saved the original as {}", backup.to_string_lossy());
+
+    Ok(descriptor_file.to_path_buf())
+}
+
+fn resolve(args: ResolveArgs) -> Result<()> {
+    let descriptor = VJoyDescriptor::load(&args.descriptor_file)?;
+
+    match args.output_file {
+        Some(output_file) => descriptor.save(&output_file)?,
+        None => println!(
+            "{}",
+            ron::ser::to_string_pretty(&descriptor, ron::ser::PrettyConfig::default())?
+        ),
+    }
+
+    Ok(())
+}
+
+fn convert(args: ConvertArgs) -> Result<()> {
+    let source = fs::read_to_string(&args.descriptor_file)
+        .map_err(|err| anyhow!("failed to open descriptor file: {err:?}"))?;
+
+    let from = Format::from_path(&args.descriptor_file);
+    let to = Format::from_path(&args.output_file);
+
+    fs::write(&args.output_file, format::convert(&source, from, to)?)?;
+
+    println!(
+        "converted {} ({from}) to {} ({to})",
+        args.descriptor_file.display(),
+        args.output_file.display()
+    );
+
+    Ok(())
+}
+
+fn calibrate(args: CalibrateArgs) -> Result<()> {
+    let descriptor = VJoyDescriptor::load(&args.descriptor_file)?;
+    let mut input_devices = open_inputs(&EvdevBackend, &descriptor.input_devices)?;
+
+    let measured = calibration::calibrate(&mut input_devices)?;
+
+    if measured.is_empty() {
+        bail!("no axis was moved, nothing to calibrate");
+    }
+
+    let mut axes: Vec<_> = measured.iter().collect();
+    axes.sort_by_key(|((index, axis), _)| (*index, axis.code()));
+
+    for ((index, axis), calibration) in axes {
+        println!(
+            "{index} ({}) {axis:?}: {} to {}, center {}",
+            descriptor.input_devices[*index], calibration.min, calibration.max, calibration.center
+        );
+    }
+
+    let calibrated = calibration::with_calibration(&args.descriptor_file, &descriptor, &measured)?;
+
+    let output_file = output_or_backup(&args.descriptor_file, args.output_file)?;
+
+    fs::write(&output_file, calibrated)?;
+    println!("wrote the calibration to {}", output_file.display());
+
+    Ok(())
+}
+
+fn monitor(args: MonitorArgs) -> Result<()> {
+    let descriptor = VJoyDescriptor::load(&args.descriptor_file)?;
+    descriptor.check()?;
+
+    let input_devices = open_inputs(&EvdevBackend, &descriptor.input_devices)?;
+
+    let capabilities = input_devices
+        .iter()
+        .map(|device| device.capabilities())
+        .collect::<Result<Vec<_>>>()?;
+
+    let output = if args.emit {
+        Some(Output::new(&descriptor, &capabilities, &EvdevBackend)?)
+    } else {
+        None
+    };
+
+    monitor::run_monitor(
+        output,
+        input_devices,
+        &capabilities,
+        Mapper::new(descriptor).with_capabilities(&capabilities),
+        args.latency,
+    )
+}
+
+fn ctl(args: CtlArgs) -> Result<()> {
+    let socket = args.socket.unwrap_or_else(control::default_socket_path);
+
+    match control::send(&socket, &args.command.try_into()?)? {
+        Response::Ok { data } => {
+            if !data.is_null() {
+                println!("{}", serde_json::to_string_pretty(&data)?);
             }
-            EventSummary::AbsoluteAxis(_, axis, value) => {
-                if debug {
-                    println!("device {index} sent axis {axis:?} with {value}");
-                }
 
-                if let Some(axis) = descriptor.axis_mappings.get(&(index, axis.into())) {
-                    if let Ok(axis) = TryInto::<AbsoluteAxisCode>::try_into(*axis) {
-                        match &mut output {
-                            Output::Combined(output_device) => {
-                                output_device.emit(&[InputEvent::new(
-                                    EventType::ABSOLUTE.0,
-                                    axis.0,
-                                    value,
-                                )])?;
-                            }
-                            Output::Passthrough(output_devices) => {
-                                output_devices[index].emit(&[InputEvent::new(
-                                    EventType::ABSOLUTE.0,
-                                    axis.0,
-                                    value,
-                                )])?;
-                            }
-                        }
-                    }
+            Ok(())
+        }
+        Response::Error { message } => bail!(message),
+    }
+}
+
+fn record(args: RecordArgs) -> Result<()> {
+    let input_devices = match (args.descriptor_file, args.input_devices) {
+        (Some(descriptor_file), _) => VJoyDescriptor::load(&descriptor_file)?.input_devices,
+        (None, Some(input_devices)) => input_devices.split(',').map(|s| s.to_string()).collect(),
+        (None, None) => bail!("either a descriptor file or input devices are required"),
+    };
+
+    let mut sources = open_inputs(&EvdevBackend, &input_devices)?;
+
+    println!("recording, press Ctrl+C to stop");
+    let recording = recording::record(&mut sources)?;
+    recording.save(&args.output_file)?;
+
+    println!(
+        "recorded {} events to {}",
+        recording.events.len(),
+        args.output_file.display()
+    );
+
+    Ok(())
+}
+
+fn replay(args: ReplayArgs) -> Result<()> {
+    let descriptor = VJoyDescriptor::load(&args.descriptor_file)?;
+    descriptor.check()?;
+
+    if args.speed <= 0.0 {
+        bail!("speed must be positive");
+    }
+
+    let recordings = args
+        .recordings
+        .iter()
+        .map(|path| match path.extension() {
+            Some(extension) if extension == "pcapng" => capture::load(path),
+            _ => Recording::load(path),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let replay = Replay::new(Recording::merge(recordings));
+    let mock = MockBackend::new();
+
+    let outputs: Box<dyn Backend> = if args.mock {
+        Box::new(mock.clone())
+    } else {
+        Box::new(EvdevBackend)
+    };
+
+    let mut runtime = Runtime::new(
+        args.descriptor_file,
+        descriptor,
+        Box::new(replay.backend(outputs)),
+        args.debug,
+    )?;
+
+    if !args.mock {
+        runtime.wait_for_output_devices(Duration::from_secs(5))?;
+    }
+
+    let handle = runtime.handle();
+    let runtime_thread = thread::spawn(move || runtime.run());
+
+    replay.play(args.speed);
+
+    handle.shutdown()?;
+    runtime_thread
+        .join()
+        .map_err(|_| anyhow!("event loop panicked"))??;
+
+    for output in mock.outputs() {
+        println!("{}:", output.name());
+
+        for event in output.events() {
+            match event.event_type() {
+                EventType::KEY => println!("  {:?} {}", KeyCode(event.code()), event.value()),
+                EventType::ABSOLUTE => {
+                    println!("  {:?} {}", AbsoluteAxisCode(event.code()), event.value())
                 }
+                _ => (),
             }
-
-            _ => (),
         }
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
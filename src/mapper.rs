@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use evdev::{AbsoluteAxisCode, EventSummary, EventType, InputEvent, KeyCode};
+
+use crate::{
+    capabilities::{AxisRange, DeviceCapabilities},
+    descriptor::VJoyDescriptor,
+    mappings::Axis,
+};
+
+/// Event after it went through the descriptor mappings
+#[derive(Debug, Clone, Copy)]
+pub struct MappedEvent {
+    /// Index of the output device (always 0 in combined mode)
+    pub output: usize,
+    pub event: InputEvent,
+}
+
+pub struct Mapper {
+    descriptor: VJoyDescriptor,
+    /// Advertised range of the input axes, calibrated values are stretched to it
+    ranges: HashMap<(usize, Axis), AxisRange>,
+}
+
+impl Mapper {
+    pub fn new(descriptor: VJoyDescriptor) -> Self {
+        Self {
+            descriptor,
+            ranges: HashMap::new(),
+        }
+    }
+
+    /// Takes the axis ranges of the input devices, the calibration of the descriptor is only
+    /// applied to axes whose range is known
+    pub fn with_capabilities(mut self, capabilities: &[DeviceCapabilities]) -> Self {
+        self.ranges = capabilities
+            .iter()
+            .enumerate()
+            .flat_map(|(index, device)| {
+                device
+                    .axes
+                    .iter()
+                    .map(move |&(axis, range)| ((index, axis), range))
+            })
+            .collect();
+
+        self
+    }
+
+    pub fn descriptor(&self) -> &VJoyDescriptor {
+        &self.descriptor
+    }
+
+    /// Configured neutral values of the source axes, mapped to their output axes
+    pub fn neutral_axes(&self) -> Vec<MappedEvent> {
+        self.descriptor
+            .neutral_axes
+            .iter()
+            .filter_map(|(&(index, axis), &value)| {
+                let axis = TryInto::<AbsoluteAxisCode>::try_into(axis).ok()?;
+                let input = InputEvent::new(EventType::ABSOLUTE.0, axis.0, value);
+
+                self.map(index, &input.destructure())
+            })
+            .collect()
+    }
+
+    pub fn map(&self, index: usize, input: &EventSummary) -> Option<MappedEvent> {
+        match *input {
+            EventSummary::AbsoluteAxis(_, axis, value) => {
+                let value = self.calibrate(index, axis.into(), value);
+                let input = InputEvent::new(EventType::ABSOLUTE.0, axis.0, value);
+
+                self.map_calibrated(index, &input.destructure())
+            }
+            _ => self.map_calibrated(index, input),
+        }
+    }
+
+    /// Like [`Mapper::map`] for axis values that are calibrated already, see [`Mapper::calibrate`]
+    pub fn map_calibrated(&self, index: usize, input: &EventSummary) -> Option<MappedEvent> {
+        let output = if self.descriptor.output_device.is_combined() {
+            0
+        } else {
+            index
+        };
+
+        match *input {
+            EventSummary::Key(_, key_code, state) => {
+                let button = self
+                    .descriptor
+                    .key_mappings
+                    .get(&(index, key_code.into()))?;
+                let code = TryInto::<KeyCode>::try_into(*button).ok()?;
+
+                Some(MappedEvent {
+                    output,
+                    event: InputEvent::new(EventType::KEY.0, code.0, state),
+                })
+            }
+            EventSummary::AbsoluteAxis(_, axis, value) => {
+                let axis = self.descriptor.axis_mappings.get(&(index, axis.into()))?;
+                let axis = TryInto::<AbsoluteAxisCode>::try_into(*axis).ok()?;
+
+                Some(MappedEvent {
+                    output,
+                    event: InputEvent::new(EventType::ABSOLUTE.0, axis.0, value),
+                })
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Raw value of a source axis with its calibration applied
+    pub fn calibrate(&self, index: usize, axis: Axis, value: i32) -> i32 {
+        let calibration = self.descriptor.calibration.get(&(index, axis));
+        let range = self.ranges.get(&(index, axis));
+
+        match (calibration, range) {
+            (Some(calibration), Some(range)) => calibration.apply(value, range),
+            _ => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use evdev::{AbsoluteAxisCode, EventSummary, EventType, InputEvent, KeyCode};
+
+    use crate::{
+        calibration::Calibration,
+        capabilities::{AxisRange, DeviceCapabilities},
+        descriptor::{OutputType, VJoyDescriptor},
+        mappings::{Axis, Button},
+    };
+
+    use super::Mapper;
+
+    fn mapper(output_device: OutputType) -> Mapper {
+        Mapper::new(VJoyDescriptor {
+            input_devices: vec!["Left".to_string(), "Right".to_string()],
+            output_device,
+            key_mappings: HashMap::from([
+                ((0, Button::BTN_TRIGGER), Button::BTN_0),
+                ((1, Button::BTN_TRIGGER), Button::BTN_1),
+                ((1, Button::BTN_THUMB), Button::Stub),
+            ]),
+            axis_mappings: HashMap::from([((1, Axis::ABS_X), Axis::ABS_RX)]),
+            neutral_axes: HashMap::from([((1, Axis::ABS_X), 0), ((0, Axis::ABS_Y), 0)]),
+            ..VJoyDescriptor::default()
+        })
+    }
+
+    fn key(code: KeyCode, value: i32) -> EventSummary {
+        InputEvent::new(EventType::KEY.0, code.0, value).destructure()
+    }
+
+    #[test]
+    fn combined() {
+        let mapper = mapper(OutputType::Combined("Combined".to_string()));
+
+        let mapped = mapper.map(1, &key(KeyCode::BTN_TRIGGER, 1)).unwrap();
+        assert_eq!(mapped.output, 0);
+        assert_eq!(mapped.event.code(), KeyCode::BTN_1.0);
+        assert_eq!(mapped.event.value(), 1);
+
+        let axis = InputEvent::new(EventType::ABSOLUTE.0, AbsoluteAxisCode::ABS_X.0, 42);
+        let mapped = mapper.map(1, &axis.destructure()).unwrap();
+        assert_eq!(mapped.event.code(), AbsoluteAxisCode::ABS_RX.0);
+        assert_eq!(mapped.event.value(), 42);
+
+        assert!(mapper.map(1, &key(KeyCode::BTN_THUMB, 1)).is_none());
+        assert!(mapper.map(0, &key(KeyCode::BTN_THUMB, 1)).is_none());
+    }
+
+    #[test]
+    fn passthrough() {
+        let mapper = mapper(OutputType::Passthrough(vec![
+            "One".to_string(),
+            "Two".to_string(),
+        ]));
+
+        assert_eq!(
+            mapper.map(1, &key(KeyCode::BTN_TRIGGER, 1)).unwrap().output,
+            1
+        );
+        assert_eq!(
+            mapper.map(0, &key(KeyCode::BTN_TRIGGER, 1)).unwrap().output,
+            0
+        );
+    }
+
+    #[test]
+    fn neutral_axes() {
+        let mapper = mapper(OutputType::Passthrough(vec![
+            "One".to_string(),
+            "Two".to_string(),
+        ]));
+
+        // (0, ABS_Y) is not mapped
+        let neutral = mapper.neutral_axes();
+        assert_eq!(neutral.len(), 1);
+        assert_eq!(neutral[0].output, 1);
+        assert_eq!(neutral[0].event.code(), AbsoluteAxisCode::ABS_RX.0);
+        assert_eq!(neutral[0].event.value(), 0);
+    }
+
+    #[test]
+    fn calibrated() {
+        let mut mapper = mapper(OutputType::Combined("Combined".to_string()));
+        mapper.descriptor.calibration.insert(
+            (1, Axis::ABS_X),
+            Calibration {
+                min: 200,
+                center: 1000,
+                max: 1800,
+            },
+        );
+
+        let axis = |value| {
+            InputEvent::new(EventType::ABSOLUTE.0, AbsoluteAxisCode::ABS_X.0, value).destructure()
+        };
+
+        // without the range of the axis the raw value is mapped
+        assert_eq!(mapper.map(1, &axis(200)).unwrap().event.value(), 200);
+
+        let device = |name: &str| DeviceCapabilities {
+            name: name.to_string(),
+            vendor: 0,
+            product: 0,
+            keys: Vec::new(),
+            axes: vec![(
+                Axis::ABS_X,
+                AxisRange {
+                    minimum: 0,
+                    maximum: 2047,
+                    fuzz: 0,
+                    flat: 0,
+                    resolution: 0,
+                },
+            )],
+        };
+
+        let mapper = mapper.with_capabilities(&[device("Left"), device("Right")]);
+        assert_eq!(mapper.map(1, &axis(200)).unwrap().event.value(), 0);
+        assert_eq!(mapper.map(1, &axis(1000)).unwrap().event.value(), 1023);
+        assert_eq!(mapper.map(1, &axis(1800)).unwrap().event.value(), 2047);
+    }
+}
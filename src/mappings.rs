@@ -1,23 +1,34 @@
 use evdev::{AbsoluteAxisCode, KeyCode};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Mapping codes that correspond to an evdev event code
+pub trait EventCode {
+    /// The evdev code, `None` for `Stub`
+    fn code(&self) -> Option<u16>;
+
+    /// Every way the code can be written in a descriptor, this one first, e.g. `KEY_DIRECTION`,
+    /// `KEY_ROTATE_DISPLAY` and `Unknown(153)`
+    fn spellings(&self) -> Vec<Self>
+    where
+        Self: Sized;
+}
 
 macro_rules! create_mapping {
-    ( $name:ident, $mapper:ident, [ $( $btn:ident $(,)? )+ ] $(, $unknown:ident )? ) => {
+    ( $name:ident, $mapper:ident, [ $( $btn:ident $(,)? )+ ], $unknown:ident ) => {
         #[allow(non_camel_case_types)]
-        #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+        #[derive(Debug, Serialize, PartialEq, Eq, Hash, Clone, Copy)]
         pub enum $name {
             $(
                 $btn,
             )+
 
-            $(
-                $unknown(u16),
-            )?
+            $unknown(u16),
 
             Stub
         }
 
-        #[allow(unreachable_code)]
+        // names that share a code map to the first one
+        #[allow(unreachable_patterns)]
         impl From<$mapper> for $name {
             fn from(mapping: $mapper) -> Self {
                 match mapping {
@@ -25,16 +36,74 @@ macro_rules! create_mapping {
                         $mapper::$btn => Self::$btn,
                     )+
 
-                    _ => {
-                        println!("mapping ({mapping:?}) missing counterpart");
+                    // codes without a name
+                    _ => Self::$unknown(mapping.0),
+                }
+            }
+        }
+
+        impl $name {
+            /// The name that events of the same code are mapped to, e.g. `BTN_DEAD` for
+            /// `Unknown(303)`
+            pub fn normalized(self) -> Self {
+                match TryInto::<$mapper>::try_into(self) {
+                    Ok(mapping) => Self::from(mapping),
+                    Err(_) => self,
+                }
+            }
+        }
+
+        // numeric codes and alternative names are read as the name that events are mapped to, so
+        // that they compare equal
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                #[allow(non_camel_case_types)]
+                #[derive(Deserialize)]
+                enum Written {
+                    $(
+                        $btn,
+                    )+
 
-                        $(
-                            return Self::$unknown(mapping.code());
-                        )?
+                    $unknown(u16),
 
-                        panic!()
-                    }
+                    Stub
                 }
+
+                let written = match Written::deserialize(deserializer)? {
+                    $(
+                        Written::$btn => Self::$btn,
+                    )+
+
+                    Written::$unknown(code) => Self::$unknown(code),
+
+                    Written::Stub => Self::Stub,
+                };
+
+                Ok(written.normalized())
+            }
+        }
+
+        impl EventCode for $name {
+            fn code(&self) -> Option<u16> {
+                TryInto::<$mapper>::try_into(*self).ok().map(|code| code.0)
+            }
+
+            fn spellings(&self) -> Vec<Self> {
+                let mut spellings = vec![*self];
+
+                let Some(code) = self.code() else {
+                    return spellings;
+                };
+
+                let named = [$( Self::$btn, )+];
+                spellings.extend(
+                    named
+                        .into_iter()
+                        .chain([Self::$unknown(code)])
+                        .filter(|other| other != self && other.code() == Some(code)),
+                );
+
+                spellings
             }
         }
 
@@ -47,9 +116,7 @@ macro_rules! create_mapping {
                         Self::$btn => Ok($mapper::$btn),
                     )+
 
-                    $(
-                        Self::$unknown(i) => Ok($mapper::new(i)),
-                    )?
+                    Self::$unknown(i) => Ok($mapper(i)),
 
                     Self::Stub => Err(anyhow::anyhow!("Stub can't be matched")),
                 }
@@ -62,6 +129,250 @@ create_mapping!(
     Button,
     KeyCode,
     [
+        KEY_RESERVED,
+        KEY_ESC,
+        KEY_1,
+        KEY_2,
+        KEY_3,
+        KEY_4,
+        KEY_5,
+        KEY_6,
+        KEY_7,
+        KEY_8,
+        KEY_9,
+        KEY_0,
+        KEY_MINUS,
+        KEY_EQUAL,
+        KEY_BACKSPACE,
+        KEY_TAB,
+        KEY_Q,
+        KEY_W,
+        KEY_E,
+        KEY_R,
+        KEY_T,
+        KEY_Y,
+        KEY_U,
+        KEY_I,
+        KEY_O,
+        KEY_P,
+        KEY_LEFTBRACE,
+        KEY_RIGHTBRACE,
+        KEY_ENTER,
+        KEY_LEFTCTRL,
+        KEY_A,
+        KEY_S,
+        KEY_D,
+        KEY_F,
+        KEY_G,
+        KEY_H,
+        KEY_J,
+        KEY_K,
+        KEY_L,
+        KEY_SEMICOLON,
+        KEY_APOSTROPHE,
+        KEY_GRAVE,
+        KEY_LEFTSHIFT,
+        KEY_BACKSLASH,
+        KEY_Z,
+        KEY_X,
+        KEY_C,
+        KEY_V,
+        KEY_B,
+        KEY_N,
+        KEY_M,
+        KEY_COMMA,
+        KEY_DOT,
+        KEY_SLASH,
+        KEY_RIGHTSHIFT,
+        KEY_KPASTERISK,
+        KEY_LEFTALT,
+        KEY_SPACE,
+        KEY_CAPSLOCK,
+        KEY_F1,
+        KEY_F2,
+        KEY_F3,
+        KEY_F4,
+        KEY_F5,
+        KEY_F6,
+        KEY_F7,
+        KEY_F8,
+        KEY_F9,
+        KEY_F10,
+        KEY_NUMLOCK,
+        KEY_SCROLLLOCK,
+        KEY_KP7,
+        KEY_KP8,
+        KEY_KP9,
+        KEY_KPMINUS,
+        KEY_KP4,
+        KEY_KP5,
+        KEY_KP6,
+        KEY_KPPLUS,
+        KEY_KP1,
+        KEY_KP2,
+        KEY_KP3,
+        KEY_KP0,
+        KEY_KPDOT,
+        KEY_ZENKAKUHANKAKU,
+        KEY_102ND,
+        KEY_F11,
+        KEY_F12,
+        KEY_RO,
+        KEY_KATAKANA,
+        KEY_HIRAGANA,
+        KEY_HENKAN,
+        KEY_KATAKANAHIRAGANA,
+        KEY_MUHENKAN,
+        KEY_KPJPCOMMA,
+        KEY_KPENTER,
+        KEY_RIGHTCTRL,
+        KEY_KPSLASH,
+        KEY_SYSRQ,
+        KEY_RIGHTALT,
+        KEY_LINEFEED,
+        KEY_HOME,
+        KEY_UP,
+        KEY_PAGEUP,
+        KEY_LEFT,
+        KEY_RIGHT,
+        KEY_END,
+        KEY_DOWN,
+        KEY_PAGEDOWN,
+        KEY_INSERT,
+        KEY_DELETE,
+        KEY_MACRO,
+        KEY_MUTE,
+        KEY_VOLUMEDOWN,
+        KEY_VOLUMEUP,
+        KEY_POWER,
+        KEY_KPEQUAL,
+        KEY_KPPLUSMINUS,
+        KEY_PAUSE,
+        KEY_SCALE,
+        KEY_KPCOMMA,
+        KEY_HANGEUL,
+        KEY_HANJA,
+        KEY_YEN,
+        KEY_LEFTMETA,
+        KEY_RIGHTMETA,
+        KEY_COMPOSE,
+        KEY_STOP,
+        KEY_AGAIN,
+        KEY_PROPS,
+        KEY_UNDO,
+        KEY_FRONT,
+        KEY_COPY,
+        KEY_OPEN,
+        KEY_PASTE,
+        KEY_FIND,
+        KEY_CUT,
+        KEY_HELP,
+        KEY_MENU,
+        KEY_CALC,
+        KEY_SETUP,
+        KEY_SLEEP,
+        KEY_WAKEUP,
+        KEY_FILE,
+        KEY_SENDFILE,
+        KEY_DELETEFILE,
+        KEY_XFER,
+        KEY_PROG1,
+        KEY_PROG2,
+        KEY_WWW,
+        KEY_MSDOS,
+        KEY_COFFEE,
+        KEY_DIRECTION,
+        KEY_ROTATE_DISPLAY,
+        KEY_CYCLEWINDOWS,
+        KEY_MAIL,
+        KEY_BOOKMARKS,
+        KEY_COMPUTER,
+        KEY_BACK,
+        KEY_FORWARD,
+        KEY_CLOSECD,
+        KEY_EJECTCD,
+        KEY_EJECTCLOSECD,
+        KEY_NEXTSONG,
+        KEY_PLAYPAUSE,
+        KEY_PREVIOUSSONG,
+        KEY_STOPCD,
+        KEY_RECORD,
+        KEY_REWIND,
+        KEY_PHONE,
+        KEY_ISO,
+        KEY_CONFIG,
+        KEY_HOMEPAGE,
+        KEY_REFRESH,
+        KEY_EXIT,
+        KEY_MOVE,
+        KEY_EDIT,
+        KEY_SCROLLUP,
+        KEY_SCROLLDOWN,
+        KEY_KPLEFTPAREN,
+        KEY_KPRIGHTPAREN,
+        KEY_NEW,
+        KEY_REDO,
+        KEY_F13,
+        KEY_F14,
+        KEY_F15,
+        KEY_F16,
+        KEY_F17,
+        KEY_F18,
+        KEY_F19,
+        KEY_F20,
+        KEY_F21,
+        KEY_F22,
+        KEY_F23,
+        KEY_F24,
+        KEY_PLAYCD,
+        KEY_PAUSECD,
+        KEY_PROG3,
+        KEY_PROG4,
+        KEY_DASHBOARD,
+        KEY_SUSPEND,
+        KEY_CLOSE,
+        KEY_PLAY,
+        KEY_FASTFORWARD,
+        KEY_BASSBOOST,
+        KEY_PRINT,
+        KEY_HP,
+        KEY_CAMERA,
+        KEY_SOUND,
+        KEY_QUESTION,
+        KEY_EMAIL,
+        KEY_CHAT,
+        KEY_SEARCH,
+        KEY_CONNECT,
+        KEY_FINANCE,
+        KEY_SPORT,
+        KEY_SHOP,
+        KEY_ALTERASE,
+        KEY_CANCEL,
+        KEY_BRIGHTNESSDOWN,
+        KEY_BRIGHTNESSUP,
+        KEY_MEDIA,
+        KEY_SWITCHVIDEOMODE,
+        KEY_KBDILLUMTOGGLE,
+        KEY_KBDILLUMDOWN,
+        KEY_KBDILLUMUP,
+        KEY_SEND,
+        KEY_REPLY,
+        KEY_FORWARDMAIL,
+        KEY_SAVE,
+        KEY_DOCUMENTS,
+        KEY_BATTERY,
+        KEY_BLUETOOTH,
+        KEY_WLAN,
+        KEY_UWB,
+        KEY_UNKNOWN,
+        KEY_VIDEO_NEXT,
+        KEY_VIDEO_PREV,
+        KEY_BRIGHTNESS_CYCLE,
+        KEY_BRIGHTNESS_AUTO,
+        KEY_DISPLAY_OFF,
+        KEY_WWAN,
+        KEY_RFKILL,
+        KEY_MICMUTE,
         BTN_0,
         BTN_1,
         BTN_2,
@@ -75,12 +386,16 @@ create_mapping!(
         BTN_LEFT,
         BTN_RIGHT,
         BTN_MIDDLE,
-        BTN_TOP,
-        BTN_TOP2,
         BTN_SIDE,
+        BTN_EXTRA,
+        BTN_FORWARD,
+        BTN_BACK,
+        BTN_TASK,
         BTN_TRIGGER,
         BTN_THUMB,
         BTN_THUMB2,
+        BTN_TOP,
+        BTN_TOP2,
         BTN_PINKIE,
         BTN_BASE,
         BTN_BASE2,
@@ -89,6 +404,281 @@ create_mapping!(
         BTN_BASE5,
         BTN_BASE6,
         BTN_DEAD,
+        BTN_SOUTH,
+        BTN_EAST,
+        BTN_C,
+        BTN_NORTH,
+        BTN_WEST,
+        BTN_Z,
+        BTN_TL,
+        BTN_TR,
+        BTN_TL2,
+        BTN_TR2,
+        BTN_SELECT,
+        BTN_START,
+        BTN_MODE,
+        BTN_THUMBL,
+        BTN_THUMBR,
+        BTN_TOOL_PEN,
+        BTN_TOOL_RUBBER,
+        BTN_TOOL_BRUSH,
+        BTN_TOOL_PENCIL,
+        BTN_TOOL_AIRBRUSH,
+        BTN_TOOL_FINGER,
+        BTN_TOOL_MOUSE,
+        BTN_TOOL_LENS,
+        BTN_TOOL_QUINTTAP,
+        BTN_TOUCH,
+        BTN_STYLUS,
+        BTN_STYLUS2,
+        BTN_TOOL_DOUBLETAP,
+        BTN_TOOL_TRIPLETAP,
+        BTN_TOOL_QUADTAP,
+        BTN_GEAR_DOWN,
+        BTN_GEAR_UP,
+        KEY_OK,
+        KEY_SELECT,
+        KEY_GOTO,
+        KEY_CLEAR,
+        KEY_POWER2,
+        KEY_OPTION,
+        KEY_INFO,
+        KEY_TIME,
+        KEY_VENDOR,
+        KEY_ARCHIVE,
+        KEY_PROGRAM,
+        KEY_CHANNEL,
+        KEY_FAVORITES,
+        KEY_EPG,
+        KEY_PVR,
+        KEY_MHP,
+        KEY_LANGUAGE,
+        KEY_TITLE,
+        KEY_SUBTITLE,
+        KEY_ANGLE,
+        KEY_ZOOM,
+        KEY_FULL_SCREEN,
+        KEY_MODE,
+        KEY_KEYBOARD,
+        KEY_SCREEN,
+        KEY_PC,
+        KEY_TV,
+        KEY_TV2,
+        KEY_VCR,
+        KEY_VCR2,
+        KEY_SAT,
+        KEY_SAT2,
+        KEY_CD,
+        KEY_TAPE,
+        KEY_RADIO,
+        KEY_TUNER,
+        KEY_PLAYER,
+        KEY_TEXT,
+        KEY_DVD,
+        KEY_AUX,
+        KEY_MP3,
+        KEY_AUDIO,
+        KEY_VIDEO,
+        KEY_DIRECTORY,
+        KEY_LIST,
+        KEY_MEMO,
+        KEY_CALENDAR,
+        KEY_RED,
+        KEY_GREEN,
+        KEY_YELLOW,
+        KEY_BLUE,
+        KEY_CHANNELUP,
+        KEY_CHANNELDOWN,
+        KEY_FIRST,
+        KEY_LAST,
+        KEY_AB,
+        KEY_NEXT,
+        KEY_RESTART,
+        KEY_SLOW,
+        KEY_SHUFFLE,
+        KEY_BREAK,
+        KEY_PREVIOUS,
+        KEY_DIGITS,
+        KEY_TEEN,
+        KEY_TWEN,
+        KEY_VIDEOPHONE,
+        KEY_GAMES,
+        KEY_ZOOMIN,
+        KEY_ZOOMOUT,
+        KEY_ZOOMRESET,
+        KEY_WORDPROCESSOR,
+        KEY_EDITOR,
+        KEY_SPREADSHEET,
+        KEY_GRAPHICSEDITOR,
+        KEY_PRESENTATION,
+        KEY_DATABASE,
+        KEY_NEWS,
+        KEY_VOICEMAIL,
+        KEY_ADDRESSBOOK,
+        KEY_MESSENGER,
+        KEY_DISPLAYTOGGLE,
+        KEY_SPELLCHECK,
+        KEY_LOGOFF,
+        KEY_DOLLAR,
+        KEY_EURO,
+        KEY_FRAMEBACK,
+        KEY_FRAMEFORWARD,
+        KEY_CONTEXT_MENU,
+        KEY_MEDIA_REPEAT,
+        KEY_10CHANNELSUP,
+        KEY_10CHANNELSDOWN,
+        KEY_IMAGES,
+        KEY_PICKUP_PHONE,
+        KEY_HANGUP_PHONE,
+        KEY_DEL_EOL,
+        KEY_DEL_EOS,
+        KEY_INS_LINE,
+        KEY_DEL_LINE,
+        KEY_FN,
+        KEY_FN_ESC,
+        KEY_FN_F1,
+        KEY_FN_F2,
+        KEY_FN_F3,
+        KEY_FN_F4,
+        KEY_FN_F5,
+        KEY_FN_F6,
+        KEY_FN_F7,
+        KEY_FN_F8,
+        KEY_FN_F9,
+        KEY_FN_F10,
+        KEY_FN_F11,
+        KEY_FN_F12,
+        KEY_FN_1,
+        KEY_FN_2,
+        KEY_FN_D,
+        KEY_FN_E,
+        KEY_FN_F,
+        KEY_FN_S,
+        KEY_FN_B,
+        KEY_BRL_DOT1,
+        KEY_BRL_DOT2,
+        KEY_BRL_DOT3,
+        KEY_BRL_DOT4,
+        KEY_BRL_DOT5,
+        KEY_BRL_DOT6,
+        KEY_BRL_DOT7,
+        KEY_BRL_DOT8,
+        KEY_BRL_DOT9,
+        KEY_BRL_DOT10,
+        KEY_NUMERIC_0,
+        KEY_NUMERIC_1,
+        KEY_NUMERIC_2,
+        KEY_NUMERIC_3,
+        KEY_NUMERIC_4,
+        KEY_NUMERIC_5,
+        KEY_NUMERIC_6,
+        KEY_NUMERIC_7,
+        KEY_NUMERIC_8,
+        KEY_NUMERIC_9,
+        KEY_NUMERIC_STAR,
+        KEY_NUMERIC_POUND,
+        KEY_NUMERIC_A,
+        KEY_NUMERIC_B,
+        KEY_NUMERIC_C,
+        KEY_NUMERIC_D,
+        KEY_CAMERA_FOCUS,
+        KEY_WPS_BUTTON,
+        KEY_TOUCHPAD_TOGGLE,
+        KEY_TOUCHPAD_ON,
+        KEY_TOUCHPAD_OFF,
+        KEY_CAMERA_ZOOMIN,
+        KEY_CAMERA_ZOOMOUT,
+        KEY_CAMERA_UP,
+        KEY_CAMERA_DOWN,
+        KEY_CAMERA_LEFT,
+        KEY_CAMERA_RIGHT,
+        KEY_ATTENDANT_ON,
+        KEY_ATTENDANT_OFF,
+        KEY_ATTENDANT_TOGGLE,
+        KEY_LIGHTS_TOGGLE,
+        BTN_DPAD_UP,
+        BTN_DPAD_DOWN,
+        BTN_DPAD_LEFT,
+        BTN_DPAD_RIGHT,
+        KEY_ALS_TOGGLE,
+        KEY_BUTTONCONFIG,
+        KEY_TASKMANAGER,
+        KEY_JOURNAL,
+        KEY_CONTROLPANEL,
+        KEY_APPSELECT,
+        KEY_SCREENSAVER,
+        KEY_VOICECOMMAND,
+        KEY_ASSISTANT,
+        KEY_KBD_LAYOUT_NEXT,
+        KEY_BRIGHTNESS_MIN,
+        KEY_BRIGHTNESS_MAX,
+        KEY_KBDINPUTASSIST_PREV,
+        KEY_KBDINPUTASSIST_NEXT,
+        KEY_KBDINPUTASSIST_PREVGROUP,
+        KEY_KBDINPUTASSIST_NEXTGROUP,
+        KEY_KBDINPUTASSIST_ACCEPT,
+        KEY_KBDINPUTASSIST_CANCEL,
+        KEY_RIGHT_UP,
+        KEY_RIGHT_DOWN,
+        KEY_LEFT_UP,
+        KEY_LEFT_DOWN,
+        KEY_ROOT_MENU,
+        KEY_MEDIA_TOP_MENU,
+        KEY_NUMERIC_11,
+        KEY_NUMERIC_12,
+        KEY_AUDIO_DESC,
+        KEY_3D_MODE,
+        KEY_NEXT_FAVORITE,
+        KEY_STOP_RECORD,
+        KEY_PAUSE_RECORD,
+        KEY_VOD,
+        KEY_UNMUTE,
+        KEY_FASTREVERSE,
+        KEY_SLOWREVERSE,
+        KEY_DATA,
+        KEY_ONSCREEN_KEYBOARD,
+        KEY_PRIVACY_SCREEN_TOGGLE,
+        KEY_SELECTIVE_SCREENSHOT,
+        BTN_TRIGGER_HAPPY1,
+        BTN_TRIGGER_HAPPY2,
+        BTN_TRIGGER_HAPPY3,
+        BTN_TRIGGER_HAPPY4,
+        BTN_TRIGGER_HAPPY5,
+        BTN_TRIGGER_HAPPY6,
+        BTN_TRIGGER_HAPPY7,
+        BTN_TRIGGER_HAPPY8,
+        BTN_TRIGGER_HAPPY9,
+        BTN_TRIGGER_HAPPY10,
+        BTN_TRIGGER_HAPPY11,
+        BTN_TRIGGER_HAPPY12,
+        BTN_TRIGGER_HAPPY13,
+        BTN_TRIGGER_HAPPY14,
+        BTN_TRIGGER_HAPPY15,
+        BTN_TRIGGER_HAPPY16,
+        BTN_TRIGGER_HAPPY17,
+        BTN_TRIGGER_HAPPY18,
+        BTN_TRIGGER_HAPPY19,
+        BTN_TRIGGER_HAPPY20,
+        BTN_TRIGGER_HAPPY21,
+        BTN_TRIGGER_HAPPY22,
+        BTN_TRIGGER_HAPPY23,
+        BTN_TRIGGER_HAPPY24,
+        BTN_TRIGGER_HAPPY25,
+        BTN_TRIGGER_HAPPY26,
+        BTN_TRIGGER_HAPPY27,
+        BTN_TRIGGER_HAPPY28,
+        BTN_TRIGGER_HAPPY29,
+        BTN_TRIGGER_HAPPY30,
+        BTN_TRIGGER_HAPPY31,
+        BTN_TRIGGER_HAPPY32,
+        BTN_TRIGGER_HAPPY33,
+        BTN_TRIGGER_HAPPY34,
+        BTN_TRIGGER_HAPPY35,
+        BTN_TRIGGER_HAPPY36,
+        BTN_TRIGGER_HAPPY37,
+        BTN_TRIGGER_HAPPY38,
+        BTN_TRIGGER_HAPPY39,
+        BTN_TRIGGER_HAPPY40,
     ],
     Unknown
 );
@@ -106,6 +696,7 @@ create_mapping!(
         ABS_THROTTLE,
         ABS_RUDDER,
         ABS_WHEEL,
+        ABS_GAS,
         ABS_BRAKE,
         ABS_HAT0X,
         ABS_HAT0Y,
@@ -113,5 +704,108 @@ create_mapping!(
         ABS_HAT1Y,
         ABS_HAT2X,
         ABS_HAT2Y,
-    ]
+        ABS_HAT3X,
+        ABS_HAT3Y,
+        ABS_PRESSURE,
+        ABS_DISTANCE,
+        ABS_TILT_X,
+        ABS_TILT_Y,
+        ABS_TOOL_WIDTH,
+        ABS_VOLUME,
+        ABS_MISC,
+        ABS_MT_SLOT,
+        ABS_MT_TOUCH_MAJOR,
+        ABS_MT_TOUCH_MINOR,
+        ABS_MT_WIDTH_MAJOR,
+        ABS_MT_WIDTH_MINOR,
+        ABS_MT_ORIENTATION,
+        ABS_MT_POSITION_X,
+        ABS_MT_POSITION_Y,
+        ABS_MT_TOOL_TYPE,
+        ABS_MT_BLOB_ID,
+        ABS_MT_TRACKING_ID,
+        ABS_MT_PRESSURE,
+        ABS_MT_DISTANCE,
+        ABS_MT_TOOL_X,
+        ABS_MT_TOOL_Y,
+    ],
+    Unknown
 );
+
+#[cfg(test)]
+mod test {
+    use evdev::{AbsoluteAxisCode, KeyCode};
+    use ron::{from_str, to_string};
+
+    use super::{Axis, Button, EventCode};
+
+    #[test]
+    fn key_names() {
+        assert_eq!(Button::from(KeyCode::BTN_SOUTH), Button::BTN_SOUTH);
+        assert_eq!(Button::from(KeyCode::KEY_A), Button::KEY_A);
+        assert_eq!(
+            Button::from(KeyCode::BTN_TRIGGER_HAPPY40),
+            Button::BTN_TRIGGER_HAPPY40
+        );
+
+        // the kernel has no names for these, e.g. the T.16000M's buttons 300 to 302
+        assert_eq!(Button::from(KeyCode::new(300)), Button::Unknown(300));
+        assert_eq!(Button::Unknown(300).code(), Some(300));
+
+        assert_eq!(to_string(&Button::BTN_SOUTH).unwrap(), "BTN_SOUTH");
+        assert_eq!(
+            from_str::<Button>("BTN_TRIGGER_HAPPY1").unwrap(),
+            Button::BTN_TRIGGER_HAPPY1
+        );
+    }
+
+    #[test]
+    fn numeric_codes() {
+        assert_eq!(
+            from_str::<Button>("Unknown(303)").unwrap(),
+            Button::BTN_DEAD
+        );
+        assert_eq!(
+            from_str::<Button>("Unknown(300)").unwrap(),
+            Button::Unknown(300)
+        );
+
+        // names sharing a code are read as the one events are mapped to
+        assert_eq!(
+            from_str::<Button>("KEY_ROTATE_DISPLAY").unwrap(),
+            Button::from(KeyCode::KEY_ROTATE_DISPLAY)
+        );
+
+        assert_eq!(
+            Button::BTN_DEAD.spellings(),
+            [Button::BTN_DEAD, Button::Unknown(303)]
+        );
+        assert_eq!(
+            Button::KEY_DIRECTION.spellings(),
+            [
+                Button::KEY_DIRECTION,
+                Button::KEY_ROTATE_DISPLAY,
+                Button::Unknown(153)
+            ]
+        );
+        assert_eq!(Button::Unknown(300).spellings(), [Button::Unknown(300)]);
+        assert_eq!(Button::Stub.spellings(), [Button::Stub]);
+    }
+
+    #[test]
+    fn axis_names() {
+        assert_eq!(Axis::from(AbsoluteAxisCode::ABS_MISC), Axis::ABS_MISC);
+        assert_eq!(Axis::from(AbsoluteAxisCode::ABS_TILT_X), Axis::ABS_TILT_X);
+        assert_eq!(
+            Axis::from(AbsoluteAxisCode::ABS_MT_POSITION_X),
+            Axis::ABS_MT_POSITION_X
+        );
+
+        // codes without a name don't panic
+        assert_eq!(Axis::from(AbsoluteAxisCode(0x3f)), Axis::Unknown(0x3f));
+        assert_eq!(Axis::Unknown(0x3f).code(), Some(0x3f));
+
+        assert_eq!(from_str::<Axis>("Unknown(0)").unwrap(), Axis::ABS_X);
+        assert_eq!(to_string(&Axis::Unknown(0x3f)).unwrap(), "Unknown(63)");
+    }
+}
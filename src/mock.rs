@@ -0,0 +1,380 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::Result;
+use evdev::{EventSummary, InputEvent};
+use mio::{
+    unix::pipe::{self, Receiver, Sender},
+    Interest, Registry, Token,
+};
+
+use crate::{
+    backend::{Backend, EventSink, EventSource},
+    capabilities::DeviceCapabilities,
+    input_device::DeviceError,
+    output_device::OutputSpec,
+};
+
+/// In-memory devices, for running without hardware or /dev/uinput
+///
+/// Clones share their devices, so one clone can be handed to a runtime while the test drives the
+/// inputs and inspects the outputs through another.
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend {
+    shared: Arc<Mutex<Devices>>,
+}
+
+/// Test side of a mock input device
+#[derive(Debug, Clone)]
+pub struct MockInput {
+    shared: Arc<Mutex<InputState>>,
+}
+
+/// Test side of a mock output device
+#[derive(Debug, Clone)]
+pub struct MockOutput {
+    shared: Arc<Mutex<OutputState>>,
+}
+
+#[derive(Debug, Default)]
+struct Devices {
+    inputs: Vec<Arc<Mutex<InputState>>>,
+    outputs: Vec<Arc<Mutex<OutputState>>>,
+}
+
+#[derive(Debug)]
+struct InputState {
+    path: String,
+    capabilities: DeviceCapabilities,
+    connected: bool,
+    grabbed: bool,
+    pending: VecDeque<InputEvent>,
+    /// Wakes the poll of the opened source
+    notify: Option<Sender>,
+}
+
+#[derive(Debug)]
+struct OutputState {
+    spec: OutputSpec,
+    reports: Vec<Vec<InputEvent>>,
+    destroyed: bool,
+}
+
+struct MockSource {
+    path: String,
+    shared: Arc<Mutex<InputState>>,
+    notified: Receiver,
+}
+
+struct MockSink {
+    shared: Arc<Mutex<OutputState>>,
+}
+
+fn lock<T>(shared: &Mutex<T>) -> MutexGuard<'_, T> {
+    // a panicking test thread must not hide the actual failure
+    shared.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects a device that reports `capabilities`
+    pub fn add_input(&self, capabilities: DeviceCapabilities) -> MockInput {
+        let mut devices = lock(&self.shared);
+
+        let shared = Arc::new(Mutex::new(InputState {
+            path: format!("mock/{}", devices.inputs.len()),
+            capabilities,
+            connected: true,
+            grabbed: false,
+            pending: VecDeque::new(),
+            notify: None,
+        }));
+
+        devices.inputs.push(shared.clone());
+
+        MockInput { shared }
+    }
+
+    /// Every output device created so far, in order of creation
+    pub fn outputs(&self) -> Vec<MockOutput> {
+        lock(&self.shared)
+            .outputs
+            .iter()
+            .map(|shared| MockOutput {
+                shared: shared.clone(),
+            })
+            .collect()
+    }
+
+    /// The most recently created output device named `name` that still exists
+    pub fn output(&self, name: &str) -> Option<MockOutput> {
+        self.outputs()
+            .into_iter()
+            .rev()
+            .find(|output| output.name() == name && !output.is_destroyed())
+    }
+}
+
+impl Backend for MockBackend {
+    fn open_input(&self, name: &str, used_paths: &[&str]) -> Option<Box<dyn EventSource>> {
+        let devices = lock(&self.shared);
+
+        let shared = devices.inputs.iter().find(|shared| {
+            let input = lock(shared);
+
+            input.connected
+                && input.capabilities.name == name
+                && !used_paths.contains(&input.path.as_str())
+        })?;
+
+        let (notify, notified) = pipe::new().ok()?;
+
+        let mut input = lock(shared);
+        input.notify = Some(notify);
+        input.grabbed = false;
+
+        // events sent before the device was opened are delivered right away
+        if !input.pending.is_empty() {
+            input.wake();
+        }
+
+        Some(Box::new(MockSource {
+            path: input.path.clone(),
+            shared: shared.clone(),
+            notified,
+        }))
+    }
+
+    fn create_output(&self, spec: &OutputSpec) -> Result<Box<dyn EventSink>> {
+        let shared = Arc::new(Mutex::new(OutputState {
+            spec: spec.clone(),
+            reports: Vec::new(),
+            destroyed: false,
+        }));
+
+        lock(&self.shared).outputs.push(shared.clone());
+
+        Ok(Box::new(MockSink { shared }))
+    }
+}
+
+impl MockInput {
+    pub fn path(&self) -> String {
+        lock(&self.shared).path.clone()
+    }
+
+    /// Queues events for the source, as if the device produced them
+    pub fn send(&self, events: &[InputEvent]) {
+        let mut input = lock(&self.shared);
+        input.pending.extend(events);
+        input.wake();
+    }
+
+    /// The open source fails with `DeviceError::Disconnected` and the device can't be opened again
+    /// until it is reconnected
+    pub fn disconnect(&self) {
+        let mut input = lock(&self.shared);
+        input.connected = false;
+        input.pending.clear();
+        input.wake();
+    }
+
+    pub fn reconnect(&self) {
+        lock(&self.shared).connected = true;
+    }
+
+    pub fn is_grabbed(&self) -> bool {
+        lock(&self.shared).grabbed
+    }
+}
+
+impl InputState {
+    fn wake(&mut self) {
+        if let Some(notify) = self.notify.as_mut() {
+            // a full pipe already wakes the poll
+            let _ = notify.write(&[0]);
+        }
+    }
+}
+
+impl MockOutput {
+    pub fn name(&self) -> String {
+        lock(&self.shared).spec.name().to_string()
+    }
+
+    pub fn spec(&self) -> OutputSpec {
+        lock(&self.shared).spec.clone()
+    }
+
+    /// Emitted events, one entry per report
+    pub fn reports(&self) -> Vec<Vec<InputEvent>> {
+        lock(&self.shared).reports.clone()
+    }
+
+    pub fn events(&self) -> Vec<InputEvent> {
+        lock(&self.shared).reports.concat()
+    }
+
+    /// Whether the owner dropped the device
+    pub fn is_destroyed(&self) -> bool {
+        lock(&self.shared).destroyed
+    }
+}
+
+impl EventSource for MockSource {
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn capabilities(&self) -> Result<DeviceCapabilities> {
+        Ok(lock(&self.shared).capabilities.clone())
+    }
+
+    fn grab(&mut self) -> io::Result<()> {
+        lock(&self.shared).grabbed = true;
+
+        Ok(())
+    }
+
+    fn ungrab(&mut self) -> io::Result<()> {
+        lock(&self.shared).grabbed = false;
+
+        Ok(())
+    }
+
+    fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        registry.register(&mut self.notified, token, Interest::READABLE)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        registry.deregister(&mut self.notified)
+    }
+
+    fn read_events(&mut self, events: &mut Vec<EventSummary>) -> Result<(), DeviceError> {
+        let mut buffer = [0; 64];
+
+        // readiness is edge triggered, the pipe has to be drained
+        loop {
+            match self.notified.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let mut input = lock(&self.shared);
+
+        if !input.connected {
+            return Err(DeviceError::Disconnected);
+        }
+
+        events.extend(input.pending.drain(..).map(|event| event.destructure()));
+
+        Ok(())
+    }
+}
+
+impl EventSink for MockSink {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        lock(&self.shared).reports.push(events.to_vec());
+
+        Ok(())
+    }
+
+    fn is_ready(&mut self) -> io::Result<bool> {
+        Ok(true)
+    }
+}
+
+impl Drop for MockSink {
+    fn drop(&mut self) {
+        lock(&self.shared).destroyed = true;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use evdev::{EventType, InputEvent, KeyCode};
+    use mio::{Events, Poll, Token};
+
+    use crate::{
+        backend::{open_inputs, Backend},
+        capabilities::DeviceCapabilities,
+        input_device::DeviceError,
+        mappings::Button,
+    };
+
+    use super::MockBackend;
+
+    fn capabilities(name: &str) -> DeviceCapabilities {
+        DeviceCapabilities {
+            name: name.to_string(),
+            vendor: 0,
+            product: 0,
+            keys: vec![Button::BTN_TRIGGER],
+            axes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn same_names() {
+        let backend = MockBackend::new();
+        backend.add_input(capabilities("Stick"));
+        backend.add_input(capabilities("Stick"));
+
+        let names = vec!["Stick".to_string(), "Stick".to_string()];
+        let sources = open_inputs(&backend, &names).unwrap();
+        assert_eq!(sources[0].path(), "mock/0");
+        assert_eq!(sources[1].path(), "mock/1");
+
+        let names = vec!["Stick".to_string(); 3];
+        assert!(open_inputs(&backend, &names).is_err());
+    }
+
+    #[test]
+    fn wakes_poll() {
+        let backend = MockBackend::new();
+        let input = backend.add_input(capabilities("Stick"));
+
+        let mut source = backend.open_input("Stick", &[]).unwrap();
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+        source.register(poll.registry(), Token(0)).unwrap();
+
+        input.send(&[InputEvent::new(EventType::KEY.0, KeyCode::BTN_TRIGGER.0, 1)]);
+
+        poll.poll(&mut events, Some(Duration::from_secs(1)))
+            .unwrap();
+        assert_eq!(
+            events.iter().next().map(|event| event.token()),
+            Some(Token(0))
+        );
+
+        let mut read = Vec::new();
+        source.read_events(&mut read).unwrap();
+        assert_eq!(read.len(), 1);
+
+        input.disconnect();
+
+        poll.poll(&mut events, Some(Duration::from_secs(1)))
+            .unwrap();
+        assert!(!events.is_empty());
+        assert!(matches!(
+            source.read_events(&mut read),
+            Err(DeviceError::Disconnected)
+        ));
+        assert!(backend.open_input("Stick", &[]).is_none());
+
+        input.reconnect();
+        assert!(backend.open_input("Stick", &[]).is_some());
+    }
+}
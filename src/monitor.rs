@@ -0,0 +1,610 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use evdev::EventSummary;
+use mio::{Events, Poll, Token, Waker};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, LineGauge, Paragraph},
+    Frame,
+};
+
+use vjoy_linux::{
+    backend::EventSource,
+    capabilities::{AxisRange, DeviceCapabilities},
+    latency::{EventKind, LatencyStats},
+    mapper::{MappedEvent, Mapper},
+    mappings::{Axis, Button},
+    output_device::Output,
+    pipeline::{Pipeline, Processed, Settled},
+};
+
+const FRAME_TIME: Duration = Duration::from_millis(33);
+const WAKER: Token = Token(usize::MAX);
+
+struct KeyState {
+    code: Button,
+    value: i32,
+    destination: Option<Button>,
+    mapped: Option<i32>,
+}
+
+struct AxisState {
+    code: Axis,
+    range: AxisRange,
+    value: i32,
+    destination: Option<Axis>,
+    mapped: Option<i32>,
+}
+
+struct DeviceState {
+    name: String,
+    keys: Vec<KeyState>,
+    axes: Vec<AxisState>,
+}
+
+#[derive(Default)]
+struct MonitorState {
+    devices: Vec<DeviceState>,
+    latency: Option<LatencyStats>,
+    error: Option<String>,
+}
+
+impl MonitorState {
+    fn new(mapper: &Mapper, capabilities: &[DeviceCapabilities], latency: bool) -> Self {
+        let descriptor = mapper.descriptor();
+
+        let devices = capabilities
+            .iter()
+            .enumerate()
+            .map(|(index, capabilities)| DeviceState {
+                name: capabilities.name.clone(),
+                keys: capabilities
+                    .keys
+                    .iter()
+                    .map(|&code| KeyState {
+                        code,
+                        value: 0,
+                        destination: descriptor.key_mappings.get(&(index, code)).copied(),
+                        mapped: None,
+                    })
+                    .collect(),
+                axes: capabilities
+                    .axes
+                    .iter()
+                    .map(|&(code, range)| AxisState {
+                        code,
+                        range,
+                        value: range.minimum,
+                        destination: descriptor.axis_mappings.get(&(index, code)).copied(),
+                        mapped: None,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            devices,
+            latency: latency.then(LatencyStats::default),
+            error: None,
+        }
+    }
+
+    fn update(&mut self, index: usize, input: &EventSummary, mapped: Option<MappedEvent>) {
+        if let (Some(latency), Some(_)) = (self.latency.as_mut(), mapped) {
+            latency.record(index, input);
+        }
+
+        let Some(device) = self.devices.get_mut(index) else {
+            return;
+        };
+
+        match *input {
+            EventSummary::Key(_, key_code, value) => {
+                let code: Button = key_code.into();
+
+                if let Some(key) = device.keys.iter_mut().find(|key| key.code == code) {
+                    key.value = value;
+
+                    if let Some(mapped) = mapped {
+                        key.mapped = Some(mapped.event.value());
+                    }
+                }
+            }
+            EventSummary::AbsoluteAxis(_, axis, value) => {
+                let code: Axis = axis.into();
+
+                if let Some(axis) = device.axes.iter_mut().find(|axis| axis.code == code) {
+                    axis.value = value;
+
+                    // filters can drop values, the previous one is still emitted then
+                    if let Some(mapped) = mapped {
+                        axis.mapped = Some(mapped.event.value());
+                    }
+                }
+            }
+
+            _ => (),
+        }
+    }
+
+    /// Mapped value of a filtered axis that moves without a new raw value, or of a button that
+    /// settled after it bounced
+    fn update_settled(&mut self, settled: &Settled) {
+        let (Some(device), Some(mapped)) = (self.devices.get_mut(settled.index), settled.mapped)
+        else {
+            return;
+        };
+
+        match settled.input.destructure() {
+            EventSummary::Key(_, key_code, _) => {
+                let code: Button = key_code.into();
+
+                if let Some(key) = device.keys.iter_mut().find(|key| key.code == code) {
+                    key.mapped = Some(mapped.event.value());
+                }
+            }
+            EventSummary::AbsoluteAxis(_, axis, _) => {
+                let code: Axis = axis.into();
+
+                if let Some(axis) = device.axes.iter_mut().find(|axis| axis.code == code) {
+                    axis.mapped = Some(mapped.event.value());
+                }
+            }
+
+            _ => (),
+        }
+    }
+}
+
+/// Shows every button and axis of the input devices with their mapped values until `q` is pressed
+pub fn run_monitor(
+    output: Option<Output>,
+    input_devices: Vec<Box<dyn EventSource>>,
+    capabilities: &[DeviceCapabilities],
+    mapper: Mapper,
+    latency: bool,
+) -> Result<()> {
+    let state = Arc::new(Mutex::new(MonitorState::new(
+        &mapper,
+        capabilities,
+        latency,
+    )));
+
+    let poll = Poll::new()?;
+    let waker = Waker::new(poll.registry(), WAKER)?;
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let thread_state = state.clone();
+    let thread_stop = stop.clone();
+    let monitor_thread = thread::spawn(move || {
+        let result = process_inputs(
+            poll,
+            &thread_stop,
+            input_devices,
+            Pipeline::new(mapper),
+            output,
+            &thread_state,
+        );
+
+        if let Err(err) = &result {
+            thread_state.lock().unwrap().error = Some(format!("monitoring stopped: {err:#}"));
+        }
+
+        result
+    });
+
+    let mut terminal = ratatui::init();
+
+    let result = (|| -> Result<()> {
+        loop {
+            terminal.draw(|frame| draw(frame, &state.lock().unwrap()))?;
+
+            if event::poll(FRAME_TIME)? {
+                if let Event::Key(key) = event::read()? {
+                    if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    })();
+
+    ratatui::restore();
+
+    stop.store(true, Ordering::SeqCst);
+    waker.wake()?;
+
+    let processed = monitor_thread
+        .join()
+        .map_err(|_| anyhow!("the monitor thread panicked"))?;
+
+    result.and(processed)
+}
+
+/// Runs the events of the input devices through `pipeline` into `state` and `output` until `stop`
+/// is set and the [`WAKER`] of `poll` woken, the output is neutralized on return
+fn process_inputs(
+    mut poll: Poll,
+    stop: &AtomicBool,
+    mut input_devices: Vec<Box<dyn EventSource>>,
+    mut pipeline: Pipeline,
+    mut output: Option<Output>,
+    state: &Mutex<MonitorState>,
+) -> Result<()> {
+    let emit = |output: &mut Option<Output>, mapped: Option<&MappedEvent>| {
+        if let (Some(output), Some(mapped)) = (output.as_mut(), mapped) {
+            if let Err(err) = output.emit(mapped) {
+                state.lock().unwrap().error = Some(format!("output error: {err}"));
+            }
+        }
+    };
+
+    let result = (|| -> Result<()> {
+        let mut events = Events::with_capacity(16);
+        let mut inputs = Vec::new();
+
+        for (index, device) in input_devices.iter_mut().enumerate() {
+            device.register(poll.registry(), Token(index))?;
+        }
+
+        loop {
+            match poll.poll(&mut events, pipeline.settle_timeout()) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => result?,
+            }
+
+            for settled in pipeline.settle() {
+                emit(&mut output, settled.mapped.as_ref());
+                state.lock().unwrap().update_settled(&settled);
+            }
+
+            for event in events.iter() {
+                if event.token() == WAKER {
+                    if stop.load(Ordering::SeqCst) {
+                        return Ok(());
+                    }
+
+                    continue;
+                }
+
+                let index = event.token().0;
+                let result = input_devices[index].read_events(&mut inputs);
+
+                for input in inputs.drain(..) {
+                    let mapped = match pipeline.process(index, &input) {
+                        Processed::Passed(mapped) => mapped,
+                        Processed::Bounced | Processed::Filtered => None,
+                    };
+
+                    emit(&mut output, mapped.as_ref());
+                    state.lock().unwrap().update(index, &input, mapped);
+                }
+
+                if let Err(err) = result {
+                    state.lock().unwrap().error =
+                        Some(format!("input device {index} failed: {err}"));
+                }
+            }
+        }
+    })();
+
+    if let Some(output) = output.as_mut() {
+        output.neutralize(&pipeline.mapper().neutral_axes())?;
+    }
+
+    result
+}
+
+fn draw(frame: &mut Frame, state: &MonitorState) {
+    let [main, status] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(frame.area());
+
+    let columns = Layout::horizontal(
+        state
+            .devices
+            .iter()
+            .map(|_| Constraint::Ratio(1, state.devices.len() as u32)),
+    )
+    .split(main);
+
+    for (index, (device, area)) in state.devices.iter().zip(columns.iter()).enumerate() {
+        draw_device(frame, index, device, state.latency.as_ref(), *area);
+    }
+
+    let status_line = match &state.error {
+        Some(err) => Line::from(err.as_str()).red(),
+        None => Line::from("q: quit").dark_gray(),
+    };
+    frame.render_widget(status_line, status);
+}
+
+fn draw_device(
+    frame: &mut Frame,
+    index: usize,
+    device: &DeviceState,
+    latency: Option<&LatencyStats>,
+    area: Rect,
+) {
+    let mut block = Block::bordered().title(format!(" {index}: {} ", device.name));
+
+    if let Some(latency) = latency {
+        let summary = [(EventKind::Key, "keys"), (EventKind::Axis, "axes")]
+            .iter()
+            .filter_map(|&(kind, name)| {
+                let histogram = latency.histogram(index, kind)?;
+
+                Some(format!(
+                    "{} {name} p50 {:?} p99 {:?} max {:?}",
+                    histogram.count(),
+                    histogram.percentile(50),
+                    histogram.percentile(99),
+                    histogram.max()
+                ))
+            })
+            .collect::<Vec<_>>()
+            .join(" │ ");
+
+        block = block.title_bottom(format!(" latency: {summary} "));
+    }
+
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let [axes_area, keys_area] = Layout::vertical([
+        Constraint::Length(device.axes.len() as u16),
+        Constraint::Fill(1),
+    ])
+    .areas(inner);
+
+    for (axis, row) in device.axes.iter().zip(axes_area.rows()) {
+        let [raw_area, mapped_area] =
+            Layout::horizontal([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)]).areas(row);
+
+        frame.render_widget(
+            LineGauge::default()
+                .ratio(ratio(axis.value, axis.range))
+                .label(format!("{:<12?} {:>6}", axis.code, axis.value))
+                .filled_style(Style::default().fg(Color::Cyan)),
+            raw_area,
+        );
+
+        let mapped = match (axis.destination, axis.mapped) {
+            (Some(destination), Some(value)) => LineGauge::default()
+                .ratio(ratio(value, axis.range))
+                .label(format!(" → {destination:<12?} {value:>6}"))
+                .filled_style(Style::default().fg(Color::Green)),
+            (Some(destination), None) => LineGauge::default()
+                .label(format!(" → {destination:<12?} {:>6}", "-"))
+                .filled_style(Style::default().fg(Color::Green)),
+            (None, _) => LineGauge::default()
+                .label(" → unmapped")
+                .unfilled_style(Style::default().fg(Color::DarkGray)),
+        };
+        frame.render_widget(mapped, mapped_area);
+    }
+
+    let lines: Vec<Line> = device
+        .keys
+        .iter()
+        .map(|key| {
+            let raw = Span::styled(
+                format!("{} {:<16?}", indicator(key.value), key.code),
+                pressed_style(key.value),
+            );
+
+            let mapped = match key.destination {
+                Some(destination) => {
+                    let value = key.mapped.unwrap_or(0);
+
+                    Span::styled(
+                        format!(" → {} {destination:?}", indicator(value)),
+                        pressed_style(value),
+                    )
+                }
+                None => Span::styled(" → unmapped", Style::default().fg(Color::DarkGray)),
+            };
+
+            Line::from(vec![raw, mapped])
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(lines), keys_area);
+}
+
+fn ratio(value: i32, range: AxisRange) -> f64 {
+    if range.maximum <= range.minimum {
+        return 0.0;
+    }
+
+    ((value - range.minimum) as f64 / (range.maximum - range.minimum) as f64).clamp(0.0, 1.0)
+}
+
+fn indicator(value: i32) -> &'static str {
+    if value != 0 {
+        "●"
+    } else {
+        "○"
+    }
+}
+
+fn pressed_style(value: i32) -> Style {
+    if value != 0 {
+        Style::default().fg(Color::Green).bold()
+    } else {
+        Style::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{atomic::AtomicBool, Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use evdev::{EventType, InputEvent, KeyCode};
+    use mio::{Poll, Waker};
+
+    use vjoy_linux::{
+        backend::{open_inputs, Backend},
+        capabilities::DeviceCapabilities,
+        mapper::Mapper,
+        mappings::Button,
+        mock::{MockBackend, MockInput, MockOutput},
+        output_device::Output,
+        pipeline::Pipeline,
+        VJoyDescriptor,
+    };
+
+    use super::{process_inputs, MonitorState, Ordering, WAKER};
+
+    struct Monitor {
+        input: MockInput,
+        output: MockOutput,
+        state: Arc<Mutex<MonitorState>>,
+        stop: Arc<AtomicBool>,
+        waker: Waker,
+        thread: thread::JoinHandle<anyhow::Result<()>>,
+    }
+
+    impl Monitor {
+        /// Monitors a single mock device named "Stick", emitting its mapped events
+        fn start(descriptor: VJoyDescriptor) -> Self {
+            let backend = MockBackend::new();
+            let input = backend.add_input(DeviceCapabilities {
+                name: "Stick".to_string(),
+                vendor: 0,
+                product: 0,
+                keys: vec![Button::BTN_TRIGGER],
+                axes: Vec::new(),
+            });
+
+            let input_devices = open_inputs(&backend, &descriptor.input_devices).unwrap();
+            let capabilities = [input_devices[0].capabilities().unwrap()];
+            let output = Output::new(&descriptor, &capabilities, &backend as &dyn Backend).unwrap();
+            let mapper = Mapper::new(descriptor).with_capabilities(&capabilities);
+
+            let state = Arc::new(Mutex::new(MonitorState::new(&mapper, &capabilities, false)));
+            let poll = Poll::new().unwrap();
+            let waker = Waker::new(poll.registry(), WAKER).unwrap();
+            let stop = Arc::new(AtomicBool::new(false));
+
+            let thread = {
+                let state = state.clone();
+                let stop = stop.clone();
+
+                thread::spawn(move || {
+                    process_inputs(
+                        poll,
+                        &stop,
+                        input_devices,
+                        Pipeline::new(mapper),
+                        Some(output),
+                        &state,
+                    )
+                })
+            };
+
+            Self {
+                output: backend.output("Joystick").unwrap(),
+                input,
+                state,
+                stop,
+                waker,
+                thread,
+            }
+        }
+
+        fn stop(self) {
+            self.stop.store(true, Ordering::SeqCst);
+            self.waker.wake().unwrap();
+            self.thread.join().unwrap().unwrap();
+        }
+    }
+
+    /// Maps BTN_TRIGGER of "Stick" to BTN_0, with `debounce` milliseconds of debouncing
+    fn descriptor(debounce: u64) -> VJoyDescriptor {
+        VJoyDescriptor::parse(&format!(
+            r#"(
+                version: 2,
+                input_devices: ["Stick"],
+                output_device: Combined("Joystick"),
+                key_mappings: {{ (0, BTN_TRIGGER): BTN_0 }},
+                axis_mappings: {{}},
+                device_debounce: {{ 0: {debounce} }},
+            )"#
+        ))
+        .unwrap()
+    }
+
+    fn key(code: KeyCode, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY.0, code.0, value)
+    }
+
+    /// Values of `code` the output received, in order
+    fn received(output: &MockOutput, code: KeyCode) -> Vec<i32> {
+        output
+            .events()
+            .iter()
+            .filter(|event| event.event_type() == EventType::KEY && event.code() == code.0)
+            .map(|event| event.value())
+            .collect()
+    }
+
+    fn wait_for(mut f: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while !f() {
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn emitted_and_neutralized() {
+        let monitor = Monitor::start(descriptor(0));
+
+        monitor.input.send(&[key(KeyCode::BTN_TRIGGER, 1)]);
+        wait_for(|| received(&monitor.output, KeyCode::BTN_0) == [1]);
+
+        let pressed = monitor.state.lock().unwrap().devices[0].keys[0].mapped;
+        assert_eq!(pressed, Some(1));
+
+        let output = monitor.output.clone();
+        monitor.stop();
+
+        // the held button is released when the monitor quits
+        assert_eq!(received(&output, KeyCode::BTN_0), [1, 0]);
+    }
+
+    #[test]
+    fn debounced_buttons() {
+        let monitor = Monitor::start(descriptor(200));
+
+        let bouncing = |value| InputEvent::new_now(EventType::KEY.0, KeyCode::BTN_TRIGGER.0, value);
+        monitor
+            .input
+            .send(&[bouncing(1), bouncing(0), bouncing(1), bouncing(0)]);
+
+        // the release within the window is passed once the window is over
+        wait_for(|| received(&monitor.output, KeyCode::BTN_0) == [1, 0]);
+
+        let released = monitor.state.lock().unwrap().devices[0].keys[0].mapped;
+        assert_eq!(released, Some(0));
+
+        monitor.stop();
+    }
+}
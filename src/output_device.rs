@@ -1,119 +1,442 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io, slice, thread,
+    time::{Duration, Instant},
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use evdev::{
-    uinput::VirtualDevice, AbsoluteAxisCode, AttributeSet, BusType, InputId, KeyCode,
-    UinputAbsSetup,
+    uinput::VirtualDevice, AbsoluteAxisCode, AttributeSet, BusType, EventType, InputEvent, InputId,
+    KeyCode, UinputAbsSetup,
 };
 
-use crate::{descriptor::OutputType, input_device::InputDevice, VJoyDescriptor};
+use crate::{
+    backend::{Backend, EventSink},
+    capabilities::{AxisRange, DeviceCapabilities},
+    descriptor::OutputType,
+    mapper::MappedEvent,
+    state::DeviceState,
+    VJoyDescriptor,
+};
 
 pub enum Output {
     Combined(OutputDevice),
     Passthrough(Vec<OutputDevice>),
 }
 
+/// Everything a virtual device is created from. Two equal specs result in identical devices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputSpec {
+    name: String,
+    /// Own vendor id, so the virtual devices can be told apart from the physical ones
+    input_id: InputId,
+    keys: BTreeSet<u16>,
+    axes: BTreeMap<u16, AxisRange>,
+}
+
 pub struct OutputDevice {
-    device: VirtualDevice,
+    spec: OutputSpec,
+    sink: Box<dyn EventSink>,
+    state: DeviceState,
 }
 
-impl OutputDevice {
-    pub fn new(descriptor: &VJoyDescriptor, input_devices: &[InputDevice]) -> Result<Output> {
+impl OutputSpec {
+    pub fn from_descriptor(
+        descriptor: &VJoyDescriptor,
+        capabilities: &[DeviceCapabilities],
+    ) -> Result<Vec<Self>> {
         match &descriptor.output_device {
-            OutputType::Combined(output_device) => {
-                let keys: AttributeSet<KeyCode> = descriptor
-                    .key_mappings
-                    .values()
-                    .filter_map(|&b| TryInto::<KeyCode>::try_into(b).ok())
-                    .collect();
-
-                let mut builder = VirtualDevice::builder()?
-                    .name(&output_device)
-                    .with_keys(&keys)?;
-
-                for (&(index, src_axis), &dst_axis) in descriptor.axis_mappings.iter() {
-                    if let Ok(dst_axis) = dst_axis.try_into() {
-                        let src_axis: AbsoluteAxisCode = src_axis.try_into()?;
-
-                        let device = &input_devices[index];
-                        let abs_info = device
-                            .device()
-                            .get_absinfo()?
-                            .find_map(|(axis, info)| (axis == src_axis).then(|| info))
-                            .ok_or(anyhow!(
-                                "failed to find described axis ({src_axis:?}) for device {index}"
-                            ))?;
-
-                        let abs_setup = UinputAbsSetup::new(dst_axis, abs_info);
-                        builder = builder.with_absolute_axis(&abs_setup)?;
-                    }
+            OutputType::Combined(output_device) => Ok(vec![Self::collect(
+                descriptor,
+                capabilities,
+                output_device,
+                InputId::new(BusType::BUS_USB, 0xcafe, 0, 1),
+                None,
+            )?]),
+            OutputType::Passthrough(output_devices) => output_devices
+                .iter()
+                .enumerate()
+                .map(|(index, output_device)| {
+                    Self::collect(
+                        descriptor,
+                        capabilities,
+                        output_device,
+                        InputId::new(BusType::BUS_USB, 0xcafe, index as u16, 1),
+                        Some(index),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn input_id(&self) -> &InputId {
+        &self.input_id
+    }
+
+    pub fn keys(&self) -> &BTreeSet<u16> {
+        &self.keys
+    }
+
+    pub fn axes(&self) -> &BTreeMap<u16, AxisRange> {
+        &self.axes
+    }
+
+    pub fn build_virtual_device(&self) -> Result<VirtualDevice> {
+        let keys: AttributeSet<KeyCode> = self.keys.iter().map(|&code| KeyCode(code)).collect();
+
+        let mut builder = VirtualDevice::builder()?
+            .name(&self.name)
+            .input_id(self.input_id.clone())
+            .with_keys(&keys)?;
+
+        for (&axis, &range) in self.axes.iter() {
+            let abs_setup = UinputAbsSetup::new(AbsoluteAxisCode(axis), range.into());
+            builder = builder.with_absolute_axis(&abs_setup)?;
+        }
+
+        Ok(builder.build()?)
+    }
+
+    /// Gathers the mapped keys and axes of `input_index`, or of all inputs if it is `None`
+    fn collect(
+        descriptor: &VJoyDescriptor,
+        capabilities: &[DeviceCapabilities],
+        name: &str,
+        input_id: InputId,
+        input_index: Option<usize>,
+    ) -> Result<Self> {
+        let keys = descriptor
+            .key_mappings
+            .iter()
+            .filter(|(&(index, _), _)| input_index.is_none_or(|i| i == index))
+            .filter_map(|(_, &b)| TryInto::<KeyCode>::try_into(b).ok())
+            .map(|code| code.0)
+            .collect();
+
+        let mut axes = BTreeMap::new();
+
+        for (&(index, src_axis), &dst_axis) in descriptor.axis_mappings.iter() {
+            if input_index.is_some_and(|i| i != index) {
+                continue;
+            }
+
+            if let Ok(dst_axis) = TryInto::<AbsoluteAxisCode>::try_into(dst_axis) {
+                let range = capabilities
+                    .get(index)
+                    .and_then(|device| device.axis(src_axis))
+                    .ok_or(anyhow!(
+                        "failed to find described axis ({src_axis:?}) for device {index}"
+                    ))?;
+
+                axes.insert(dst_axis.0, range);
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            input_id,
+            keys,
+            axes,
+        })
+    }
+}
+
+impl OutputDevice {
+    fn build(spec: OutputSpec, backend: &dyn Backend) -> Result<Self> {
+        Ok(Self {
+            sink: backend.create_output(&spec)?,
+            spec,
+            state: DeviceState::default(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.spec.name
+    }
+
+    pub fn state(&self) -> &DeviceState {
+        &self.state
+    }
+
+    /// Emits the events and keeps track of the resulting device state
+    pub fn send(&mut self, events: &[InputEvent]) -> Result<()> {
+        self.sink.emit(events)?;
+        events.iter().for_each(|event| self.state.update(event));
+
+        Ok(())
+    }
+
+    /// Releases all pressed buttons and moves the axes to their neutral values in a single report
+    ///
+    /// Axes without a value in `neutral` are centered.
+    pub fn neutralize(&mut self, neutral: &BTreeMap<u16, i32>) -> Result<()> {
+        let keys = self
+            .state
+            .pressed_keys()
+            .map(|code| InputEvent::new(EventType::KEY.0, code, 0));
+
+        let axes = self.spec.axes.iter().map(|(&code, range)| {
+            let value = neutral
+                .get(&code)
+                .copied()
+                .unwrap_or((range.minimum + range.maximum) / 2);
+
+            InputEvent::new(EventType::ABSOLUTE.0, code, value)
+        });
+
+        let events: Vec<_> = keys.chain(axes).collect();
+
+        self.send(&events)
+    }
+}
+
+impl Output {
+    pub fn new(
+        descriptor: &VJoyDescriptor,
+        capabilities: &[DeviceCapabilities],
+        backend: &dyn Backend,
+    ) -> Result<Self> {
+        Self::build(
+            descriptor.output_device.is_combined(),
+            OutputSpec::from_descriptor(descriptor, capabilities)?,
+            backend,
+        )
+    }
+
+    fn build(combined: bool, specs: Vec<OutputSpec>, backend: &dyn Backend) -> Result<Self> {
+        let mut devices = specs
+            .into_iter()
+            .map(|spec| OutputDevice::build(spec, backend))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(if combined {
+            Output::Combined(devices.remove(0))
+        } else {
+            Output::Passthrough(devices)
+        })
+    }
+
+    /// Applies a changed descriptor, recreating only the virtual devices whose capabilities differ.
+    ///
+    /// Returns the indices of the recreated devices. On error nothing is changed.
+    pub fn reconfigure(
+        &mut self,
+        descriptor: &VJoyDescriptor,
+        capabilities: &[DeviceCapabilities],
+        backend: &dyn Backend,
+    ) -> Result<Vec<usize>> {
+        let combined = descriptor.output_device.is_combined();
+        let specs = OutputSpec::from_descriptor(descriptor, capabilities)?;
+
+        if combined != matches!(self, Output::Combined(_)) || specs.len() != self.devices().len() {
+            let count = specs.len();
+            *self = Self::build(combined, specs, backend)?;
+
+            return Ok((0..count).collect());
+        }
+
+        let replacements = specs
+            .into_iter()
+            .enumerate()
+            .filter(|(index, spec)| self.devices()[*index].spec != *spec)
+            .map(|(index, spec)| Ok((index, OutputDevice::build(spec, backend)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let devices = self.devices_mut();
+
+        Ok(replacements
+            .into_iter()
+            .map(|(index, device)| {
+                devices[index] = device;
+                index
+            })
+            .collect())
+    }
+
+    /// Blocks until other programs can open all output devices
+    pub fn wait_until_ready(&mut self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+
+        for device in self.devices_mut() {
+            while !device.sink.is_ready()? {
+                if Instant::now() > deadline {
+                    bail!("output device {} did not show up in time", device.name());
                 }
 
-                Ok(Output::Combined(Self {
-                    device: builder.build()?,
-                }))
+                thread::sleep(Duration::from_millis(10));
             }
-            OutputType::Passthrough(output_devices) => Ok(Output::Passthrough(
-                output_devices
-                    .into_iter()
-                    .enumerate()
-                    .map(|(index, output_device)| {
-                        let keys: AttributeSet<KeyCode> = descriptor
-                            .key_mappings
-                            .iter()
-                            .filter_map(|(&(input_index, _), &b)| {
-                                (index == input_index)
-                                    .then_some(TryInto::<KeyCode>::try_into(b).ok())
-                                    .flatten()
-                            })
-                            .collect();
-
-                        let mut builder = VirtualDevice::builder()?
-                            .name(&output_device)
-                            .input_id(InputId::new(BusType::BUS_USB, 0xcafe, index as u16, 1))
-                            .with_keys(&keys)?;
-
-                        for (&(input_index, src_axis), &dst_axis) in descriptor.axis_mappings.iter()
-                        {
-                            if index == input_index {
-                                if let Ok(dst_axis) = dst_axis.try_into() {
-                                    let src_axis: AbsoluteAxisCode = src_axis.try_into()?;
-
-                                    let device = &input_devices[index];
-                                    let abs_info = device
-                                        .device()
-                                        .get_absinfo()?
-                                        .find_map(|(axis, info)| (axis == src_axis).then(|| info))
-                                        .ok_or(anyhow!(
-                                "failed to find described axis ({src_axis:?}) for device {index}"
-                            ))?;
-
-                                    let abs_setup = UinputAbsSetup::new(dst_axis, abs_info);
-                                    builder = builder.with_absolute_axis(&abs_setup)?;
-                                }
-                            }
-                        }
-
-                        Ok(Self {
-                            device: builder.build()?,
-                        })
-                    })
-                    .collect::<Result<Vec<_>>>()?,
-            )),
+        }
+
+        Ok(())
+    }
+
+    pub fn devices(&self) -> &[OutputDevice] {
+        match self {
+            Output::Combined(output_device) => slice::from_ref(output_device),
+            Output::Passthrough(output_devices) => output_devices,
         }
     }
+
+    pub fn devices_mut(&mut self) -> &mut [OutputDevice] {
+        match self {
+            Output::Combined(output_device) => slice::from_mut(output_device),
+            Output::Passthrough(output_devices) => output_devices,
+        }
+    }
+
+    pub fn emit(&mut self, mapped: &MappedEvent) -> Result<()> {
+        self.devices_mut()[mapped.output].send(&[mapped.event])
+    }
+
+    /// Puts every output device into its neutral state, see `OutputDevice::neutralize`
+    pub fn neutralize(&mut self, neutral_axes: &[MappedEvent]) -> Result<()> {
+        for (index, device) in self.devices_mut().iter_mut().enumerate() {
+            let neutral = neutral_axes
+                .iter()
+                .filter(|mapped| mapped.output == index)
+                .map(|mapped| (mapped.event.code(), mapped.event.value()))
+                .collect();
+
+            device.neutralize(&neutral)?;
+        }
+
+        Ok(())
+    }
 }
 
-impl Deref for OutputDevice {
-    type Target = VirtualDevice;
+impl EventSink for VirtualDevice {
+    fn emit(&mut self, events: &[InputEvent]) -> io::Result<()> {
+        VirtualDevice::emit(self, events)
+    }
+
+    fn is_ready(&mut self) -> io::Result<bool> {
+        let nodes = self
+            .enumerate_dev_nodes_blocking()?
+            .collect::<io::Result<Vec<_>>>()?;
 
-    fn deref(&self) -> &Self::Target {
-        &self.device
+        // udev might still be creating the node or adjusting its permissions
+        Ok(!nodes.is_empty() && nodes.iter().all(|node| File::open(node).is_ok()))
     }
 }
 
-impl DerefMut for OutputDevice {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.device
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::{
+        capabilities::{AxisRange, DeviceCapabilities},
+        descriptor::{OutputType, VJoyDescriptor},
+        mappings::{Axis, Button},
+    };
+
+    use super::OutputSpec;
+
+    fn capabilities() -> Vec<DeviceCapabilities> {
+        let range = AxisRange {
+            minimum: 0,
+            maximum: 255,
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        };
+
+        vec![
+            DeviceCapabilities {
+                name: "Left".to_string(),
+                vendor: 0,
+                product: 0,
+                keys: vec![Button::BTN_TRIGGER, Button::BTN_THUMB],
+                axes: vec![(Axis::ABS_X, range), (Axis::ABS_Y, range)],
+            },
+            DeviceCapabilities {
+                name: "Right".to_string(),
+                vendor: 0,
+                product: 0,
+                keys: vec![Button::BTN_TRIGGER, Button::BTN_THUMB],
+                axes: vec![(Axis::ABS_X, range), (Axis::ABS_Y, range)],
+            },
+        ]
+    }
+
+    fn descriptor(
+        key_mappings: &[((usize, Button), Button)],
+        axis_mappings: &[((usize, Axis), Axis)],
+    ) -> VJoyDescriptor {
+        VJoyDescriptor {
+            input_devices: vec!["Left".to_string(), "Right".to_string()],
+            output_device: OutputType::Passthrough(vec!["One".to_string(), "Two".to_string()]),
+            key_mappings: HashMap::from_iter(key_mappings.iter().copied()),
+            axis_mappings: HashMap::from_iter(axis_mappings.iter().copied()),
+            ..VJoyDescriptor::default()
+        }
+    }
+
+    #[test]
+    fn unchanged_capabilities() {
+        let before = OutputSpec::from_descriptor(
+            &descriptor(
+                &[
+                    ((0, Button::BTN_TRIGGER), Button::BTN_0),
+                    ((0, Button::BTN_THUMB), Button::BTN_1),
+                ],
+                &[((1, Axis::ABS_X), Axis::ABS_X)],
+            ),
+            &capabilities(),
+        )
+        .unwrap();
+
+        // swapped buttons still result in the same capability set
+        let after = OutputSpec::from_descriptor(
+            &descriptor(
+                &[
+                    ((0, Button::BTN_TRIGGER), Button::BTN_1),
+                    ((0, Button::BTN_THUMB), Button::BTN_0),
+                ],
+                &[((1, Axis::ABS_X), Axis::ABS_X)],
+            ),
+            &capabilities(),
+        )
+        .unwrap();
+
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn changed_capabilities() {
+        let before = OutputSpec::from_descriptor(
+            &descriptor(
+                &[((0, Button::BTN_TRIGGER), Button::BTN_0)],
+                &[((1, Axis::ABS_X), Axis::ABS_X)],
+            ),
+            &capabilities(),
+        )
+        .unwrap();
+
+        let after = OutputSpec::from_descriptor(
+            &descriptor(
+                &[((0, Button::BTN_TRIGGER), Button::BTN_0)],
+                &[((1, Axis::ABS_X), Axis::ABS_RX)],
+            ),
+            &capabilities(),
+        )
+        .unwrap();
+
+        assert_eq!(before[0], after[0]);
+        assert_ne!(before[1], after[1]);
+    }
+
+    #[test]
+    fn missing_axis() {
+        let result = OutputSpec::from_descriptor(
+            &descriptor(&[], &[((1, Axis::ABS_RZ), Axis::ABS_RZ)]),
+            &capabilities(),
+        );
+
+        assert!(result.is_err());
     }
 }
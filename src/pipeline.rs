@@ -0,0 +1,277 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use evdev::{EventSummary, EventType, InputEvent};
+
+use crate::{
+    debounce::Debouncer,
+    filter::{AxisFilters, SETTLE_INTERVAL},
+    mapper::{MappedEvent, Mapper},
+};
+
+/// Stages an input event goes through until it can be emitted: the debouncing of buttons, the
+/// calibration and the filters of axes, then the mappings
+///
+/// Shared by the runtime and the monitor, so that both emit the same events for the same input.
+pub struct Pipeline {
+    mapper: Mapper,
+    debouncer: Debouncer,
+    filters: AxisFilters,
+    /// When smoothed axes take their next step towards the value they rest at
+    settle_filters_at: Option<Instant>,
+}
+
+/// What became of an input event
+#[derive(Debug, Clone, Copy)]
+pub enum Processed {
+    /// Passed every stage, `None` if it isn't mapped
+    Passed(Option<MappedEvent>),
+    /// Dropped as a bounce of its button
+    Bounced,
+    /// Dropped by a filter of its axis
+    Filtered,
+}
+
+/// Event that is produced without new input, e.g. by a smoothed axis catching up or a button that
+/// rests in another state after it bounced
+#[derive(Debug, Clone, Copy)]
+pub struct Settled {
+    pub index: usize,
+    pub input: InputEvent,
+    pub mapped: Option<MappedEvent>,
+}
+
+impl Pipeline {
+    pub fn new(mapper: Mapper) -> Self {
+        let descriptor = mapper.descriptor();
+
+        Self {
+            debouncer: Debouncer::new(&descriptor.device_debounce, &descriptor.button_debounce),
+            filters: AxisFilters::new(&mapper.descriptor().axis_filters),
+            mapper,
+            settle_filters_at: None,
+        }
+    }
+
+    pub fn mapper(&self) -> &Mapper {
+        &self.mapper
+    }
+
+    pub fn debouncer(&self) -> &Debouncer {
+        &self.debouncer
+    }
+
+    /// Swaps in the mappings of another profile or descriptor, the state of the buttons is kept and
+    /// the state of the filters unless they changed
+    pub fn set_mapper(&mut self, mapper: Mapper) {
+        let descriptor = mapper.descriptor();
+        self.debouncer
+            .set_windows(&descriptor.device_debounce, &descriptor.button_debounce);
+
+        if mapper.descriptor().axis_filters != self.mapper.descriptor().axis_filters {
+            self.filters = AxisFilters::new(&mapper.descriptor().axis_filters);
+            self.settle_filters_at = None;
+        }
+
+        self.mapper = mapper;
+    }
+
+    pub fn process(&mut self, index: usize, input: &EventSummary) -> Processed {
+        let mapped = match *input {
+            EventSummary::AbsoluteAxis(event, axis, value) => {
+                let value = self.mapper.calibrate(index, axis.into(), value);
+                let filtered = self
+                    .filters
+                    .filter(index, axis.into(), value, event.timestamp());
+
+                let Some(value) = filtered else {
+                    return Processed::Filtered;
+                };
+
+                self.schedule_settle();
+
+                let input = InputEvent::new(EventType::ABSOLUTE.0, axis.0, value);
+                self.mapper.map_calibrated(index, &input.destructure())
+            }
+            EventSummary::Key(event, key_code, state) => {
+                let accepted =
+                    self.debouncer
+                        .accept(index, key_code.into(), state, event.timestamp());
+
+                if !accepted {
+                    return Processed::Bounced;
+                }
+
+                self.mapper.map(index, input)
+            }
+            _ => self.mapper.map(index, input),
+        };
+
+        Processed::Passed(mapped)
+    }
+
+    /// Time until [`Pipeline::settle`] has to run, `None` while nothing lags behind
+    pub fn settle_timeout(&self) -> Option<Duration> {
+        let filters = self
+            .settle_filters_at
+            .map(|at| at.saturating_duration_since(Instant::now()));
+        let debouncer = self.debouncer.pending(SystemTime::now());
+
+        filters.into_iter().chain(debouncer).min()
+    }
+
+    /// Events of the stages that are due to catch up with input that stopped
+    pub fn settle(&mut self) -> Vec<Settled> {
+        let mut settled = Vec::new();
+
+        if self
+            .settle_filters_at
+            .is_some_and(|at| at <= Instant::now())
+        {
+            self.settle_filters_at = None;
+
+            for (index, input) in self.filters.settle() {
+                settled.push(Settled {
+                    index,
+                    input,
+                    mapped: self.mapper.map_calibrated(index, &input.destructure()),
+                });
+            }
+
+            self.schedule_settle();
+        }
+
+        for (index, input) in self.debouncer.settle(SystemTime::now()) {
+            settled.push(Settled {
+                index,
+                input,
+                mapped: self.mapper.map(index, &input.destructure()),
+            });
+        }
+
+        settled
+    }
+
+    /// Forgets the state of the buttons of a device, e.g. after it failed
+    pub fn reset(&mut self, index: usize) {
+        self.debouncer.reset(index);
+    }
+
+    fn schedule_settle(&mut self) {
+        if self.settle_filters_at.is_none() && !self.filters.is_settled() {
+            self.settle_filters_at = Some(Instant::now() + SETTLE_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, thread};
+
+    use evdev::{AbsoluteAxisCode, EventType, InputEvent};
+
+    use crate::{
+        calibration::Calibration,
+        capabilities::{AxisRange, DeviceCapabilities},
+        descriptor::{OutputType, VJoyDescriptor},
+        filter::{AxisFilter, SETTLE_INTERVAL},
+        mapper::Mapper,
+        mappings::Axis,
+    };
+
+    use super::{Pipeline, Processed};
+
+    #[test]
+    fn filtered_and_settled() {
+        let mut pipeline = Pipeline::new(Mapper::new(VJoyDescriptor {
+            input_devices: vec!["Pedals".to_string()],
+            output_device: OutputType::Combined("Joystick".to_string()),
+            axis_mappings: HashMap::from([((0, Axis::ABS_RZ), Axis::ABS_RUDDER)]),
+            axis_filters: HashMap::from([(
+                (0, Axis::ABS_RZ),
+                vec![AxisFilter::Threshold(3), AxisFilter::Exponential(0.5)],
+            )]),
+            ..VJoyDescriptor::default()
+        }));
+
+        let axis = |value| {
+            InputEvent::new(EventType::ABSOLUTE.0, AbsoluteAxisCode::ABS_RZ.0, value).destructure()
+        };
+
+        assert!(matches!(
+            pipeline.process(0, &axis(0)),
+            Processed::Passed(Some(_))
+        ));
+        assert!(matches!(pipeline.process(0, &axis(2)), Processed::Filtered));
+        assert!(pipeline.settle_timeout().is_none());
+
+        let Processed::Passed(Some(mapped)) = pipeline.process(0, &axis(100)) else {
+            panic!("not mapped");
+        };
+        assert_eq!(mapped.event.code(), AbsoluteAxisCode::ABS_RUDDER.0);
+        assert_eq!(mapped.event.value(), 50);
+
+        // catches up with the axis at rest
+        let mut last = 50;
+
+        while let Some(timeout) = pipeline.settle_timeout() {
+            assert!(timeout <= SETTLE_INTERVAL);
+            thread::sleep(timeout);
+
+            for settled in pipeline.settle() {
+                last = settled.mapped.unwrap().event.value();
+            }
+        }
+
+        assert_eq!(last, 100);
+    }
+
+    #[test]
+    fn calibrated_before_filters() {
+        let mapper = Mapper::new(VJoyDescriptor {
+            input_devices: vec!["Pedals".to_string()],
+            output_device: OutputType::Combined("Joystick".to_string()),
+            axis_mappings: HashMap::from([((0, Axis::ABS_RZ), Axis::ABS_RUDDER)]),
+            calibration: HashMap::from([(
+                (0, Axis::ABS_RZ),
+                Calibration {
+                    min: 900,
+                    center: 1000,
+                    max: 1100,
+                },
+            )]),
+            axis_filters: HashMap::from([((0, Axis::ABS_RZ), vec![AxisFilter::Threshold(3)])]),
+            ..VJoyDescriptor::default()
+        });
+        let mut pipeline = Pipeline::new(mapper.with_capabilities(&[DeviceCapabilities {
+            name: "Pedals".to_string(),
+            vendor: 0,
+            product: 0,
+            keys: Vec::new(),
+            axes: vec![(
+                Axis::ABS_RZ,
+                AxisRange {
+                    minimum: 0,
+                    maximum: 2047,
+                    fuzz: 0,
+                    flat: 0,
+                    resolution: 0,
+                },
+            )],
+        }]));
+
+        let axis = |value| {
+            InputEvent::new(EventType::ABSOLUTE.0, AbsoluteAxisCode::ABS_RZ.0, value).destructure()
+        };
+
+        let Processed::Passed(Some(centered)) = pipeline.process(0, &axis(1000)) else {
+            panic!("not mapped");
+        };
+        assert_eq!(centered.event.value(), 1023);
+
+        // a single raw step is stretched beyond the threshold
+        let Processed::Passed(Some(moved)) = pipeline.process(0, &axis(1001)) else {
+            panic!("filtered in raw units");
+        };
+        assert!(moved.event.value() - 1023 >= 3);
+    }
+}
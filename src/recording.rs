@@ -0,0 +1,378 @@
+use std::{
+    fs, io,
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Result};
+use evdev::{EventSummary, InputEvent};
+use mio::{Events, Interest, Poll, Token};
+use ron::{
+    from_str,
+    ser::{to_string_pretty, PrettyConfig},
+};
+use serde::{Deserialize, Serialize};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook_mio::v1_0::Signals;
+
+use crate::{
+    backend::{Backend, EventSink, EventSource},
+    capabilities::DeviceCapabilities,
+    mapper::{MappedEvent, Mapper},
+    mock::{MockBackend, MockInput},
+    output_device::OutputSpec,
+};
+
+// input devices use their index as token
+const SIGNALS: Token = Token(usize::MAX);
+
+/// Key and axis events of input devices, together with what the devices support
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub devices: Vec<DeviceCapabilities>,
+    pub events: Vec<RecordedEvent>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Index into `Recording::devices`
+    pub device: usize,
+    /// Microseconds since the first event of the recording
+    pub time: u64,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+/// Collects events into a recording, timed by their kernel timestamps
+pub struct Recorder {
+    start: Option<SystemTime>,
+    recording: Recording,
+}
+
+/// Plays a recording on mock input devices
+pub struct Replay {
+    recording: Recording,
+    backend: MockBackend,
+    inputs: Vec<MockInput>,
+}
+
+/// Input devices of a replay, output devices of another backend
+pub struct ReplayBackend {
+    inputs: MockBackend,
+    outputs: Box<dyn Backend>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        from_str(
+            &fs::read_to_string(path)
+                .map_err(|err| anyhow!("failed to open recording: {err:?}"))?,
+        )
+        .map_err(|err| anyhow!("failed to parse recording: {err:?}"))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // one line per event
+        fs::write(
+            path,
+            to_string_pretty(self, PrettyConfig::default().depth_limit(2))?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Combines recordings of single devices into one, the devices keep the order of `recordings`
+    pub fn merge(recordings: impl IntoIterator<Item = Recording>) -> Self {
+        let mut merged = Recording {
+            devices: Vec::new(),
+            events: Vec::new(),
+        };
+
+        for recording in recordings {
+            let offset = merged.devices.len();
+
+            merged.devices.extend(recording.devices);
+            merged
+                .events
+                .extend(recording.events.into_iter().map(|event| RecordedEvent {
+                    device: event.device + offset,
+                    ..event
+                }));
+        }
+
+        // stable, events of the same time keep their order
+        merged.events.sort_by_key(|event| event.time);
+
+        merged
+    }
+
+    /// Runs every event through `mapper`, without any timing or I/O
+    pub fn map(&self, mapper: &Mapper) -> Vec<MappedEvent> {
+        self.events
+            .iter()
+            .filter_map(|event| mapper.map(event.device, &event.input().destructure()))
+            .collect()
+    }
+}
+
+impl RecordedEvent {
+    pub fn input(&self) -> InputEvent {
+        InputEvent::new_now(self.event_type, self.code, self.value)
+    }
+}
+
+impl Recorder {
+    pub fn new(devices: Vec<DeviceCapabilities>) -> Self {
+        Self {
+            start: None,
+            recording: Recording {
+                devices,
+                events: Vec::new(),
+            },
+        }
+    }
+
+    /// Records key and axis events, everything else is of no use to the mapping
+    pub fn record(&mut self, device: usize, input: &EventSummary) {
+        let event: InputEvent = match *input {
+            EventSummary::Key(event, ..) => event.into(),
+            EventSummary::AbsoluteAxis(event, ..) => event.into(),
+            _ => return,
+        };
+
+        let start = *self.start.get_or_insert(event.timestamp());
+
+        // events of different devices might not arrive in order
+        let time = event.timestamp().duration_since(start).unwrap_or_default();
+
+        self.recording.events.push(RecordedEvent {
+            device,
+            time: time.as_micros() as u64,
+            event_type: event.event_type().0,
+            code: event.code(),
+            value: event.value(),
+        });
+    }
+
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+/// Records the events of `sources` until SIGINT or SIGTERM arrives
+pub fn record(sources: &mut [Box<dyn EventSource>]) -> Result<Recording> {
+    let devices = sources
+        .iter()
+        .map(|source| source.capabilities())
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut recorder = Recorder::new(devices);
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(16);
+    let mut inputs = Vec::new();
+
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    poll.registry()
+        .register(&mut signals, SIGNALS, Interest::READABLE)?;
+
+    for (index, source) in sources.iter_mut().enumerate() {
+        source.register(poll.registry(), Token(index))?;
+    }
+
+    loop {
+        match poll.poll(&mut events, None) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            result => result?,
+        }
+
+        for event in events.iter() {
+            let index = match event.token() {
+                SIGNALS => return Ok(recorder.finish()),
+                Token(index) => index,
+            };
+
+            let result = sources[index].read_events(&mut inputs);
+
+            for input in inputs.drain(..) {
+                recorder.record(index, &input);
+            }
+
+            result.map_err(|err| anyhow!("input device {index} failed: {err}"))?;
+        }
+    }
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        let backend = MockBackend::new();
+
+        let inputs = recording
+            .devices
+            .iter()
+            .map(|capabilities| backend.add_input(capabilities.clone()))
+            .collect();
+
+        Self {
+            recording,
+            backend,
+            inputs,
+        }
+    }
+
+    /// Backend whose input devices are the ones of the recording, output devices are created by
+    /// `outputs`
+    pub fn backend(&self, outputs: Box<dyn Backend>) -> ReplayBackend {
+        ReplayBackend {
+            inputs: self.backend.clone(),
+            outputs,
+        }
+    }
+
+    /// Sends all events with their recorded timing, scaled by `speed`, blocks until done
+    pub fn play(&self, speed: f64) {
+        let start = Instant::now();
+
+        for event in self.recording.events.iter() {
+            let due = Duration::from_micros(event.time).div_f64(speed);
+
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+
+            self.inputs[event.device].send(&[event.input()]);
+        }
+    }
+}
+
+impl Backend for ReplayBackend {
+    fn open_input(&self, name: &str, used_paths: &[&str]) -> Option<Box<dyn EventSource>> {
+        self.inputs.open_input(name, used_paths)
+    }
+
+    fn create_output(&self, spec: &OutputSpec) -> Result<Box<dyn EventSink>> {
+        self.outputs.create_output(spec)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, thread};
+
+    use evdev::{AbsoluteAxisCode, EventType, InputEvent, KeyCode};
+
+    use crate::{
+        capabilities::{AxisRange, DeviceCapabilities},
+        descriptor::{ErrorPolicy, OutputType, VJoyDescriptor},
+        mapper::Mapper,
+        mappings::{Axis, Button},
+        mock::MockBackend,
+        runtime::Runtime,
+    };
+
+    use super::{Recorder, Recording, Replay};
+
+    fn descriptor() -> VJoyDescriptor {
+        VJoyDescriptor {
+            input_devices: vec!["Stick".to_string()],
+            output_device: OutputType::Combined("Joystick".to_string()),
+            key_mappings: HashMap::from([((0, Button::BTN_TRIGGER), Button::BTN_0)]),
+            axis_mappings: HashMap::from([((0, Axis::ABS_X), Axis::ABS_RX)]),
+            on_device_error: ErrorPolicy::Exit,
+            ..VJoyDescriptor::default()
+        }
+    }
+
+    fn recording() -> Recording {
+        let mut recorder = Recorder::new(vec![DeviceCapabilities {
+            name: "Stick".to_string(),
+            vendor: 0,
+            product: 0,
+            keys: vec![Button::BTN_TRIGGER],
+            axes: vec![(
+                Axis::ABS_X,
+                AxisRange {
+                    minimum: 0,
+                    maximum: 255,
+                    fuzz: 0,
+                    flat: 0,
+                    resolution: 0,
+                },
+            )],
+        }]);
+
+        for event in [
+            InputEvent::new(EventType::KEY.0, KeyCode::BTN_TRIGGER.0, 1),
+            InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0),
+            InputEvent::new(EventType::ABSOLUTE.0, AbsoluteAxisCode::ABS_X.0, 42),
+            InputEvent::new(EventType::KEY.0, KeyCode::BTN_TRIGGER.0, 0),
+        ] {
+            recorder.record(0, &event.destructure());
+        }
+
+        recorder.finish()
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let recording = recording();
+        assert_eq!(recording.events.len(), 3);
+
+        let path = std::env::temp_dir().join("vjoy-linux-recording.ron");
+        recording.save(&path).unwrap();
+        let loaded = Recording::load(&path).unwrap();
+
+        assert_eq!(loaded.events, recording.events);
+        assert_eq!(loaded.devices[0].name, "Stick");
+    }
+
+    #[test]
+    fn mapped() {
+        let mapped = recording().map(&Mapper::new(descriptor()));
+
+        let values: Vec<_> = mapped
+            .iter()
+            .map(|mapped| (mapped.event.code(), mapped.event.value()))
+            .collect();
+
+        assert_eq!(
+            values,
+            [
+                (KeyCode::BTN_0.0, 1),
+                (AbsoluteAxisCode::ABS_RX.0, 42),
+                (KeyCode::BTN_0.0, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn replayed() {
+        let replay = Replay::new(recording());
+        let outputs = MockBackend::new();
+
+        let runtime = Runtime::new(
+            "replay.ron".into(),
+            descriptor(),
+            Box::new(replay.backend(Box::new(outputs.clone()))),
+            false,
+        )
+        .unwrap();
+
+        let handle = runtime.handle();
+        let runtime_thread = thread::spawn(move || runtime.run());
+
+        replay.play(1.0);
+
+        handle.shutdown().unwrap();
+        runtime_thread.join().unwrap().unwrap();
+
+        let events: Vec<_> = outputs.outputs()[0]
+            .events()
+            .iter()
+            .map(|event| (event.code(), event.value()))
+            .collect();
+        assert!(events.contains(&(KeyCode::BTN_0.0, 1)));
+        assert!(events.contains(&(AbsoluteAxisCode::ABS_RX.0, 42)));
+    }
+}
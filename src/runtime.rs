@@ -0,0 +1,1048 @@
+use std::{
+    io, mem,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use evdev::{AbsoluteAxisCode, EventSummary, EventType, InputEvent, KeyCode};
+use mio::{event::Event, Events, Interest, Poll, Token, Waker};
+use serde_json::{json, Value};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook_mio::v1_0::Signals;
+
+use crate::{
+    backend::{open_inputs, Backend, EventSource},
+    capabilities::DeviceCapabilities,
+    compose,
+    control::{ControlServer, InjectEvent, Request},
+    descriptor::{ErrorPolicy, VJoyDescriptor},
+    input_device::DeviceError,
+    latency::LatencyStats,
+    mapper::Mapper,
+    output_device::Output,
+    pipeline::{Pipeline, Processed},
+    state::DeviceState,
+    validate::validate,
+    watcher::DescriptorWatcher,
+};
+
+/// Time between attempts to open a failed input device again
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// input devices use their index as token
+const FIRST_CONNECTION: usize = 1 << 16;
+const CONTROL: Token = Token(usize::MAX - 3);
+const WATCHER: Token = Token(usize::MAX - 2);
+const SIGNALS: Token = Token(usize::MAX - 1);
+const WAKER: Token = Token(usize::MAX);
+
+enum Timer {
+    RetryDevice(usize),
+    Settle,
+}
+
+/// Single threaded event loop over the input devices, the descriptor file, the control socket and
+/// signals
+pub struct Runtime {
+    descriptor_file: PathBuf,
+    descriptor: VJoyDescriptor,
+    profile: Option<String>,
+    capabilities: Vec<DeviceCapabilities>,
+    backend: Box<dyn Backend>,
+    /// `None` while a device is gone
+    inputs: Vec<Option<Box<dyn EventSource>>>,
+    input_states: Vec<DeviceState>,
+    /// Reused for reading input events
+    input_buffer: Vec<EventSummary>,
+    pipeline: Pipeline,
+    output: Output,
+    poll: Poll,
+    timers: Vec<(Instant, Timer)>,
+    watcher: Option<DescriptorWatcher>,
+    control: Option<ControlServer>,
+    signals: Option<Signals>,
+    stop: Arc<AtomicBool>,
+    waker: Arc<Waker>,
+    latency: Option<LatencyStats>,
+    debug: bool,
+}
+
+/// Stops a runtime that runs on another thread
+pub struct RuntimeHandle {
+    stop: Arc<AtomicBool>,
+    waker: Arc<Waker>,
+}
+
+impl Runtime {
+    pub fn new(
+        descriptor_file: PathBuf,
+        descriptor: VJoyDescriptor,
+        backend: Box<dyn Backend>,
+        debug: bool,
+    ) -> Result<Self> {
+        let mut input_devices = open_inputs(backend.as_ref(), &descriptor.input_devices)?;
+
+        if debug {
+            let paths: Vec<_> = input_devices.iter().map(|device| device.path()).collect();
+            println!("input devices: {paths:#?}");
+        }
+
+        let capabilities = input_devices
+            .iter()
+            .map(|device| device.capabilities())
+            .collect::<Result<Vec<_>>>()?;
+
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+
+        for (index, device) in input_devices.iter_mut().enumerate() {
+            if descriptor.grab_inputs {
+                device.grab()?;
+            }
+
+            device.register(poll.registry(), Token(index))?;
+        }
+
+        let effective = descriptor.with_profile(None)?;
+        let output = Output::new(&effective, &capabilities, backend.as_ref())?;
+        let pipeline = Pipeline::new(Mapper::new(effective).with_capabilities(&capabilities));
+
+        Ok(Self {
+            descriptor_file,
+            descriptor,
+            profile: None,
+            input_states: vec![DeviceState::default(); capabilities.len()],
+            backend,
+            inputs: input_devices.into_iter().map(Some).collect(),
+            input_buffer: Vec::new(),
+            capabilities,
+            pipeline,
+            output,
+            poll,
+            timers: Vec::new(),
+            watcher: None,
+            control: None,
+            signals: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            waker,
+            latency: None,
+            debug,
+        })
+    }
+
+    pub fn capabilities(&self) -> &[DeviceCapabilities] {
+        &self.capabilities
+    }
+
+    pub fn wait_for_output_devices(&mut self, timeout: Duration) -> Result<()> {
+        self.output.wait_until_ready(timeout)
+    }
+
+    /// Reloads the descriptor whenever its file or one of its includes changes
+    pub fn watch_descriptor(&mut self) -> Result<()> {
+        let watcher = DescriptorWatcher::new(&compose::source_files(&self.descriptor_file))?;
+        watcher.register(self.poll.registry(), WATCHER)?;
+        self.watcher = Some(watcher);
+
+        Ok(())
+    }
+
+    /// Accepts control requests on the socket at `path`
+    pub fn listen(&mut self, path: &Path) -> Result<()> {
+        self.control = Some(ControlServer::bind(
+            path,
+            self.poll.registry(),
+            CONTROL,
+            FIRST_CONNECTION,
+        )?);
+
+        Ok(())
+    }
+
+    /// Stops on SIGINT, SIGTERM and SIGHUP instead of exiting right away
+    pub fn shutdown_on_signals(&mut self) -> Result<()> {
+        let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+        self.poll
+            .registry()
+            .register(&mut signals, SIGNALS, Interest::READABLE)?;
+        self.signals = Some(signals);
+
+        Ok(())
+    }
+
+    /// Measures the latency of every mapped event, reported on shutdown
+    pub fn measure_latency(&mut self) {
+        self.latency = Some(LatencyStats::default());
+    }
+
+    pub fn handle(&self) -> RuntimeHandle {
+        RuntimeHandle {
+            stop: self.stop.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+
+    /// Processes events until it is stopped, the virtual devices are destroyed on return
+    pub fn run(mut self) -> Result<()> {
+        let result = self.process();
+
+        // leave the devices in a defined state, even if processing failed
+        self.shutdown()?;
+
+        result
+    }
+
+    fn process(&mut self) -> Result<()> {
+        let mut events = Events::with_capacity(64);
+
+        loop {
+            let timeout = self
+                .timers
+                .iter()
+                .map(|(deadline, _)| deadline.saturating_duration_since(Instant::now()))
+                .min();
+
+            if let Err(err) = self.poll.poll(&mut events, timeout) {
+                // a signal arrived
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+
+                return Err(err.into());
+            }
+
+            for event in events.iter() {
+                match event.token() {
+                    WAKER => {
+                        if self.stop.load(Ordering::SeqCst) {
+                            // events that arrived before the stop request are still mapped
+                            for index in 0..self.inputs.len() {
+                                self.handle_readable(index)?;
+                            }
+
+                            return Ok(());
+                        }
+                    }
+                    SIGNALS => {
+                        if let Some(signals) = self.signals.as_mut() {
+                            if signals.pending().next().is_some() {
+                                return Ok(());
+                            }
+                        }
+                    }
+                    WATCHER => self.handle_watcher()?,
+                    Token(index) if index < self.inputs.len() => self.handle_readable(index)?,
+                    _ => self.handle_control_event(event)?,
+                }
+            }
+
+            self.run_timers()?;
+        }
+    }
+
+    /// Releases pressed buttons, moves axes to their neutral values and ungrabs the input devices
+    fn shutdown(self) -> Result<()> {
+        let Self {
+            descriptor,
+            pipeline,
+            mut output,
+            inputs,
+            latency,
+            debug,
+            ..
+        } = self;
+
+        output.neutralize(&pipeline.mapper().neutral_axes())?;
+
+        let bounces = pipeline.debouncer().report(&descriptor.input_devices);
+
+        if debug && !bounces.is_empty() {
+            print!("bounces dropped by debouncing:\n{bounces}");
+        }
+
+        if let Some(latency) = latency {
+            print!(
+                "latency from input event to emitted event:\n{}",
+                latency.report(&descriptor.input_devices)
+            );
+        }
+
+        if descriptor.grab_inputs {
+            for mut device in inputs.into_iter().flatten() {
+                device.ungrab()?;
+            }
+        }
+
+        // the virtual devices are destroyed when `output` is dropped
+        Ok(())
+    }
+
+    fn handle_readable(&mut self, index: usize) -> Result<()> {
+        let Some(device) = self.inputs[index].as_mut() else {
+            return Ok(());
+        };
+
+        let mut inputs = mem::take(&mut self.input_buffer);
+        let result = device.read_events(&mut inputs);
+
+        for input in inputs.drain(..) {
+            self.handle_input(index, &input)?;
+        }
+
+        self.input_buffer = inputs;
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(error) => self.handle_device_error(index, error),
+        }
+    }
+
+    fn handle_watcher(&mut self) -> Result<()> {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return Ok(());
+        };
+
+        if watcher.changed()? {
+            if let Err(err) = self.reload() {
+                println!("reload failed: {err}");
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_control_event(&mut self, event: &Event) -> Result<()> {
+        let Some(control) = self.control.as_mut() else {
+            return Ok(());
+        };
+
+        if !control.owns(event.token()) {
+            return Ok(());
+        }
+
+        for (token, request) in control.handle_event(self.poll.registry(), event)? {
+            let response = self.handle_control(request).into();
+
+            if let Some(control) = self.control.as_mut() {
+                control.respond(self.poll.registry(), token, &response);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_timers(&mut self) -> Result<()> {
+        let now = Instant::now();
+
+        let (expired, pending) = mem::take(&mut self.timers)
+            .into_iter()
+            .partition(|(deadline, _)| *deadline <= now);
+
+        self.timers = pending;
+
+        for (_, timer) in expired {
+            match timer {
+                Timer::RetryDevice(index) => self.retry_device(index),
+                Timer::Settle => self.settle()?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_input(&mut self, index: usize, input: &EventSummary) -> Result<()> {
+        let processed = self.pipeline.process(index, input);
+
+        match input {
+            EventSummary::Key(event, key_code, state) => {
+                if self.debug {
+                    println!("device {index} sent key event {key_code:?} in state {state}");
+                }
+
+                if let Processed::Bounced = processed {
+                    if self.debug {
+                        let bounces = self.pipeline.debouncer().bounces(index, (*key_code).into());
+                        println!(
+                            "device {index} key {key_code:?} bounced, {bounces} bounces so far"
+                        );
+                    }
+                } else {
+                    self.input_states[index].update(event);
+                }
+            }
+            EventSummary::AbsoluteAxis(event, axis, value) => {
+                if self.debug {
+                    println!("device {index} sent axis {axis:?} with {value}");
+                }
+
+                self.input_states[index].update(event);
+            }
+            _ => (),
+        }
+
+        if let Processed::Passed(Some(mapped)) = processed {
+            self.output.emit(&mapped)?;
+
+            if let Some(latency) = self.latency.as_mut() {
+                latency.record(index, input);
+            }
+        }
+
+        self.schedule_settle();
+
+        Ok(())
+    }
+
+    fn schedule_settle(&mut self) {
+        self.timers
+            .retain(|(_, timer)| !matches!(timer, Timer::Settle));
+
+        if let Some(timeout) = self.pipeline.settle_timeout() {
+            self.timers.push((Instant::now() + timeout, Timer::Settle));
+        }
+    }
+
+    /// Emits the events of stages that catch up with input that stopped, e.g. smoothed axes or
+    /// buttons that rest in another state after they bounced
+    fn settle(&mut self) -> Result<()> {
+        for settled in self.pipeline.settle() {
+            if let EventSummary::Key(event, key_code, state) = settled.input.destructure() {
+                if self.debug {
+                    let index = settled.index;
+                    println!("device {index} key {key_code:?} settled in state {state}");
+                }
+
+                self.input_states[settled.index].update(&event);
+            }
+
+            if let Some(mapped) = settled.mapped {
+                self.output.emit(&mapped)?;
+            }
+        }
+
+        self.schedule_settle();
+
+        Ok(())
+    }
+
+    fn handle_device_error(&mut self, index: usize, error: DeviceError) -> Result<()> {
+        let name = self.descriptor.input_devices[index].clone();
+        println!("input device {index} ({name}) failed: {error}");
+
+        if let Some(mut device) = self.inputs[index].take() {
+            // a failed device can't be deregistered anymore, dropping it closes it either way
+            let _ = device.deregister(self.poll.registry());
+        }
+
+        // buttons that were held on the device would be stuck otherwise, without being debounced
+        self.pipeline.reset(index);
+
+        let releases: Vec<_> = self.input_states[index]
+            .pressed_keys()
+            .map(|code| InputEvent::new(EventType::KEY.0, code, 0))
+            .collect();
+
+        for event in releases {
+            self.handle_input(index, &event.destructure())?;
+        }
+
+        match self.descriptor.on_device_error {
+            ErrorPolicy::Retry => self.schedule_retry(index),
+            ErrorPolicy::Log => (),
+            ErrorPolicy::Exit => bail!("input device {index} ({name}) failed: {error}"),
+        }
+
+        Ok(())
+    }
+
+    fn schedule_retry(&mut self, index: usize) {
+        self.timers
+            .push((Instant::now() + RETRY_INTERVAL, Timer::RetryDevice(index)));
+    }
+
+    fn retry_device(&mut self, index: usize) {
+        let name = self.descriptor.input_devices[index].clone();
+        let used_paths: Vec<&str> = self.inputs.iter().flatten().map(|d| d.path()).collect();
+
+        let Some(mut device) = self.backend.open_input(&name, &used_paths) else {
+            self.schedule_retry(index);
+            return;
+        };
+
+        if self.descriptor.grab_inputs {
+            if let Err(err) = device.grab() {
+                println!("failed to grab input device {index} ({name}): {err}");
+                self.schedule_retry(index);
+                return;
+            }
+        }
+
+        if let Err(err) = device.register(self.poll.registry(), Token(index)) {
+            println!("failed to register input device {index} ({name}): {err}");
+            self.schedule_retry(index);
+            return;
+        }
+
+        println!("input device {index} ({name}) is back at {}", device.path());
+
+        self.input_states[index] = DeviceState::default();
+        self.inputs[index] = Some(device);
+    }
+
+    fn handle_control(&mut self, request: Request) -> Result<Value> {
+        match request {
+            Request::Reload => self.reload()?,
+            Request::Profile { name } => self.switch_profile(name)?,
+            Request::Profiles => {
+                let mut profiles: Vec<_> = self.descriptor.profiles.keys().collect();
+                profiles.sort();
+
+                return Ok(json!({
+                    "active": self.profile,
+                    "profiles": profiles,
+                }));
+            }
+            Request::Devices => {
+                let inputs: Vec<_> = self
+                    .capabilities
+                    .iter()
+                    .enumerate()
+                    .map(|(index, device)| json!({ "index": index, "name": device.name }))
+                    .collect();
+
+                let outputs: Vec<_> = self
+                    .output
+                    .devices()
+                    .iter()
+                    .enumerate()
+                    .map(|(index, device)| json!({ "index": index, "name": device.name() }))
+                    .collect();
+
+                return Ok(json!({ "inputs": inputs, "outputs": outputs }));
+            }
+            Request::State => {
+                let inputs: Vec<_> = self.input_states.iter().map(|s| s.snapshot()).collect();
+
+                let outputs: Vec<_> = self
+                    .output
+                    .devices()
+                    .iter()
+                    .map(|device| device.state().snapshot())
+                    .collect();
+
+                return Ok(json!({ "inputs": inputs, "outputs": outputs }));
+            }
+            Request::Inject { output, events } => {
+                let events = events
+                    .into_iter()
+                    .map(|event| match event {
+                        InjectEvent::Key { code, value } => {
+                            let code: KeyCode = code.try_into()?;
+                            Ok(InputEvent::new(EventType::KEY.0, code.0, value))
+                        }
+                        InjectEvent::Axis { code, value } => {
+                            let code: AbsoluteAxisCode = code.try_into()?;
+                            Ok(InputEvent::new(EventType::ABSOLUTE.0, code.0, value))
+                        }
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                self.output
+                    .devices_mut()
+                    .get_mut(output)
+                    .ok_or(anyhow!("output device {output} does not exist"))?
+                    .send(&events)?;
+            }
+        }
+
+        Ok(Value::Null)
+    }
+
+    /// Activates the mappings of a profile, or the base mappings for `None`
+    fn switch_profile(&mut self, profile: Option<String>) -> Result<()> {
+        let effective = self.descriptor.with_profile(profile.as_deref())?;
+        self.apply(effective)?;
+        self.profile = profile;
+
+        Ok(())
+    }
+
+    /// Swaps in the mappings of the changed descriptor file, the previous ones stay active on error
+    fn reload(&mut self) -> Result<()> {
+        if let Some(watcher) = self.watcher.as_mut() {
+            // includes might have been added or removed
+            watcher.watch(&compose::source_files(&self.descriptor_file))?;
+        }
+
+        let (source, located) = compose::checked_source(&self.descriptor_file)?;
+
+        let mut diagnostics = validate(&source, Some(&self.capabilities));
+
+        if !located {
+            for diagnostic in diagnostics.iter_mut() {
+                diagnostic.location = None;
+            }
+        }
+
+        for diagnostic in diagnostics.iter() {
+            println!("{}:{diagnostic}", self.descriptor_file.display());
+        }
+
+        if diagnostics.iter().any(|d| d.is_error()) {
+            bail!("descriptor contains errors");
+        }
+
+        let descriptor = VJoyDescriptor::parse(&source)?;
+
+        if descriptor.input_devices != self.descriptor.input_devices {
+            bail!("input devices changed, a restart is required to apply them");
+        }
+
+        if descriptor.grab_inputs != self.descriptor.grab_inputs {
+            bail!("grab_inputs changed, a restart is required to apply it");
+        }
+
+        // keep the active profile if it still exists
+        let profile = self
+            .profile
+            .clone()
+            .filter(|name| descriptor.profiles.contains_key(name));
+
+        let effective = descriptor.with_profile(profile.as_deref())?;
+        self.apply(effective)?;
+        self.descriptor = descriptor;
+        self.profile = profile;
+
+        println!("reloaded {}", self.descriptor_file.display());
+
+        Ok(())
+    }
+
+    fn apply(&mut self, effective: VJoyDescriptor) -> Result<()> {
+        // the release of a held button goes through the new mappings, which might press another
+        // output button, so the old one would be stuck
+        self.emit_input_states(true)?;
+
+        let recreated =
+            match self
+                .output
+                .reconfigure(&effective, &self.capabilities, self.backend.as_ref())
+            {
+                Ok(recreated) => recreated,
+                Err(err) => {
+                    self.emit_input_states(false)?;
+                    return Err(err);
+                }
+            };
+
+        self.pipeline
+            .set_mapper(Mapper::new(effective).with_capabilities(&self.capabilities));
+        self.emit_input_states(false)?;
+
+        if !recreated.is_empty() {
+            println!("recreated output devices: {recreated:?}");
+        }
+
+        Ok(())
+    }
+
+    /// Sends the held buttons and the axis positions of the input devices through the current
+    /// mappings, or only a release for every held button with `release`
+    fn emit_input_states(&mut self, release: bool) -> Result<()> {
+        let mut events = Vec::new();
+
+        for (index, state) in self.input_states.iter().enumerate() {
+            for code in state.pressed_keys() {
+                let value = if release { 0 } else { 1 };
+                events.push((index, InputEvent::new(EventType::KEY.0, code, value)));
+            }
+
+            if !release {
+                for (code, value) in state.axes() {
+                    events.push((index, InputEvent::new(EventType::ABSOLUTE.0, code, value)));
+                }
+            }
+        }
+
+        for (index, event) in events {
+            if let Some(mapped) = self.pipeline.mapper().map(index, &event.destructure()) {
+                self.output.emit(&mapped)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl RuntimeHandle {
+    pub fn shutdown(&self) -> Result<()> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.waker.wake()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        env, process, thread,
+        time::{Duration, Instant},
+    };
+
+    use evdev::{
+        uinput::VirtualDevice, AbsInfo, AbsoluteAxisCode, EventType, InputEvent, KeyCode,
+        UinputAbsSetup,
+    };
+
+    use crate::{
+        backend::{Backend, EvdevBackend},
+        capabilities::{AxisRange, DeviceCapabilities},
+        control::{self, Request, Response},
+        descriptor::{ErrorPolicy, OutputType, Profile, VJoyDescriptor},
+        filter::AxisFilter,
+        mappings::{Axis, Button},
+        mock::{MockBackend, MockInput, MockOutput},
+    };
+
+    use super::{Runtime, RuntimeHandle};
+
+    fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        loop {
+            if let Some(value) = f() {
+                return value;
+            }
+
+            assert!(Instant::now() < deadline, "timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn descriptor(on_device_error: ErrorPolicy) -> VJoyDescriptor {
+        VJoyDescriptor {
+            input_devices: vec!["Stick".to_string()],
+            output_device: OutputType::Combined("Joystick".to_string()),
+            key_mappings: HashMap::from([((0, Button::BTN_TRIGGER), Button::BTN_0)]),
+            axis_mappings: HashMap::from([((0, Axis::ABS_X), Axis::ABS_RX)]),
+            grab_inputs: true,
+            on_device_error,
+            ..VJoyDescriptor::default()
+        }
+    }
+
+    /// Runs a runtime for `descriptor` on a single mock device named "Stick"
+    fn start(
+        descriptor: VJoyDescriptor,
+    ) -> (
+        MockInput,
+        MockOutput,
+        RuntimeHandle,
+        thread::JoinHandle<anyhow::Result<()>>,
+    ) {
+        let (backend, input, runtime) = create(descriptor);
+        let output = backend.output("Joystick").unwrap();
+
+        let handle = runtime.handle();
+        let runtime_thread = thread::spawn(move || runtime.run());
+
+        (input, output, handle, runtime_thread)
+    }
+
+    /// Runtime for `descriptor` on a single mock device named "Stick", without running it
+    fn create(descriptor: VJoyDescriptor) -> (MockBackend, MockInput, Runtime) {
+        let backend = MockBackend::new();
+        let input = backend.add_input(DeviceCapabilities {
+            name: "Stick".to_string(),
+            vendor: 0,
+            product: 0,
+            keys: vec![Button::BTN_TRIGGER],
+            axes: vec![(
+                Axis::ABS_X,
+                AxisRange {
+                    minimum: 0,
+                    maximum: 255,
+                    fuzz: 0,
+                    flat: 0,
+                    resolution: 0,
+                },
+            )],
+        });
+
+        let runtime = Runtime::new(
+            "mock.ron".into(),
+            descriptor,
+            Box::new(backend.clone()),
+            false,
+        )
+        .unwrap();
+
+        (backend, input, runtime)
+    }
+
+    fn key(code: KeyCode, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY.0, code.0, value)
+    }
+
+    fn axis(code: AbsoluteAxisCode, value: i32) -> InputEvent {
+        InputEvent::new(EventType::ABSOLUTE.0, code.0, value)
+    }
+
+    fn received(output: &MockOutput, event: InputEvent) -> bool {
+        output.events().iter().any(|e| {
+            (e.event_type(), e.code(), e.value())
+                == (event.event_type(), event.code(), event.value())
+        })
+    }
+
+    #[test]
+    fn mapped_events() {
+        let (input, output, handle, runtime_thread) = start(descriptor(ErrorPolicy::Exit));
+        assert!(input.is_grabbed());
+
+        input.send(&[
+            key(KeyCode::BTN_TRIGGER, 1),
+            axis(AbsoluteAxisCode::ABS_X, 42),
+        ]);
+
+        wait_for(|| received(&output, axis(AbsoluteAxisCode::ABS_RX, 42)).then_some(()));
+        assert!(received(&output, key(KeyCode::BTN_0, 1)));
+
+        handle.shutdown().unwrap();
+        runtime_thread.join().unwrap().unwrap();
+
+        // the held button is released and the axis centered before the device is destroyed
+        assert!(received(&output, key(KeyCode::BTN_0, 0)));
+        assert!(received(&output, axis(AbsoluteAxisCode::ABS_RX, 127)));
+        assert!(output.is_destroyed());
+        assert!(!input.is_grabbed());
+    }
+
+    #[test]
+    fn filtered_axes() {
+        let (input, output, handle, runtime_thread) = start(VJoyDescriptor {
+            axis_filters: HashMap::from([(
+                (0, Axis::ABS_X),
+                vec![AxisFilter::Threshold(3), AxisFilter::Exponential(0.5)],
+            )]),
+            ..descriptor(ErrorPolicy::Exit)
+        });
+
+        input.send(&[axis(AbsoluteAxisCode::ABS_X, 0)]);
+        input.send(&[axis(AbsoluteAxisCode::ABS_X, 2)]);
+        input.send(&[axis(AbsoluteAxisCode::ABS_X, 200)]);
+
+        // smoothed, then catches up with the axis at rest
+        wait_for(|| received(&output, axis(AbsoluteAxisCode::ABS_RX, 100)).then_some(()));
+        wait_for(|| received(&output, axis(AbsoluteAxisCode::ABS_RX, 200)).then_some(()));
+        assert!(!received(&output, axis(AbsoluteAxisCode::ABS_RX, 2)));
+        assert!(!received(&output, axis(AbsoluteAxisCode::ABS_RX, 1)));
+
+        handle.shutdown().unwrap();
+        runtime_thread.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn debounced_buttons() {
+        let (input, output, handle, runtime_thread) = start(VJoyDescriptor {
+            device_debounce: HashMap::from([(0, 200)]),
+            ..descriptor(ErrorPolicy::Exit)
+        });
+
+        let bouncing = |value| InputEvent::new_now(EventType::KEY.0, KeyCode::BTN_TRIGGER.0, value);
+        input.send(&[bouncing(1), bouncing(0), bouncing(1), bouncing(0)]);
+
+        // the release within the window is passed once the window is over
+        wait_for(|| received(&output, key(KeyCode::BTN_0, 0)).then_some(()));
+
+        let presses = output
+            .events()
+            .iter()
+            .filter(|e| e.code() == KeyCode::BTN_0.0 && e.value() == 1)
+            .count();
+        assert_eq!(presses, 1);
+
+        handle.shutdown().unwrap();
+        runtime_thread.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn held_across_profile_switch() {
+        // the profile swaps the destinations, so the output device is kept
+        let (backend, input, mut runtime) = create(VJoyDescriptor {
+            key_mappings: HashMap::from([
+                ((0, Button::BTN_TRIGGER), Button::BTN_0),
+                ((0, Button::BTN_THUMB), Button::BTN_1),
+            ]),
+            profiles: HashMap::from([(
+                "landing".to_string(),
+                Profile {
+                    key_mappings: HashMap::from([
+                        ((0, Button::BTN_TRIGGER), Button::BTN_1),
+                        ((0, Button::BTN_THUMB), Button::BTN_0),
+                    ]),
+                    axis_mappings: HashMap::new(),
+                },
+            )]),
+            ..descriptor(ErrorPolicy::Exit)
+        });
+
+        let socket = env::temp_dir().join(format!("vjoy-linux-test-held-{}.sock", process::id()));
+        runtime.listen(&socket).unwrap();
+
+        let output = backend.output("Joystick").unwrap();
+        let handle = runtime.handle();
+        let runtime_thread = thread::spawn(move || runtime.run());
+
+        input.send(&[key(KeyCode::BTN_TRIGGER, 1)]);
+        wait_for(|| received(&output, key(KeyCode::BTN_0, 1)).then_some(()));
+
+        let landing = Request::Profile {
+            name: Some("landing".to_string()),
+        };
+        let response = control::send(&socket, &landing).unwrap();
+        assert!(matches!(response, Response::Ok { .. }));
+
+        // the old destination is released and the new one pressed while the button is held
+        assert!(!output.is_destroyed());
+        assert!(received(&output, key(KeyCode::BTN_0, 0)));
+        assert!(received(&output, key(KeyCode::BTN_1, 1)));
+
+        input.send(&[key(KeyCode::BTN_TRIGGER, 0)]);
+        wait_for(|| received(&output, key(KeyCode::BTN_1, 0)).then_some(()));
+
+        handle.shutdown().unwrap();
+        runtime_thread.join().unwrap().unwrap();
+        std::fs::remove_file(socket).unwrap();
+    }
+
+    #[test]
+    fn reconnecting_device() {
+        let (input, output, handle, runtime_thread) = start(descriptor(ErrorPolicy::Retry));
+
+        input.send(&[key(KeyCode::BTN_TRIGGER, 1)]);
+        wait_for(|| received(&output, key(KeyCode::BTN_0, 1)).then_some(()));
+
+        input.disconnect();
+        wait_for(|| received(&output, key(KeyCode::BTN_0, 0)).then_some(()));
+
+        input.reconnect();
+        wait_for(|| input.is_grabbed().then_some(()));
+
+        input.send(&[axis(AbsoluteAxisCode::ABS_X, 42)]);
+        wait_for(|| received(&output, axis(AbsoluteAxisCode::ABS_RX, 42)).then_some(()));
+
+        handle.shutdown().unwrap();
+        runtime_thread.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn failing_device() {
+        let (input, _output, _handle, runtime_thread) = start(descriptor(ErrorPolicy::Exit));
+
+        input.disconnect();
+
+        assert!(runtime_thread.join().unwrap().is_err());
+    }
+
+    /// Time from emitting an event on a virtual source device until the mapped event can be read
+    /// from the output device. Needs access to /dev/uinput:
+    ///
+    /// `cargo test --release latency -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn latency() {
+        const SOURCE: &str = "vjoy-linux latency source";
+        const OUTPUT: &str = "vjoy-linux latency output";
+        const SAMPLES: i32 = 10_000;
+
+        let mut source = VirtualDevice::builder()
+            .unwrap()
+            .name(SOURCE)
+            .with_absolute_axis(&UinputAbsSetup::new(
+                AbsoluteAxisCode::ABS_X,
+                AbsInfo::new(0, 0, SAMPLES, 0, 0, 0),
+            ))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let descriptor = VJoyDescriptor {
+            input_devices: vec![SOURCE.to_string()],
+            output_device: OutputType::Combined(OUTPUT.to_string()),
+            axis_mappings: HashMap::from([((0, Axis::ABS_X), Axis::ABS_X)]),
+            on_device_error: ErrorPolicy::Exit,
+            ..VJoyDescriptor::default()
+        };
+
+        // the source device shows up asynchronously
+        wait_for(|| EvdevBackend.open_input(SOURCE, &[]));
+
+        let mut runtime = Runtime::new(
+            "latency.ron".into(),
+            descriptor,
+            Box::new(EvdevBackend),
+            false,
+        )
+        .unwrap();
+        runtime
+            .wait_for_output_devices(Duration::from_secs(5))
+            .unwrap();
+
+        let handle = runtime.handle();
+        let runtime_thread = thread::spawn(move || runtime.run());
+
+        let mut output = wait_for(|| {
+            evdev::enumerate()
+                .find(|(_, device)| device.name() == Some(OUTPUT))
+                .map(|(_, device)| device)
+        });
+
+        let mut latencies = Vec::new();
+
+        // changing values, the kernel drops repeated ones
+        for value in 1..=SAMPLES {
+            let start = Instant::now();
+
+            source
+                .emit(&[InputEvent::new(
+                    EventType::ABSOLUTE.0,
+                    AbsoluteAxisCode::ABS_X.0,
+                    value,
+                )])
+                .unwrap();
+
+            'received: loop {
+                for event in output.fetch_events().unwrap() {
+                    if event.event_type() == EventType::ABSOLUTE && event.value() == value {
+                        break 'received;
+                    }
+                }
+            }
+
+            latencies.push(start.elapsed());
+        }
+
+        handle.shutdown().unwrap();
+        runtime_thread.join().unwrap().unwrap();
+
+        latencies.sort();
+
+        let mean = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+
+        println!(
+            "{SAMPLES} events, mean {mean:?}, median {:?}, p99 {:?}, max {:?}",
+            percentile(50),
+            percentile(99),
+            percentile(100)
+        );
+    }
+}
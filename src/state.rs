@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+
+use evdev::{AbsoluteAxisCode, EventType, InputEvent, KeyCode};
+use serde::{Deserialize, Serialize};
+
+/// Last known value of every button and axis of a device
+#[derive(Debug, Default, Clone)]
+pub struct DeviceState {
+    keys: BTreeMap<u16, i32>,
+    axes: BTreeMap<u16, i32>,
+}
+
+/// Serializable form of a `DeviceState` with evdev names as keys
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StateSnapshot {
+    pub keys: BTreeMap<String, i32>,
+    pub axes: BTreeMap<String, i32>,
+}
+
+impl DeviceState {
+    pub fn update(&mut self, event: &InputEvent) {
+        match event.event_type() {
+            EventType::KEY => {
+                self.keys.insert(event.code(), event.value());
+            }
+            EventType::ABSOLUTE => {
+                self.axes.insert(event.code(), event.value());
+            }
+
+            _ => (),
+        }
+    }
+
+    pub fn pressed_keys(&self) -> impl Iterator<Item = u16> + '_ {
+        self.keys
+            .iter()
+            .filter(|(_, &value)| value != 0)
+            .map(|(&code, _)| code)
+    }
+
+    /// Code and last value of every axis
+    pub fn axes(&self) -> impl Iterator<Item = (u16, i32)> + '_ {
+        self.axes.iter().map(|(&code, &value)| (code, value))
+    }
+
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            keys: self
+                .keys
+                .iter()
+                .map(|(&code, &value)| (format!("{:?}", KeyCode(code)), value))
+                .collect(),
+            axes: self
+                .axes
+                .iter()
+                .map(|(&code, &value)| (format!("{:?}", AbsoluteAxisCode(code)), value))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,1115 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    iter::{self, Peekable},
+    ops::Range,
+    str::CharIndices,
+};
+
+use serde::Serialize;
+
+use crate::{
+    capabilities::DeviceCapabilities,
+    descriptor::{DescriptorFile, DeviceRef, OutputType},
+    mappings::{Axis, Button, EventCode},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// 1-based line and column in the descriptor file
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(location: Option<(usize, usize)>, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            location,
+            message,
+        }
+    }
+
+    fn warning(location: Option<(usize, usize)>, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            location,
+            message,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((line, column)) = self.location {
+            write!(f, "{line}:{column}: ")?;
+        }
+
+        match self.severity {
+            Severity::Error => write!(f, "error: {}", self.message),
+            Severity::Warning => write!(f, "warning: {}", self.message),
+        }
+    }
+}
+
+/// Checks a descriptor source against the capabilities of its input devices.
+///
+/// Capability checks are skipped when `capabilities` is `None`.
+pub fn validate(source: &str, capabilities: Option<&[DeviceCapabilities]>) -> Vec<Diagnostic> {
+    let descriptor = match DescriptorFile::parse(source) {
+        Ok(descriptor) => descriptor,
+        Err(err) => {
+            return vec![Diagnostic::error(
+                Some((err.position.line, err.position.col)),
+                err.code.to_string(),
+            )]
+        }
+    };
+
+    let locator = Locator::new(source);
+    let mut diagnostics = Vec::new();
+
+    for (index, entry) in descriptor.input_devices.iter().enumerate() {
+        let Some(alias) = entry.alias() else {
+            continue;
+        };
+
+        let earlier = descriptor.input_devices[..index]
+            .iter()
+            .filter(|entry| entry.alias() == Some(alias))
+            .count();
+
+        if earlier > 0 {
+            diagnostics.push(Diagnostic::error(
+                locator.find_nth_in("input_devices", &format!("alias:{alias:?}"), earlier),
+                format!("device alias {alias} is used more than once"),
+            ));
+        }
+    }
+
+    if !descriptor.include.is_empty() {
+        diagnostics.push(Diagnostic::warning(
+            locator.find("include"),
+            "included descriptors are not checked, validate the resolved descriptor instead"
+                .to_string(),
+        ));
+    } else if descriptor.output_device.is_none() {
+        diagnostics.push(Diagnostic::error(
+            None,
+            "output_device is missing".to_string(),
+        ));
+    }
+
+    if let Some(OutputType::Passthrough(outputs)) = &descriptor.output_device {
+        if outputs.len() != descriptor.input_devices.len() {
+            diagnostics.push(Diagnostic::error(
+                locator.find("output_device"),
+                format!(
+                    "passthrough lists {} output devices but there are {} input devices",
+                    outputs.len(),
+                    descriptor.input_devices.len()
+                ),
+            ));
+        }
+    }
+
+    if let Some(capabilities) = capabilities {
+        if capabilities.len() != descriptor.input_devices.len() {
+            diagnostics.push(Diagnostic::error(
+                locator.find("input_devices"),
+                format!(
+                    "capabilities are known for {} devices but {} input devices are listed",
+                    capabilities.len(),
+                    descriptor.input_devices.len()
+                ),
+            ));
+        }
+    }
+
+    let mut profiles: Vec<_> = descriptor.profiles.iter().collect();
+    profiles.sort_by_key(|&(name, _)| name);
+
+    let scopes = iter::once((None, &descriptor.key_mappings, &descriptor.axis_mappings)).chain(
+        profiles.iter().map(|(name, profile)| {
+            (
+                Some(name.as_str()),
+                &profile.key_mappings,
+                &profile.axis_mappings,
+            )
+        }),
+    );
+
+    let mut mappings = Vec::new();
+
+    for (profile, key_mappings, axis_mappings) in scopes {
+        let section = |field| match profile {
+            None => locator.section(field),
+            Some(name) => locator.profile_section(name, field),
+        };
+
+        let key_section = section("key_mappings");
+        mappings.extend(key_mappings.iter().map(|((device, src), &dst)| {
+            let location = find_entry(&locator, key_section.clone(), device, src);
+            let support = capabilities
+                .zip(descriptor.resolve(device))
+                .and_then(|(c, index)| c.get(index))
+                .map(|device| (device.has_key(*src), device.name.as_str()));
+
+            Mapping {
+                location,
+                profile,
+                device: device.clone(),
+                source: format!("{src:?}"),
+                destination: format!("{dst:?}"),
+                is_stub: dst == Button::Stub,
+                support,
+            }
+        }));
+
+        let axis_section = section("axis_mappings");
+        mappings.extend(axis_mappings.iter().map(|((device, src), &dst)| {
+            let location = find_entry(&locator, axis_section.clone(), device, src);
+            let support = capabilities
+                .zip(descriptor.resolve(device))
+                .and_then(|(c, index)| c.get(index))
+                .map(|device| (device.axis(*src).is_some(), device.name.as_str()));
+
+            Mapping {
+                location,
+                profile,
+                device: device.clone(),
+                source: format!("{src:?}"),
+                destination: format!("{dst:?}"),
+                is_stub: dst == Axis::Stub,
+                support,
+            }
+        }));
+    }
+
+    // report in file order
+    mappings.sort_by_key(|mapping| mapping.location);
+
+    let mut sources = HashSet::new();
+    let mut resolved = Vec::new();
+
+    for mapping in mappings.iter() {
+        let index = match check_device(&descriptor, &mapping.device, mapping.location) {
+            Ok(index) => index,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        if !sources.insert((mapping.profile, index, mapping.source.as_str())) {
+            diagnostics.push(Diagnostic::error(
+                mapping.location,
+                format!(
+                    "({}, {}) is mapped twice, by index and by alias",
+                    mapping.device, mapping.source
+                ),
+            ));
+        }
+
+        if let Some((false, name)) = mapping.support {
+            diagnostics.push(Diagnostic::error(
+                mapping.location,
+                format!(
+                    "device {index} ({name}) does not provide {}",
+                    mapping.source
+                ),
+            ));
+        }
+
+        if mapping.is_stub {
+            diagnostics.push(Diagnostic::warning(
+                mapping.location,
+                format!(
+                    "({}, {}) is mapped to Stub and will be ignored",
+                    mapping.device, mapping.source
+                ),
+            ));
+        }
+
+        resolved.push((index, mapping));
+    }
+
+    let combined = descriptor
+        .output_device
+        .as_ref()
+        .is_none_or(OutputType::is_combined);
+
+    let mut destinations: HashMap<_, Vec<&Mapping>> = HashMap::new();
+
+    // every profile is checked with the mappings it is applied on top of
+    for profile in iter::once(None).chain(profiles.iter().map(|(name, _)| Some(name.as_str()))) {
+        let overridden: HashSet<_> = resolved
+            .iter()
+            .filter(|(_, mapping)| profile.is_some() && mapping.profile == profile)
+            .map(|(index, mapping)| (*index, mapping.source.as_str()))
+            .collect();
+
+        for (index, mapping) in resolved.iter() {
+            let active = mapping.profile == profile
+                || (mapping.profile.is_none()
+                    && !overridden.contains(&(*index, mapping.source.as_str())));
+
+            if active && !mapping.is_stub {
+                let output = (!combined).then_some(*index);
+
+                destinations
+                    .entry((profile, output, mapping.destination.as_str()))
+                    .or_default()
+                    .push(mapping);
+            }
+        }
+    }
+
+    let mut neutral_axes: Vec<_> = descriptor
+        .neutral_axes
+        .keys()
+        .map(|(device, axis)| {
+            let location = find_entry(&locator, locator.section("neutral_axes"), device, axis);
+
+            (location, device, *axis)
+        })
+        .collect();
+
+    neutral_axes.sort_by_key(|&(location, ..)| location);
+
+    let mapped_axes: HashSet<_> = descriptor
+        .axis_mappings
+        .keys()
+        .chain(
+            descriptor
+                .profiles
+                .values()
+                .flat_map(|profile| profile.axis_mappings.keys()),
+        )
+        .filter_map(|(device, axis)| Some((descriptor.resolve(device)?, *axis)))
+        .collect();
+
+    for (location, device, axis) in neutral_axes {
+        let index = match check_device(&descriptor, device, location) {
+            Ok(index) => index,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        if let Some(device) = capabilities.and_then(|c| c.get(index)) {
+            if device.axis(axis).is_none() {
+                diagnostics.push(Diagnostic::error(
+                    location,
+                    format!("device {index} ({}) does not provide {axis:?}", device.name),
+                ));
+            }
+        }
+
+        if !mapped_axes.contains(&(index, axis)) {
+            diagnostics.push(Diagnostic::warning(
+                location,
+                format!(
+                    "neutral value of ({device}, {axis:?}) has no effect, the axis is not mapped"
+                ),
+            ));
+        }
+    }
+
+    let mut calibration: Vec<_> = descriptor
+        .calibration
+        .iter()
+        .map(|((device, axis), calibration)| {
+            let location = find_entry(&locator, locator.section("calibration"), device, axis);
+
+            (location, device, *axis, calibration)
+        })
+        .collect();
+
+    calibration.sort_by_key(|&(location, ..)| location);
+
+    for (location, device, axis, calibration) in calibration {
+        let index = match check_device(&descriptor, device, location) {
+            Ok(index) => index,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        if !calibration.is_valid() {
+            diagnostics.push(Diagnostic::error(
+                location,
+                format!("calibration of ({device}, {axis:?}) needs min < center < max"),
+            ));
+        }
+
+        if let Some(device) = capabilities.and_then(|c| c.get(index)) {
+            match device.axis(axis) {
+                None => diagnostics.push(Diagnostic::error(
+                    location,
+                    format!("device {index} ({}) does not provide {axis:?}", device.name),
+                )),
+                Some(range)
+                    if calibration.min < range.minimum || calibration.max > range.maximum =>
+                {
+                    diagnostics.push(Diagnostic::warning(
+                        location,
+                        format!(
+                            "calibration of ({index}, {axis:?}) exceeds its range of {} to {}",
+                            range.minimum, range.maximum
+                        ),
+                    ))
+                }
+                Some(_) => (),
+            }
+        }
+    }
+
+    let mut axis_filters: Vec<_> = descriptor
+        .axis_filters
+        .iter()
+        .map(|((device, axis), filters)| {
+            let location = find_entry(&locator, locator.section("axis_filters"), device, axis);
+
+            (location, device, *axis, filters)
+        })
+        .collect();
+
+    axis_filters.sort_by_key(|&(location, ..)| location);
+
+    for (location, device, axis, filters) in axis_filters {
+        let index = match check_device(&descriptor, device, location) {
+            Ok(index) => index,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        for filter in filters {
+            if let Err(err) = filter.check() {
+                diagnostics.push(Diagnostic::error(
+                    location,
+                    format!("filter of ({device}, {axis:?}): {err}"),
+                ));
+            }
+        }
+
+        if let Some(device) = capabilities.and_then(|c| c.get(index)) {
+            if device.axis(axis).is_none() {
+                diagnostics.push(Diagnostic::error(
+                    location,
+                    format!("device {index} ({}) does not provide {axis:?}", device.name),
+                ));
+            }
+        }
+    }
+
+    let mut device_debounce: Vec<_> = descriptor
+        .device_debounce
+        .keys()
+        .map(|device| {
+            let key = format!("{}:", ron::to_string(device).unwrap_or_default());
+
+            (locator.find_in("device_debounce", &key), device)
+        })
+        .collect();
+
+    device_debounce.sort_by_key(|&(location, _)| location);
+
+    for (location, device) in device_debounce {
+        if let Err(diagnostic) = check_device(&descriptor, device, location) {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    let mut button_debounce: Vec<_> = descriptor
+        .button_debounce
+        .keys()
+        .map(|(device, button)| {
+            let location = find_entry(&locator, locator.section("button_debounce"), device, button);
+
+            (location, device, *button)
+        })
+        .collect();
+
+    button_debounce.sort_by_key(|&(location, ..)| location);
+
+    for (location, device, button) in button_debounce {
+        let index = match check_device(&descriptor, device, location) {
+            Ok(index) => index,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+
+        if let Some(device) = capabilities.and_then(|c| c.get(index)) {
+            if !device.has_key(button) {
+                diagnostics.push(Diagnostic::error(
+                    location,
+                    format!(
+                        "device {index} ({}) does not provide {button:?}",
+                        device.name
+                    ),
+                ));
+            }
+        }
+    }
+
+    // collisions of the mappings outside of profiles are only reported once
+    let mut collisions: Vec<_> = destinations
+        .into_iter()
+        .filter(|((profile, ..), sources)| {
+            sources.len() > 1 && (profile.is_none() || sources.iter().any(|m| m.profile.is_some()))
+        })
+        .collect();
+    collisions.sort_by_key(|((profile, ..), sources)| (*profile, sources[0].location));
+
+    for ((profile, _, destination), sources) in collisions {
+        let names = sources
+            .iter()
+            .map(|m| format!("({}, {})", m.device, m.source))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let message = match profile {
+            None => format!("destination {destination} is used by multiple sources: {names}"),
+            Some(profile) => format!(
+                "in profile {profile}, destination {destination} is used by multiple sources: \
+                 {names}"
+            ),
+        };
+
+        for mapping in sources.iter() {
+            diagnostics.push(Diagnostic::error(mapping.location, message.clone()));
+        }
+    }
+
+    diagnostics
+}
+
+struct Mapping<'a> {
+    location: Option<(usize, usize)>,
+    /// The profile the entry belongs to, `None` outside of profiles
+    profile: Option<&'a str>,
+    /// As written in the file
+    device: DeviceRef,
+    source: String,
+    destination: String,
+    is_stub: bool,
+    /// Whether the source device provides the source code, and the device name
+    support: Option<(bool, &'a str)>,
+}
+
+/// Index of the referenced device, or the error why there is none
+fn check_device(
+    descriptor: &DescriptorFile,
+    device: &DeviceRef,
+    location: Option<(usize, usize)>,
+) -> Result<usize, Diagnostic> {
+    match descriptor.resolve(device) {
+        Some(index) if index < descriptor.input_devices.len() => Ok(index),
+        Some(index) => Err(Diagnostic::error(
+            location,
+            format!(
+                "device index {index} is out of range, there are {} input devices",
+                descriptor.input_devices.len()
+            ),
+        )),
+        None => Err(Diagnostic::error(
+            location,
+            format!("unknown device alias {device}"),
+        )),
+    }
+}
+
+/// Location of the entry of `device` and `code` within `section`, however its code is written
+fn find_entry<K: EventCode + Serialize>(
+    locator: &Locator,
+    section: Option<Range<usize>>,
+    device: &DeviceRef,
+    code: &K,
+) -> Option<(usize, usize)> {
+    let section = section?;
+
+    code.spellings()
+        .iter()
+        .find_map(|code| locator.find_within(section.clone(), &key_text(device, code)))
+}
+
+fn key_text(device: &DeviceRef, code: &impl Serialize) -> String {
+    format!(
+        "({},{})",
+        ron::to_string(device).unwrap_or_default(),
+        ron::to_string(code).unwrap_or_default()
+    )
+}
+
+/// Finds text in the descriptor source while ignoring whitespace
+struct Locator<'a> {
+    source: &'a str,
+    stripped: String,
+    offsets: Vec<usize>,
+}
+
+impl<'a> Locator<'a> {
+    fn new(source: &'a str) -> Self {
+        let mut offsets = Vec::new();
+        let mut stripped = String::new();
+        let mut chars = source.char_indices().peekable();
+        let mut in_string = false;
+        let mut escaped = false;
+
+        while let Some((offset, c)) = chars.next() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => (),
+                }
+            } else {
+                match (c, chars.peek().map(|&(_, next)| next)) {
+                    ('"', _) => in_string = true,
+                    ('/', Some('/')) => {
+                        while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+                        continue;
+                    }
+                    ('/', Some('*')) => {
+                        skip_block_comment(&mut chars);
+                        continue;
+                    }
+                    _ => (),
+                }
+            }
+
+            if !c.is_whitespace() {
+                offsets.push(offset);
+                stripped.push(c);
+            }
+        }
+
+        Self {
+            source,
+            stripped,
+            offsets,
+        }
+    }
+
+    fn find(&self, needle: &str) -> Option<(usize, usize)> {
+        self.find_within(0..self.stripped.len(), needle)
+    }
+
+    /// Like `find`, but only looks within the value of the top level field `section`
+    fn find_in(&self, section: &str, needle: &str) -> Option<(usize, usize)> {
+        self.find_nth_in(section, needle, 0)
+    }
+
+    /// Like `find_in`, but skips the first `n` occurrences of `needle`
+    fn find_nth_in(&self, section: &str, needle: &str, n: usize) -> Option<(usize, usize)> {
+        let section = self.section(section)?;
+        let mut start = section.start;
+
+        for _ in 0..n {
+            start += self.position(start..section.end, needle)? + 1;
+        }
+
+        self.find_within(start..section.end, needle)
+    }
+
+    /// Range of the value of a top level field in the stripped source
+    fn section(&self, field: &str) -> Option<Range<usize>> {
+        self.field_in(0..self.stripped.len(), field)
+    }
+
+    /// Range of the value of a field of the profile `name`
+    fn profile_section(&self, name: &str, field: &str) -> Option<Range<usize>> {
+        let profiles = self.section("profiles")?;
+        let key = format!("{name:?}:");
+        let start = profiles.start + self.position(profiles.clone(), &key)? + key.len();
+
+        self.field_in(start..profiles.end, field)
+    }
+
+    /// Range of the value of a field of the struct starting at `range`
+    fn field_in(&self, range: Range<usize>, field: &str) -> Option<Range<usize>> {
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut start = None;
+        let mut previous = None;
+
+        for (index, c) in self.stripped.get(range.clone())?.char_indices() {
+            let index = range.start + index;
+
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => (),
+                }
+
+                continue;
+            }
+
+            match c {
+                '"' => in_string = true,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => (),
+            }
+
+            match start {
+                None if depth == 1 && matches!(previous, Some('(' | ',')) => {
+                    let rest = &self.stripped[index..];
+
+                    if rest.strip_prefix(field).is_some_and(|r| r.starts_with(':')) {
+                        start = Some(index + field.len() + 1);
+                    }
+                }
+                Some(start) if depth == 0 || (depth == 1 && c == ',') => {
+                    return Some(start..index);
+                }
+                _ => (),
+            }
+
+            previous = Some(c);
+        }
+
+        start.map(|start| start..range.end)
+    }
+
+    /// Offset of `needle` in the stripped source, relative to the start of `range`
+    fn position(&self, range: Range<usize>, needle: &str) -> Option<usize> {
+        let needle: String = needle.chars().filter(|c| !c.is_whitespace()).collect();
+
+        self.stripped.get(range)?.find(&needle)
+    }
+
+    fn find_within(&self, range: Range<usize>, needle: &str) -> Option<(usize, usize)> {
+        let position = range.start + self.position(range, needle)?;
+        let char_index = self.stripped[..position].chars().count();
+        let offset = self.offsets[char_index];
+
+        let before = &self.source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+
+        Some((line, column))
+    }
+}
+
+/// Consumes a block comment starting at `/*`, RON allows them to be nested
+fn skip_block_comment(chars: &mut Peekable<CharIndices>) {
+    let mut depth = 0;
+    let mut previous = None;
+
+    for (_, c) in chars.by_ref() {
+        match (previous, c) {
+            (Some('/'), '*') => {
+                depth += 1;
+                previous = None;
+                continue;
+            }
+            (Some('*'), '/') => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return;
+                }
+
+                previous = None;
+                continue;
+            }
+            _ => (),
+        }
+
+        previous = Some(c);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        capabilities::{AxisRange, DeviceCapabilities},
+        descriptor::{GenerationDescription, OutputType, VJoyDescriptor},
+        format::Format,
+        mappings::{Axis, Button},
+        mock::MockBackend,
+    };
+
+    use super::{validate, Diagnostic, Severity};
+
+    /// Severity and line of every diagnostic
+    fn summary(diagnostics: &[Diagnostic]) -> Vec<(Severity, usize)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.severity, d.location.unwrap().0))
+            .collect()
+    }
+
+    fn capabilities() -> Vec<DeviceCapabilities> {
+        let range = AxisRange {
+            minimum: 0,
+            maximum: 255,
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        };
+
+        vec![
+            DeviceCapabilities {
+                name: "Left".to_string(),
+                vendor: 0,
+                product: 0,
+                keys: vec![Button::BTN_TRIGGER, Button::BTN_THUMB],
+                axes: vec![(Axis::ABS_X, range)],
+            },
+            DeviceCapabilities {
+                name: "Right".to_string(),
+                vendor: 0,
+                product: 0,
+                keys: vec![Button::BTN_TRIGGER],
+                axes: vec![(Axis::ABS_X, range)],
+            },
+        ]
+    }
+
+    #[test]
+    fn valid_descriptor() {
+        let source = r#"(
+    input_devices: ["Left", "Right"],
+    output_device: Combined("Combined"),
+    key_mappings: {
+        (0, BTN_TRIGGER): BTN_0,
+        (1, BTN_TRIGGER): BTN_1,
+    },
+    axis_mappings: {
+        (0, ABS_X): ABS_X,
+        (1, ABS_X): ABS_RX,
+    },
+)"#;
+
+        assert_eq!(validate(source, Some(&capabilities())), Vec::new());
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let diagnostics = validate("(\n    input_devices: [\"Left\",\n    output_device", None);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].location.map(|(line, _)| line), Some(3));
+    }
+
+    #[test]
+    fn mapping_errors() {
+        let source = r#"(
+    input_devices: ["Left", "Right"],
+    output_device: Combined("Combined"),
+    key_mappings: {
+        (0, BTN_TRIGGER): BTN_0,
+        (1, BTN_TRIGGER): BTN_0,
+        (1, BTN_THUMB): BTN_1,
+        (2, BTN_TRIGGER): BTN_2,
+        (0, BTN_THUMB): Stub,
+    },
+    axis_mappings: {
+        (0, ABS_Y): ABS_Y,
+    },
+)"#;
+
+        let diagnostics = validate(source, Some(&capabilities()));
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                (Severity::Error, 7),
+                (Severity::Error, 8),
+                (Severity::Warning, 9),
+                (Severity::Error, 12),
+                (Severity::Error, 5),
+                (Severity::Error, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn neutral_axes() {
+        let source = r#"(
+    input_devices: ["Left", "Right"],
+    output_device: Combined("Combined"),
+    key_mappings: {},
+    axis_mappings: {
+        (0, ABS_X): ABS_X,
+    },
+    neutral_axes: {
+        (0, ABS_X): 0,
+        (1, ABS_X): 0,
+        (0, ABS_Y): 0,
+        (2, ABS_X): 0,
+    },
+)"#;
+
+        let diagnostics = validate(source, Some(&capabilities()));
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                (Severity::Warning, 10),
+                (Severity::Error, 11),
+                (Severity::Warning, 11),
+                (Severity::Error, 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn calibration() {
+        let source = r#"(
+    version: 2,
+    input_devices: ["Left", "Right"],
+    output_device: Combined("Combined"),
+    key_mappings: {},
+    axis_mappings: {},
+    calibration: {
+        (0, ABS_X): (min: 10, center: 120, max: 300),
+        (1, ABS_X): (min: 10, center: 5, max: 250),
+        (1, ABS_Y): (min: 10, center: 120, max: 250),
+        (2, ABS_X): (min: 10, center: 120, max: 250),
+    },
+)"#;
+
+        let diagnostics = validate(source, Some(&capabilities()));
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                (Severity::Warning, 8),
+                (Severity::Error, 9),
+                (Severity::Error, 10),
+                (Severity::Error, 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn axis_filters() {
+        let source = r#"(
+    version: 2,
+    input_devices: ["Left", "Right"],
+    output_device: Combined("Combined"),
+    axis_filters: {
+        (0, ABS_X): [Threshold(2), OneEuro(min_cutoff: 1.0, beta: 0.01)],
+        (1, ABS_X): [MovingAverage(0)],
+        (1, ABS_Y): [Exponential(0.5)],
+        (2, ABS_X): [],
+    },
+)"#;
+
+        let diagnostics = validate(source, Some(&capabilities()));
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                (Severity::Error, 7),
+                (Severity::Error, 8),
+                (Severity::Error, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn debounce() {
+        let source = r#"(
+    version: 2,
+    input_devices: ["Left", (name: "Right", alias: "right")],
+    output_device: Combined("Combined"),
+    device_debounce: { 0: 10, "right": 10, "pedals": 10 },
+    button_debounce: {
+        (0, BTN_THUMB): 30,
+        ("right", BTN_THUMB): 30,
+        (2, BTN_TRIGGER): 30,
+    },
+)"#;
+
+        let diagnostics = validate(source, Some(&capabilities()));
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                (Severity::Error, 5),
+                (Severity::Error, 8),
+                (Severity::Error, 9),
+            ]
+        );
+        assert_eq!(diagnostics[0].message, "unknown device alias pedals");
+    }
+
+    #[test]
+    fn entry_locations() {
+        let source = r#"(
+    version: 2,
+    input_devices: ["Left", "Right"],
+    output_device: Combined("Combined"),
+    profiles: {
+        "landing": (key_mappings: { (1, BTN_THUMB): BTN_5 }),
+    },
+    key_mappings: {
+        (1, BTN_THUMB): BTN_1,
+        (0, Unknown(303)): BTN_2,
+    },
+    axis_mappings: {},
+)"#;
+
+        let diagnostics = validate(source, Some(&capabilities()));
+        let locations: Vec<_> = diagnostics.iter().map(|d| d.location).collect();
+
+        // the entry in the profile is reported at its own location, and the code as it is written
+        assert_eq!(locations, vec![Some((6, 37)), Some((9, 9)), Some((10, 9))]);
+        assert_eq!(
+            diagnostics[2].message,
+            "device 0 (Left) does not provide BTN_DEAD"
+        );
+    }
+
+    #[test]
+    fn profiles() {
+        let source = r#"(
+    version: 2,
+    input_devices: ["Left", "Right"],
+    output_device: Combined("Combined"),
+    key_mappings: {
+        (0, BTN_TRIGGER): BTN_0,
+        (1, BTN_TRIGGER): BTN_1,
+    },
+    profiles: {
+        "landing": (
+            key_mappings: {
+                (0, BTN_THUMB): BTN_1,
+                (1, BTN_TRIGGER): BTN_2,
+            },
+        ),
+        "combat": (
+            key_mappings: {
+                (0, BTN_THUMB): BTN_1,
+                (2, BTN_TRIGGER): BTN_3,
+            },
+        ),
+    },
+)"#;
+
+        // landing replaces the mapping to BTN_1, combat adds another one
+        let diagnostics = validate(source, Some(&capabilities()));
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                (Severity::Error, 19),
+                (Severity::Error, 7),
+                (Severity::Error, 18),
+            ]
+        );
+        assert_eq!(
+            diagnostics[1].message,
+            "in profile combat, destination BTN_1 is used by multiple sources: (1, BTN_TRIGGER), \
+             (0, BTN_THUMB)"
+        );
+    }
+
+    #[test]
+    fn examples() {
+        for example in std::fs::read_dir("example_descriptor").unwrap() {
+            let path = example.unwrap().path();
+            let source = std::fs::read_to_string(&path).unwrap();
+
+            assert_eq!(validate(&source, None), Vec::new(), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn passthrough_count() {
+        let source = r#"(
+    input_devices: ["Left", "Right"],
+    output_device: Passthrough(["One"]),
+    key_mappings: {},
+    axis_mappings: {},
+)"#;
+
+        let diagnostics = validate(source, None);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].location, Some((3, 5)));
+    }
+
+    #[test]
+    fn aliases() {
+        let source = r#"(
+    version: 2,
+    input_devices: [
+        (name: "Left", alias: "left"),
+        (name: "Right", alias: "left"),
+    ],
+    output_device: Combined("Combined"),
+    key_mappings: {
+        ("left", BTN_TRIGGER): BTN_0,
+        (0, BTN_TRIGGER): BTN_1,
+        ("right", BTN_TRIGGER): BTN_2,
+    },
+    axis_mappings: {},
+)"#;
+
+        let diagnostics = validate(source, Some(&capabilities()));
+        assert_eq!(
+            summary(&diagnostics),
+            vec![
+                (Severity::Error, 5),
+                (Severity::Error, 10),
+                (Severity::Error, 11),
+            ]
+        );
+        assert_eq!(diagnostics[2].message, "unknown device alias right");
+    }
+
+    #[test]
+    fn generated_with_comments() -> anyhow::Result<()> {
+        let mut devices = capabilities();
+        devices[0].name = "Left (rev \"2\"".to_string();
+        devices[1].name = "Right) /* (".to_string();
+
+        let backend = MockBackend::new();
+        backend.add_input(devices[0].clone());
+        backend.add_input(devices[1].clone());
+
+        let source = VJoyDescriptor::generate_file(
+            GenerationDescription {
+                input: devices.iter().map(|d| d.name.clone()).collect(),
+                output: OutputType::Combined("Combined".to_string()),
+            },
+            &backend,
+            Format::Ron,
+            true,
+        )?;
+        assert!(source.contains("// Right) /* (\n"));
+
+        // the stubs of the second device
+        let stubs = source
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.contains("(1, "))
+            .map(|(index, _)| (Severity::Warning, index + 1))
+            .collect::<Vec<_>>();
+        assert_eq!(stubs.len(), 2);
+        assert_eq!(summary(&validate(&source, Some(&devices))), stubs);
+
+        Ok(())
+    }
+}
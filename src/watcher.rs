@@ -0,0 +1,146 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+use mio::{unix::SourceFd, Interest, Registry, Token};
+
+/// Notices when the descriptor file or one of its includes is written or replaced
+pub struct DescriptorWatcher {
+    inotify: Inotify,
+    /// Watched directories with the names of the watched files in them
+    directories: HashMap<WatchDescriptor, HashSet<OsString>>,
+    buffer: [u8; 4096],
+}
+
+impl DescriptorWatcher {
+    pub fn new(paths: &[PathBuf]) -> Result<Self> {
+        let mut watcher = Self {
+            inotify: Inotify::init()?,
+            directories: HashMap::new(),
+            buffer: [0; 4096],
+        };
+        watcher.watch(paths)?;
+
+        Ok(watcher)
+    }
+
+    /// Replaces the watched files, e.g. after includes were added or removed
+    pub fn watch(&mut self, paths: &[PathBuf]) -> Result<()> {
+        let mut directories: HashMap<_, HashSet<_>> = HashMap::new();
+
+        for path in paths {
+            let (directory, file_name) = split(path)?;
+
+            // watch the directory, editors commonly replace the file instead of writing into it
+            let watch = self
+                .inotify
+                .watches()
+                .add(directory, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+
+            directories.entry(watch).or_default().insert(file_name);
+        }
+
+        for watch in self.directories.keys() {
+            if !directories.contains_key(watch) {
+                // the directory might be gone already, which removed the watch
+                let _ = self.inotify.watches().remove(watch.clone());
+            }
+        }
+
+        self.directories = directories;
+
+        Ok(())
+    }
+
+    pub fn register(&self, registry: &Registry, token: Token) -> io::Result<()> {
+        registry.register(
+            &mut SourceFd(&self.inotify.as_raw_fd()),
+            token,
+            Interest::READABLE,
+        )
+    }
+
+    /// Consumes all pending notifications, true if a watched file was among them
+    pub fn changed(&mut self) -> Result<bool> {
+        let mut changed = false;
+
+        loop {
+            match self.inotify.read_events(&mut self.buffer) {
+                Ok(events) => {
+                    for event in events {
+                        let watched = self.directories.get(&event.wd);
+
+                        changed |= event
+                            .name
+                            .is_some_and(|name| watched.is_some_and(|names| names.contains(name)));
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(changed),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Directory and file name of a path, the file itself doesn't have to exist
+fn split(path: &Path) -> Result<(PathBuf, OsString)> {
+    let file_name = path
+        .file_name()
+        .ok_or(anyhow!("{} has no file name", path.display()))?
+        .to_os_string();
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .canonicalize()?;
+
+    Ok((directory, file_name))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use anyhow::Result;
+
+    use super::DescriptorWatcher;
+
+    #[test]
+    fn included_files() -> Result<()> {
+        let dir = std::env::temp_dir().join("vjoy-linux-watcher");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("includes"))?;
+
+        let main = dir.join("main.ron");
+        let include = dir.join("includes").join("base.ron");
+        let other = dir.join("includes").join("other.ron");
+        fs::write(&main, "")?;
+        fs::write(&include, "")?;
+
+        let mut watcher = DescriptorWatcher::new(&[main.clone(), include.clone()])?;
+        assert!(!watcher.changed()?);
+
+        fs::write(&include, "changed")?;
+        assert!(watcher.changed()?);
+
+        // other files in the same directory are ignored
+        fs::write(&other, "")?;
+        assert!(!watcher.changed()?);
+
+        // the include was dropped, its directory isn't watched anymore
+        watcher.watch(std::slice::from_ref(&main))?;
+        fs::write(&include, "changed again")?;
+        assert!(!watcher.changed()?);
+
+        fs::write(&main, "changed")?;
+        assert!(watcher.changed()?);
+
+        Ok(())
+    }
+}